@@ -0,0 +1,174 @@
+// Order-preserving encodings of signed values into bit strings.
+//
+// The ibDCF comparison is over unsigned, MSB-first bit strings, so any
+// value that ends up as an interval endpoint (or as a heavy-hitter path
+// that we want to decode again) has to be mapped into a bit string whose
+// unsigned order matches the order of the original values. Plain two's
+// complement does not have this property: every negative value sorts
+// above every positive one.
+
+/// Maps values to fixed-length, MSB-first bit strings such that
+/// `a <= b` iff `encode(a) <= encode(b)` when the bit strings are
+/// compared as unsigned integers.
+pub trait Encoder {
+    type Value;
+
+    /// Number of bits produced by `encode`.
+    fn bit_len(&self) -> usize;
+
+    fn encode(&self, value: Self::Value) -> Vec<bool>;
+
+    fn decode(&self, bits: &[bool]) -> Self::Value;
+
+    /// The least and greatest values whose encodings start with `prefix`,
+    /// e.g., the extent of a node of the crawl on one dimension.
+    fn decode_prefix(&self, prefix: &[bool]) -> (Self::Value, Self::Value) {
+        assert!(prefix.len() <= self.bit_len());
+        let pad = |bit| {
+            let mut bits = prefix.to_vec();
+            bits.resize(self.bit_len(), bit);
+            self.decode(&bits)
+        };
+        (pad(false), pad(true))
+    }
+}
+
+/// Coordinates in degrees are encoded as whole centidegrees (about 1.1 km
+/// of latitude), which fit in an `i16`.
+pub const CENTIDEGREES_PER_DEGREE: f64 = 100.0;
+
+pub fn to_centidegrees(degrees: f64) -> i16 {
+    (degrees * CENTIDEGREES_PER_DEGREE).round() as i16
+}
+
+pub fn from_centidegrees(centidegrees: i16) -> f64 {
+    f64::from(centidegrees) / CENTIDEGREES_PER_DEGREE
+}
+
+/// Offset-binary encoding of an `i16`: the value is shifted by 2^15 so
+/// that `i16::MIN` maps to all zeros and `i16::MAX` to all ones. This is
+/// the same as flipping the sign bit of the two's-complement encoding.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OffsetBinary;
+
+/// Order-preserving encoding of an `f64`. Non-negative values have their
+/// sign bit set; negative values have all of their bits flipped, which
+/// reverses the (otherwise descending) order of their magnitudes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OrderedF64;
+
+const SIGN_16: u16 = 1 << 15;
+const SIGN_64: u64 = 1 << 63;
+
+fn u64_to_msb_bits(nbits: usize, value: u64) -> Vec<bool> {
+    (0..nbits).map(|i| (value >> (nbits - 1 - i)) & 1 == 1).collect()
+}
+
+fn msb_bits_to_u64(bits: &[bool]) -> u64 {
+    assert!(bits.len() <= 64);
+
+    let mut out = 0u64;
+    for &bit in bits {
+        out = (out << 1) | (bit as u64);
+    }
+    out
+}
+
+impl Encoder for OffsetBinary {
+    type Value = i16;
+
+    fn bit_len(&self) -> usize {
+        16
+    }
+
+    fn encode(&self, value: i16) -> Vec<bool> {
+        let biased = (value as u16) ^ SIGN_16;
+        u64_to_msb_bits(16, biased.into())
+    }
+
+    fn decode(&self, bits: &[bool]) -> i16 {
+        assert_eq!(bits.len(), 16);
+        let biased = msb_bits_to_u64(bits) as u16;
+        (biased ^ SIGN_16) as i16
+    }
+}
+
+impl Encoder for OrderedF64 {
+    type Value = f64;
+
+    fn bit_len(&self) -> usize {
+        64
+    }
+
+    fn encode(&self, value: f64) -> Vec<bool> {
+        let raw = value.to_bits();
+        let ordered = if raw & SIGN_64 == 0 { raw ^ SIGN_64 } else { !raw };
+        u64_to_msb_bits(64, ordered)
+    }
+
+    fn decode(&self, bits: &[bool]) -> f64 {
+        assert_eq!(bits.len(), 64);
+        let ordered = msb_bits_to_u64(bits);
+        let raw = if ordered & SIGN_64 != 0 { ordered ^ SIGN_64 } else { !ordered };
+        f64::from_bits(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_binary_round_trip() {
+        for v in i16::MIN..=i16::MAX {
+            let bits = OffsetBinary.encode(v);
+            assert_eq!(bits.len(), OffsetBinary.bit_len());
+            assert_eq!(OffsetBinary.decode(&bits), v);
+        }
+    }
+
+    #[test]
+    fn offset_binary_order() {
+        // Lexicographic order on MSB-first Vec<bool> is unsigned order.
+        let mut prev = OffsetBinary.encode(i16::MIN);
+        assert!(prev.iter().all(|&b| !b));
+        for v in (i16::MIN + 1)..=i16::MAX {
+            let cur = OffsetBinary.encode(v);
+            assert!(prev < cur, "order broken at {}", v);
+            prev = cur;
+        }
+        assert!(prev.iter().all(|&b| b));
+    }
+
+    #[test]
+    fn prefix_extent() {
+        assert_eq!(OffsetBinary.decode_prefix(&[]), (i16::MIN, i16::MAX));
+        assert_eq!(OffsetBinary.decode_prefix(&[true]), (0, i16::MAX));
+        assert_eq!(OffsetBinary.decode_prefix(&[false, true]), (-16384, -1));
+        let bits = OffsetBinary.encode(-9774);
+        assert_eq!(OffsetBinary.decode_prefix(&bits), (-9774, -9774));
+        assert_eq!(OffsetBinary.decode_prefix(&bits[..14]), (-9776, -9773));
+    }
+
+    #[test]
+    fn ordered_f64() {
+        let vals = [
+            f64::NEG_INFINITY,
+            -180.0,
+            -97.74,
+            -1.0,
+            -0.0001,
+            0.0,
+            0.0001,
+            30.26,
+            180.0,
+            f64::INFINITY,
+        ];
+        for w in vals.windows(2) {
+            assert!(OrderedF64.encode(w[0]) < OrderedF64.encode(w[1]));
+        }
+        for &v in &vals {
+            assert_eq!(OrderedF64.decode(&OrderedF64.encode(v)), v);
+        }
+    }
+}
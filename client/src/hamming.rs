@@ -0,0 +1,160 @@
+// Fuzzy matching of bit strings by Hamming distance.
+//
+// A client splits its point into XOR shares, one for each server. At a
+// node of the crawl, one server XORs the node's path into its share, which
+// leaves the two servers with XOR shares of where the path differs from the
+// client's point. A garbled circuit then compares the number of differing
+// symbols with the radius. The radius is the servers' choice, so unlike
+// interval keys, a client's Hamming key cannot be malformed.
+
+use rand::Rng;
+use crate::prg;
+use serde::{Deserialize, Serialize};
+
+/// One server's share of a client's point: an XOR share of the bits of
+/// each dimension.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HammingKey {
+    pub key_idx: bool,
+    pub shares: Vec<Vec<bool>>,
+}
+
+/// The ball around each client's point in Hamming mode.
+#[derive(Clone, Debug)]
+pub struct HammingBall {
+    /// The number of symbols that may differ on each dimension.
+    pub radius: Vec<usize>,
+    /// Bits per symbol, e.g., one for bit strings or eight for bytes.
+    pub symbol_bits: usize,
+}
+
+impl HammingKey {
+    /// Keys for the point `alpha`, with one bit string per dimension. The
+    /// client counts in every node within the servers' `HammingBall` of it.
+    pub fn gen_hamming_ball(alpha: Vec<Vec<bool>>) -> (HammingKey, HammingKey) {
+        let mut rng = prg::thread_rng();
+        let shares0 = alpha
+            .iter()
+            .map(|dim| dim.iter().map(|_| rng.gen::<bool>()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let shares1 = alpha
+            .iter()
+            .zip(&shares0)
+            .map(|(dim, share)| dim.iter().zip(share).map(|(a, s)| a ^ s).collect())
+            .collect();
+
+        (
+            HammingKey {
+                key_idx: false,
+                shares: shares0,
+            },
+            HammingKey {
+                key_idx: true,
+                shares: shares1,
+            },
+        )
+    }
+
+    /// Bit length of each dimension.
+    pub fn dim_lens(&self) -> Vec<usize> {
+        self.shares.iter().map(|s| s.len()).collect()
+    }
+
+    /// This server's XOR share of the bits where `path`, a prefix of each
+    /// dimension, differs from the client's point, concatenated over the
+    /// dimensions.
+    pub fn mismatch_share(&self, path: &[Vec<bool>]) -> Vec<bool> {
+        assert_eq!(path.len(), self.shares.len());
+
+        let mut out = vec![];
+        for (prefix, share) in path.iter().zip(&self.shares) {
+            assert!(prefix.len() <= share.len());
+            for (&p, &s) in prefix.iter().zip(share) {
+                out.push(s ^ (p & !self.key_idx));
+            }
+        }
+        out
+    }
+}
+
+impl HammingBall {
+    /// The number of symbols in which `a` and `b` differ, where a last,
+    /// partial symbol counts as a symbol.
+    pub fn distance(&self, a: &[bool], b: &[bool]) -> usize {
+        assert_eq!(a.len(), b.len());
+        a.chunks(self.symbol_bits)
+            .zip(b.chunks(self.symbol_bits))
+            .filter(|(x, y)| x != y)
+            .count()
+    }
+
+    /// Whether some point that starts with `path` on every dimension lies in
+    /// the ball around `center`. This is what the crawl computes at each
+    /// node, in the clear.
+    pub fn contains(&self, center: &[Vec<bool>], path: &[Vec<bool>]) -> bool {
+        assert_eq!(center.len(), self.radius.len());
+        assert_eq!(path.len(), self.radius.len());
+        center
+            .iter()
+            .zip(path)
+            .zip(&self.radius)
+            .all(|((c, p), &r)| self.distance(&c[..p.len()], p) <= r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MSB_u32_to_bits;
+
+    #[test]
+    fn symbol_distance() {
+        let ball = HammingBall {
+            radius: vec![1],
+            symbol_bits: 2,
+        };
+        let a = MSB_u32_to_bits(6, 0b00_11_01);
+        assert_eq!(ball.distance(&a, &MSB_u32_to_bits(6, 0b00_11_01)), 0);
+        assert_eq!(ball.distance(&a, &MSB_u32_to_bits(6, 0b00_10_01)), 1);
+        assert_eq!(ball.distance(&a, &MSB_u32_to_bits(6, 0b00_00_01)), 1);
+        assert_eq!(ball.distance(&a, &MSB_u32_to_bits(6, 0b11_00_01)), 2);
+        // A partial last symbol.
+        assert_eq!(ball.distance(&a[..3], &MSB_u32_to_bits(3, 0b000)), 1);
+    }
+
+    #[test]
+    fn mismatch_shares() {
+        // Two dimensions over a four-letter alphabet.
+        let ball = HammingBall {
+            radius: vec![1, 0],
+            symbol_bits: 2,
+        };
+        let center = vec![MSB_u32_to_bits(6, 0b01_10_11), MSB_u32_to_bits(4, 0b10_00)];
+        let (k0, k1) = HammingKey::gen_hamming_ball(center.clone());
+        assert_eq!(k0.dim_lens(), vec![6, 4]);
+
+        for x in 0..(1u32 << 6) {
+            for y in 0..(1u32 << 4) {
+                for (len0, len1) in &[(6, 4), (3, 2), (5, 1)] {
+                    let path = vec![
+                        MSB_u32_to_bits(6, x)[..*len0].to_vec(),
+                        MSB_u32_to_bits(4, y)[..*len1].to_vec(),
+                    ];
+                    let mismatch = k0
+                        .mismatch_share(&path)
+                        .iter()
+                        .zip(k1.mismatch_share(&path))
+                        .map(|(a, b)| a ^ b)
+                        .collect::<Vec<_>>();
+
+                    // Where the path differs from the center, and so how
+                    // many symbols differ.
+                    let (m0, m1) = mismatch.split_at(*len0);
+                    let zeros = vec![false; mismatch.len()];
+                    let within = ball.distance(m0, &zeros[..*len0]) <= 1 && ball.distance(m1, &zeros[..*len1]) == 0;
+                    assert_eq!(within, ball.contains(&center, &path));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1275 @@
+use std::cmp::{max, min};
+use crate::{add_bitstrings, bits_to_u32, prg, subtract_bitstrings, u32_to_bits, MSB_u32_to_bits};
+use crate::Group;
+
+use rand::Rng;
+use serde::Deserialize;
+use serde::Serialize;
+use crate::encoding::{Encoder, OffsetBinary};
+
+#[derive(Clone, Debug)]
+pub struct CorWord {
+    pub seed: prg::PrgSeed,
+    // The four control bits, packed as in the wire format: bits.0, bits.1,
+    // y_bits.0 and y_bits.1, from least to most significant.
+    ctrl: u8,
+}
+
+// Keys are serialized with `to_bytes`, which is much smaller than the
+// derived encoding of the struct (see `from_bytes` for the format).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "PackedKey", try_from = "PackedKey")]
+pub struct ibDCFKey {
+    pub key_idx: bool,
+    pub root_seed: prg::PrgSeed,
+    pub cor_words: Vec<CorWord>,
+    // This server's XOR share of the bits of `alpha`, MSB first. The
+    // servers check the control bits against it as they crawl (see
+    // `check_step`) and compare interval widths with it.
+    pub alpha_share: Vec<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PackedKey(Vec<u8>);
+
+impl From<ibDCFKey> for PackedKey {
+    fn from(key: ibDCFKey) -> PackedKey {
+        PackedKey(key.to_bytes())
+    }
+}
+
+impl std::convert::TryFrom<PackedKey> for ibDCFKey {
+    type Error = &'static str;
+
+    fn try_from(packed: PackedKey) -> Result<Self, Self::Error> {
+        ibDCFKey::from_bytes(&packed.0)
+    }
+}
+
+/// Version of the wire format written by `ibDCFKey::to_bytes`. Version 3
+/// changed how seeds expand (see `prg::PrgSeed::expand_dir`) without
+/// changing the layout, and version 4 replaced the check word with shares
+/// of `alpha`, so keys of earlier versions are rejected rather than
+/// evaluated to garbage.
+pub const KEY_FORMAT_VERSION: u8 = 4;
+
+const SEED_SIZE: usize = 16;
+const HEADER_SIZE: usize = 2 + 4 + SEED_SIZE;
+
+/// One server's share of an interval: the (left, right) boundary DCFs.
+pub type IntervalKey = (ibDCFKey, ibDCFKey);
+
+
+#[derive(Clone)]
+pub struct EvalState {
+    level: usize,
+    seed: prg::PrgSeed,
+    pub bit: bool,
+    pub y_bit: bool
+}
+
+trait TupleMapToExt<T, U> {
+    type Output;
+    fn map<F: FnMut(&T) -> U>(&self, f: F) -> Self::Output;
+}
+
+type TupleMutIter<'a, T> =
+std::iter::Chain<std::iter::Once<(bool, &'a mut T)>, std::iter::Once<(bool, &'a mut T)>>;
+
+trait TupleExt<T> {
+    fn map_mut<F: Fn(&mut T)>(&mut self, f: F);
+    fn get(&self, val: bool) -> &T;
+    fn get_mut(&mut self, val: bool) -> &mut T;
+    fn iter_mut(&mut self) -> TupleMutIter<T>;
+}
+
+impl<T, U> TupleMapToExt<T, U> for (T, T) {
+    type Output = (U, U);
+
+    #[inline(always)]
+    fn map<F: FnMut(&T) -> U>(&self, mut f: F) -> Self::Output {
+        (f(&self.0), f(&self.1))
+    }
+}
+
+impl<T> TupleExt<T> for (T, T) {
+    #[inline(always)]
+    fn map_mut<F: Fn(&mut T)>(&mut self, f: F) {
+        f(&mut self.0);
+        f(&mut self.1);
+    }
+
+    #[inline(always)]
+    fn get(&self, val: bool) -> &T {
+        match val {
+            false => &self.0,
+            true => &self.1,
+        }
+    }
+
+    #[inline(always)]
+    fn get_mut(&mut self, val: bool) -> &mut T {
+        match val {
+            false => &mut self.0,
+            true => &mut self.1,
+        }
+    }
+
+    fn iter_mut(&mut self) -> TupleMutIter<T> {
+        std::iter::once((false, &mut self.0)).chain(std::iter::once((true, &mut self.1)))
+    }
+}
+
+impl CorWord {
+    pub fn new(seed: prg::PrgSeed, bits: (bool, bool), y_bits: (bool, bool)) -> CorWord {
+        let ctrl = (bits.0 as u8) | (bits.1 as u8) << 1 | (y_bits.0 as u8) << 2 | (y_bits.1 as u8) << 3;
+        CorWord { seed, ctrl }
+    }
+
+    pub fn bits(&self) -> (bool, bool) {
+        (self.ctrl & 1 != 0, self.ctrl & 2 != 0)
+    }
+
+    pub fn y_bits(&self) -> (bool, bool) {
+        (self.ctrl & 4 != 0, self.ctrl & 8 != 0)
+    }
+}
+
+fn gen_cor_word(bit: bool, side : bool, bits: &mut (bool, bool), seeds: &mut (prg::PrgSeed, prg::PrgSeed)) -> CorWord
+{
+    let data = seeds.map(|s| s.expand());
+    let keep = bit;
+    let lose = !keep;
+
+    let cw = CorWord::new(
+        data.0.seeds.get(lose) ^ data.1.seeds.get(lose),
+        (
+            data.0.bits.0 ^ data.1.bits.0 ^ bit ^ true,
+            data.0.bits.1 ^ data.1.bits.1 ^ bit,
+        ),
+        (
+            data.0.y_bits.0 ^ data.1.y_bits.0 ^ (bit & !side),
+            data.0.y_bits.1 ^ data.1.y_bits.1 ^ (!bit & side)
+        ),
+    );
+
+    for (b, seed) in seeds.iter_mut() {
+        *seed = data.get(b).seeds.get(keep).clone();
+
+        if *bits.get(b) {
+            *seed = &*seed ^ &cw.seed;
+        }
+
+        let mut newbit = *data.get(b).bits.get(keep);
+        if *bits.get(b) {
+            newbit ^= cw.bits().get(keep);
+        }
+
+        *bits.get_mut(b) = newbit;
+    }
+
+    cw
+}
+// The bit that each dimension advances by, or None for dimensions whose
+// keys are exhausted. `eval_string` holds one bit for each active dimension.
+fn dim_bits(keys: &[Vec<IntervalKey>], states: &[Vec<(EvalState, EvalState)>], eval_string: &[bool]) -> Vec<Option<bool>> {
+    let mut bits = eval_string.iter();
+    let out = states
+        .iter()
+        .zip(keys)
+        .map(|(dim_states, dim_keys)| {
+            if dim_states[0].0.level == dim_keys[0].0.domain_size() {
+                None
+            } else {
+                Some(*bits.next().expect("eval_string is shorter than the number of active dimensions"))
+            }
+        })
+        .collect();
+    debug_assert!(bits.next().is_none());
+    out
+}
+
+/// Advance every dimension that still has bits left by one level.
+/// `eval_string` holds one bit for each such dimension, in order; the
+/// states of dimensions whose keys are exhausted are carried over as is,
+/// so dimensions of different bit lengths can be crawled together.
+pub fn eval_str(keys : &Vec<Vec<IntervalKey>>, states: &Vec<Vec<(EvalState,EvalState)>>, eval_string: &Vec<bool>) -> Vec<Vec<(EvalState,EvalState)>> {
+    let dim = keys.len();
+    let mut new_states = Vec::with_capacity(dim);
+
+    for (i, (dim_states, bit)) in states.iter().zip(dim_bits(keys, states, eval_string)).enumerate() {
+        let bit = match bit {
+            Some(bit) => bit,
+            None => {
+                new_states.push(dim_states.clone());
+                continue;
+            }
+        };
+
+        let mut new_dim_states = Vec::with_capacity(dim_states.len());
+        for (j, &(ref state_left, ref state_right)) in dim_states.iter().enumerate() {
+            let (left_key, right_key) = &keys[i][j];
+            let new_state_left = left_key.eval_bit(state_left, bit);
+            let new_state_right = right_key.eval_bit(state_right, bit);
+            new_dim_states.push((new_state_left, new_state_right));
+        }
+        new_states.push(new_dim_states);
+    }
+    new_states
+}
+
+/// One dimension's states after a step with bit 0 and with bit 1, or None
+/// if the dimension's keys are exhausted: boundary-pair states for interval
+/// keys, or, for arithmetic keys, each key's state and output.
+pub type DimStep<S = (EvalState, EvalState)> = Option<(Vec<S>, Vec<S>)>;
+
+/// Advances every client's states on every active dimension in both
+/// directions at once. In a crawl, the 2^d children of a node then pick
+/// their states out of these steps with `child_states`, instead of each
+/// child expanding every seed again. Each seed is expanded once for both
+/// children, through the pipelined AES (see `prg::PrgSeed::expand_all`).
+pub fn eval_both_batch(keys: &[&Vec<Vec<IntervalKey>>], states: &[&Vec<Vec<(EvalState,EvalState)>>]) -> Vec<Vec<DimStep>> {
+    assert_eq!(keys.len(), states.len());
+
+    let active = |dim_keys: &Vec<IntervalKey>, dim_states: &Vec<(EvalState, EvalState)>| {
+        dim_states[0].0.level < dim_keys[0].0.domain_size()
+    };
+
+    // Every seed of an active dimension.
+    let mut seeds = vec![];
+    for (client_keys, client_states) in keys.iter().zip(states) {
+        for (dim_keys, dim_states) in client_keys.iter().zip(client_states.iter()) {
+            if active(dim_keys, dim_states) {
+                for (left, right) in dim_states {
+                    seeds.push(&left.seed);
+                    seeds.push(&right.seed);
+                }
+            }
+        }
+    }
+    let mut taus = prg::PrgSeed::expand_all(&seeds).into_iter();
+
+    let mut steps = Vec::with_capacity(keys.len());
+    for (client_keys, client_states) in keys.iter().zip(states) {
+        let mut client_steps = Vec::with_capacity(client_keys.len());
+        for (dim_keys, dim_states) in client_keys.iter().zip(client_states.iter()) {
+            if !active(dim_keys, dim_states) {
+                client_steps.push(None);
+                continue;
+            }
+
+            let mut zero = Vec::with_capacity(dim_states.len());
+            let mut one = Vec::with_capacity(dim_states.len());
+            for ((left_key, right_key), (state_left, state_right)) in dim_keys.iter().zip(dim_states) {
+                let tau_left = taus.next().unwrap();
+                let tau_right = taus.next().unwrap();
+                for (dir, out) in [(false, &mut zero), (true, &mut one)] {
+                    out.push((
+                        left_key.correct(state_left, &tau_left, dir),
+                        right_key.correct(state_right, &tau_right, dir),
+                    ));
+                }
+            }
+            client_steps.push(Some((zero, one)));
+        }
+        steps.push(client_steps);
+    }
+    steps
+}
+
+/// Like `eval_both_batch`, for arithmetic keys: `keys` holds each client's
+/// keys on each dimension (see `ArithDCFKey`), and `states` their states
+/// and outputs.
+pub fn arith_both_batch<T>(keys: &[Vec<Vec<&ArithDCFKey<T>>>], states: &[&Vec<Vec<(EvalState, T)>>]) -> Vec<Vec<DimStep<(EvalState, T)>>>
+where
+    T: Group + prg::FromRng + Clone,
+{
+    assert_eq!(keys.len(), states.len());
+
+    let active = |dim_keys: &Vec<&ArithDCFKey<T>>, dim_states: &Vec<(EvalState, T)>| {
+        dim_states[0].0.level < dim_keys[0].domain_size()
+    };
+
+    let mut seeds = vec![];
+    for (client_keys, client_states) in keys.iter().zip(states) {
+        for (dim_keys, dim_states) in client_keys.iter().zip(client_states.iter()) {
+            if active(dim_keys, dim_states) {
+                seeds.extend(dim_states.iter().map(|(state, _)| &state.seed));
+            }
+        }
+    }
+    let mut taus = prg::PrgSeed::expand_all(&seeds).into_iter();
+
+    let mut steps = Vec::with_capacity(keys.len());
+    for (client_keys, client_states) in keys.iter().zip(states) {
+        let mut client_steps = Vec::with_capacity(client_keys.len());
+        for (dim_keys, dim_states) in client_keys.iter().zip(client_states.iter()) {
+            if !active(dim_keys, dim_states) {
+                client_steps.push(None);
+                continue;
+            }
+
+            let mut zero = Vec::with_capacity(dim_states.len());
+            let mut one = Vec::with_capacity(dim_states.len());
+            for (key, state) in dim_keys.iter().zip(dim_states) {
+                let tau = taus.next().unwrap();
+                zero.push(key.correct(state, &tau, false));
+                one.push(key.correct(state, &tau, true));
+            }
+            client_steps.push(Some((zero, one)));
+        }
+        steps.push(client_steps);
+    }
+    steps
+}
+
+/// The states of the child along `eval_string`, which holds one bit for
+/// each active dimension, given the parent's `states` and the `steps` from
+/// `eval_both_batch` or `arith_both_batch`. Same result as `eval_str`.
+pub fn child_states<S: Clone>(states: &Vec<Vec<S>>, steps: &[DimStep<S>], eval_string: &[bool]) -> Vec<Vec<S>> {
+    let mut bits = eval_string.iter();
+    let out = states
+        .iter()
+        .zip(steps)
+        .map(|(dim_states, step)| match step {
+            Some((zero, one)) => {
+                if *bits.next().expect("eval_string is shorter than the number of active dimensions") {
+                    one.clone()
+                } else {
+                    zero.clone()
+                }
+            }
+            None => dim_states.clone(),
+        })
+        .collect();
+    debug_assert!(bits.next().is_none());
+    out
+}
+
+/// One server's share of the check that a key steps correctly from the
+/// nodes of one level to their children, built up by
+/// `ibDCFKey::check_step`. Writing `alpha`, `linear` and `path` for the
+/// XOR of both servers' fields, the check passes iff `linear` is zero when
+/// `alpha` is zero and equal to `path` when it is one. A key that does not
+/// encode a comparison on the nodes checked fails except with probability
+/// 2^-128.
+#[derive(Clone, Debug)]
+pub struct LevelCheck {
+    /// This server's share of the level's bit of `alpha`.
+    pub alpha: bool,
+    /// The part of the check that is linear in the shares.
+    pub linear: u128,
+    /// The part that the bit of `alpha` multiplies.
+    pub path: u128,
+}
+
+impl LevelCheck {
+    /// An empty check of `key` on the step from level `level`.
+    pub fn new(key: &ibDCFKey, level: usize) -> LevelCheck {
+        LevelCheck {
+            alpha: key.alpha_share[level],
+            linear: 0,
+            path: 0,
+        }
+    }
+
+    /// Whether the check passes, given both servers' shares. The servers
+    /// never put their shares together; they multiply in the bit of
+    /// `alpha` by OT instead.
+    pub fn passes(&self, other: &LevelCheck) -> bool {
+        let path = if self.alpha ^ other.alpha { self.path ^ other.path } else { 0 };
+        self.linear ^ other.linear ^ path == 0
+    }
+}
+
+/// One server's share of the check that an arithmetic key's outputs are
+/// those of its comparison, given the nodes on the path to `alpha`; see
+/// `ArithDCFKey::check_values`. With `linear` the first server's share
+/// minus the second's, and each bit the XOR of both servers' shares, the
+/// check passes iff `linear` is the sum of the coefficients of the bits
+/// that are set. A key whose outputs are wrong at a node checked fails
+/// except with probability about one over the size of the group.
+#[derive(Clone, Debug)]
+pub struct ValueCheck<T> {
+    /// The part of the check that is linear in the output shares.
+    pub linear: T,
+    /// This server's shares of control bits, each with its coefficient.
+    pub bits: Vec<(bool, T)>,
+}
+
+impl<T: Group> Default for ValueCheck<T> {
+    fn default() -> ValueCheck<T> {
+        ValueCheck {
+            linear: T::zero(),
+            bits: vec![],
+        }
+    }
+}
+
+// `r` if `bit` is set, and zero otherwise.
+fn times(r: u128, bit: bool) -> u128 {
+    if bit { r } else { 0 }
+}
+
+/// Treat a key with one interval per dimension as a union key with a single
+/// boundary pair on every dimension.
+pub fn to_union(keys: Vec<IntervalKey>) -> Vec<Vec<IntervalKey>> {
+    keys.into_iter().map(|k| vec![k]).collect()
+}
+
+/// The inclusive (left, right) endpoints, on latitude and then longitude,
+/// of the box that `gen_l_inf_ball_from_coords` makes keys for: `sizes`
+/// centidegrees on either side of `(lat, long)`, clamped to the globe.
+pub fn coords_box((lat, long): (i16, i16), (lat_size, long_size): (i16, i16)) -> Vec<(Vec<bool>, Vec<bool>)> {
+    let left_lat = lat.saturating_sub(lat_size).clamp(-9000, 9000);
+    let right_lat = lat.saturating_add(lat_size).clamp(-9000, 9000);
+    let left_long = long.saturating_sub(long_size).clamp(-18000, 18000);
+    let right_long = long.saturating_add(long_size).clamp(-18000, 18000);
+    vec![
+        (OffsetBinary.encode(left_lat), OffsetBinary.encode(right_lat)),
+        (OffsetBinary.encode(left_long), OffsetBinary.encode(right_long)),
+    ]
+}
+
+
+// `size` as an `nbits`-long MSB-first bit string.
+fn ball_delta(nbits: usize, size: u32) -> Vec<bool> {
+    let delta = MSB_u32_to_bits(32, size);
+    if nbits >= 32 {
+        let mut out = vec![false; nbits - 32];
+        out.extend(delta);
+        out
+    } else {
+        assert!(delta[..32 - nbits].iter().all(|&b| !b), "ball size does not fit in the domain");
+        delta[32 - nbits..].to_vec()
+    }
+}
+
+// Endpoints of [center - size, center + size] over `center.len()`-bit
+// strings. On a bounded dimension the endpoints are clamped to the edges of
+// the domain. On a toroidal dimension they are reduced mod 2^n, so the
+// returned `left` is greater than `right` when the ball wraps around.
+fn ball_endpoints(center: &[bool], size: u32, toroidal: bool) -> (Vec<bool>, Vec<bool>) {
+    let nbits = center.len();
+    if toroidal && nbits < 64 && 2 * size as u64 + 1 >= 1u64 << nbits {
+        // The ball goes all the way around.
+        return (vec![false; nbits], vec![true; nbits]);
+    }
+    let delta = ball_delta(nbits, size);
+
+    let underflow = center < delta.as_slice();
+    let mut left = subtract_bitstrings(center, &delta);
+    let mut right = add_bitstrings(center, &delta);
+    let overflow = right.len() > nbits;
+    if overflow {
+        right.remove(0);
+    }
+
+    if !toroidal {
+        if underflow {
+            left = vec![false; nbits];
+        }
+        if overflow {
+            right = vec![true; nbits];
+        }
+    }
+    (left, right)
+}
+
+// Split a (possibly wrapping) range into at most two ranges that each have
+// left <= right.
+fn split_range(left: Vec<bool>, right: Vec<bool>) -> Vec<(Vec<bool>, Vec<bool>)> {
+    if left <= right {
+        vec![(left, right)]
+    } else {
+        let nbits = left.len();
+        vec![(left, vec![true; nbits]), (vec![false; nbits], right)]
+    }
+}
+
+// Pad the ranges of one dimension with empty ranges up to `n_pairs`, so that
+// the servers learn only the public number of boundary pairs and not how many
+// of them the client actually uses.
+fn pad_ranges(mut ranges: Vec<(Vec<bool>, Vec<bool>)>, n_pairs: usize) -> Vec<(Vec<bool>, Vec<bool>)> {
+    assert!(!ranges.is_empty());
+    assert!(ranges.len() <= n_pairs, "more ranges than boundary pairs");
+    let nbits = ranges[0].0.len();
+    while ranges.len() < n_pairs {
+        ranges.push(empty_range(nbits));
+    }
+    ranges
+}
+
+// The range [1...1, 0...0]. Its left DCF is one everywhere except on the
+// all-ones prefix and its right DCF is one everywhere except on the
+// all-zeros prefix, so it contains no prefix of any length >= 1.
+fn empty_range(nbits: usize) -> (Vec<bool>, Vec<bool>) {
+    (vec![true; nbits], vec![false; nbits])
+}
+
+fn gen_box(ranges: &[(Vec<bool>, Vec<bool>)]) -> (Vec<IntervalKey>, Vec<IntervalKey>) {
+    let mut s0_keys = vec![];
+    let mut s1_keys = vec![];
+    for (left, right) in ranges {
+        let (k0, k1) = ibDCFKey::gen_interval(left, right);
+        s0_keys.push(k0);
+        s1_keys.push(k1);
+    }
+    (s0_keys, s1_keys)
+}
+
+// Half-extents (x, y) of `n_boxes` nested, centered boxes whose union
+// approximates the disc of radius `radius`. The boxes form a staircase: box
+// `j` reaches up to the top of the `j`-th of `n_boxes` equal slices of
+// [0, radius], and its half-width is the disc's at the middle of that slice,
+// so the corners it cuts off about balance what it adds.
+pub fn l2_boxes(radius: u32, n_boxes: usize) -> Vec<(u32, u32)> {
+    assert!(n_boxes > 0);
+    let r = radius as f64;
+    (0..n_boxes)
+        .map(|j| {
+            let top = r * (j + 1) as f64 / n_boxes as f64;
+            let mid = r * (j as f64 + 0.5) / n_boxes as f64;
+            ((r * r - mid * mid).sqrt().round() as u32, top.round() as u32)
+        })
+        .collect()
+}
+
+// Keys for a union of boxes, where `boxes[j][i]` is the range of box `j` on
+// dimension `i`. Box `j` becomes boundary pair `j` of every dimension.
+fn gen_box_union(boxes: &[Vec<(Vec<bool>, Vec<bool>)>]) -> (Vec<Vec<IntervalKey>>, Vec<Vec<IntervalKey>>) {
+    let n_dims = boxes[0].len();
+    let mut s0_keys = vec![];
+    let mut s1_keys = vec![];
+    for i in 0..n_dims {
+        let ranges = boxes.iter().map(|b| b[i].clone()).collect::<Vec<_>>();
+        let (k0, k1) = gen_box(&ranges);
+        s0_keys.push(k0);
+        s1_keys.push(k1);
+    }
+    (s0_keys, s1_keys)
+}
+
+/// All-prefix DPF implementation.
+impl ibDCFKey
+{
+
+    pub fn gen_ibDCF(alpha_bits: &[bool], side : bool) -> (ibDCFKey, ibDCFKey) {
+        Self::gen_with(alpha_bits, side, |_, _, _| ())
+    }
+
+    // Like `gen_ibDCF`, but calls `at_level` with the bit of `alpha`, the
+    // two shares' control bits and their seeds on the path to `alpha`
+    // before each level.
+    fn gen_with<F>(alpha_bits: &[bool], side: bool, mut at_level: F) -> (ibDCFKey, ibDCFKey)
+    where
+        F: FnMut(bool, &(bool, bool), &(prg::PrgSeed, prg::PrgSeed)),
+    {
+        let root_seeds = (prg::PrgSeed::random(), prg::PrgSeed::random());
+        let root_bits = (false, true);
+
+        let mut seeds = root_seeds.clone();
+        let mut bits = root_bits;
+
+        let mut cor_words: Vec<CorWord> = Vec::new();
+
+        for (_, &bit) in alpha_bits.iter().enumerate() {
+            at_level(bit, &bits, &seeds);
+            let cw = gen_cor_word(bit, side, &mut bits, &mut seeds);
+            cor_words.push(cw);
+        }
+
+        let mut rng = prg::thread_rng();
+        let alpha_share0 = alpha_bits.iter().map(|_| rng.gen::<bool>()).collect::<Vec<_>>();
+        let alpha_share1 = alpha_bits.iter().zip(&alpha_share0).map(|(a, r)| a ^ r).collect();
+
+        (
+            ibDCFKey {
+                key_idx: false,
+                root_seed: root_seeds.0,
+                cor_words: cor_words.clone(),
+                alpha_share: alpha_share0,
+            },
+            ibDCFKey {
+                key_idx: true,
+                root_seed: root_seeds.1,
+                cor_words,
+                alpha_share: alpha_share1,
+            },
+        )
+    }
+
+    pub fn gen_interval(left_bits: &[bool], right_bits: &[bool]) -> ((ibDCFKey, ibDCFKey), (ibDCFKey, ibDCFKey)){
+        // let r = &[false; 512];
+        let l_minus_one = left_bits.to_vec();
+        let r_plus_one = right_bits.to_vec();
+        let left_key = Self::gen_ibDCF(l_minus_one.as_slice(), true);
+        let right_key = Self::gen_ibDCF(r_plus_one.as_slice(), false);
+        ((left_key.0, right_key.0), (left_key.1, right_key.1))
+    }
+
+    pub fn gen_l_inf_ball(alpha : Vec<Vec<bool>>, size: u32) -> (Vec<(ibDCFKey, ibDCFKey)>, Vec<(ibDCFKey, ibDCFKey)>){
+        let sizes = vec![size; alpha.len()];
+        Self::gen_l_inf_box(alpha, &sizes)
+    }
+
+    /// Like `gen_l_inf_ball`, but with radius `sizes[i]` on dimension `i`.
+    /// Each dimension's bit length is that of its center `alpha[i]`.
+    pub fn gen_l_inf_box(alpha: Vec<Vec<bool>>, sizes: &[u32]) -> (Vec<(ibDCFKey, ibDCFKey)>, Vec<(ibDCFKey, ibDCFKey)>) {
+        assert_eq!(alpha.len(), sizes.len());
+
+        let ranges = alpha
+            .iter()
+            .zip(sizes)
+            .map(|(a, &size)| ball_endpoints(a, size, false))
+            .collect::<Vec<_>>();
+        gen_box(&ranges)
+    }
+
+    /// Keys for a box whose side on dimension `i` is the union of the
+    /// disjoint ranges `ranges[i]`, each given by its inclusive (left, right)
+    /// endpoints. Dimension `i` is padded with empty ranges up to
+    /// `pairs_per_dim[i]` boundary pairs, so that every client in a
+    /// collection has the same key shape.
+    pub fn gen_union(ranges: &[Vec<(Vec<bool>, Vec<bool>)>], pairs_per_dim: &[usize]) -> (Vec<Vec<IntervalKey>>, Vec<Vec<IntervalKey>>) {
+        assert_eq!(ranges.len(), pairs_per_dim.len());
+
+        let mut s0_keys = vec![];
+        let mut s1_keys = vec![];
+        for (dim_ranges, &n_pairs) in ranges.iter().zip(pairs_per_dim) {
+            let mut sorted = dim_ranges.clone();
+            sorted.sort();
+            for (left, right) in &sorted {
+                assert_eq!(left.len(), sorted[0].0.len());
+                assert_eq!(right.len(), left.len());
+                assert!(left <= right, "range endpoints out of order");
+            }
+            for w in sorted.windows(2) {
+                assert!(w[0].1 < w[1].0, "ranges on a dimension must be disjoint");
+            }
+
+            let (k0, k1) = gen_box(&pad_ranges(sorted, n_pairs));
+            s0_keys.push(k0);
+            s1_keys.push(k1);
+        }
+        (s0_keys, s1_keys)
+    }
+
+    /// Like `gen_l_inf_box`, but dimensions marked in `toroidal` wrap around
+    /// at the edges of their domain instead of being clamped. A ball that
+    /// crosses the seam of a toroidal dimension is split into one range per
+    /// side. Toroidal dimensions always get two boundary pairs (the second
+    /// one empty if the ball does not wrap), so the key shape does not
+    /// reveal which clients are near the seam.
+    pub fn gen_l_inf_ball_toroidal(alpha: Vec<Vec<bool>>, sizes: &[u32], toroidal: &[bool]) -> (Vec<Vec<IntervalKey>>, Vec<Vec<IntervalKey>>) {
+        assert_eq!(alpha.len(), sizes.len());
+        assert_eq!(alpha.len(), toroidal.len());
+
+        let ranges = alpha
+            .iter()
+            .zip(sizes)
+            .zip(toroidal)
+            .map(|((a, &size), &t)| {
+                let (left, right) = ball_endpoints(a, size, t);
+                split_range(left, right)
+            })
+            .collect::<Vec<_>>();
+        let pairs_per_dim = toroidal.iter().map(|&t| if t { 2 } else { 1 }).collect::<Vec<_>>();
+
+        Self::gen_union(&ranges, &pairs_per_dim)
+    }
+
+    /// Keys for the union of the `n_boxes` boxes of `l2_boxes` around the
+    /// two-dimensional point `alpha`, which approximates the disc of radius
+    /// `radius` around it. Box `j` is boundary pair `j` on both dimensions:
+    /// unlike the keys of `gen_union`, a point is inside if one box holds
+    /// it on every dimension, not if every dimension has a pair that holds
+    /// it. Boxes are clamped to the edges of the domain.
+    pub fn gen_l2_ball(alpha: Vec<Vec<bool>>, radius: u32, n_boxes: usize) -> (Vec<Vec<IntervalKey>>, Vec<Vec<IntervalKey>>) {
+        assert_eq!(alpha.len(), 2, "an L2 ball needs two dimensions");
+
+        let boxes = l2_boxes(radius, n_boxes)
+            .into_iter()
+            .map(|(dx, dy)| vec![ball_endpoints(&alpha[0], dx, false), ball_endpoints(&alpha[1], dy, false)])
+            .collect::<Vec<_>>();
+        gen_box_union(&boxes)
+    }
+
+    /// Like `gen_l2_ball`, but around a (latitude, longitude) pair, with the
+    /// boxes clamped as in `gen_l_inf_ball_from_coords`.
+    pub fn gen_l2_ball_from_coords((lat, long): (i16, i16), radius: i16, n_boxes: usize) -> (Vec<Vec<IntervalKey>>, Vec<Vec<IntervalKey>>) {
+        assert!(radius >= 0);
+        let range = |center: i16, size: u32, limit: i16| {
+            let size = size as i16;
+            (
+                OffsetBinary.encode(center.saturating_sub(size).clamp(-limit, limit)),
+                OffsetBinary.encode(center.saturating_add(size).clamp(-limit, limit)),
+            )
+        };
+
+        let boxes = l2_boxes(radius as u32, n_boxes)
+            .into_iter()
+            .map(|(dx, dy)| vec![range(lat, dx, 9000), range(long, dy, 18000)])
+            .collect::<Vec<_>>();
+        gen_box_union(&boxes)
+    }
+
+    pub fn gen_l_inf_ball_from_coords(coords: (i16, i16), sizes: (i16, i16)) -> (Vec<(ibDCFKey, ibDCFKey)>, Vec<(ibDCFKey, ibDCFKey)>) {
+        let ranges = coords_box(coords, sizes);
+        let (k0_lat, k1_lat) = Self::gen_interval(&ranges[0].0, &ranges[0].1);
+        let (k0_long, k1_long) = Self::gen_interval(&ranges[1].0, &ranges[1].1);
+        (vec![k0_lat, k0_long], vec![k1_lat, k1_long])
+    }
+
+    /// Like `gen_l_inf_ball_from_coords`, but longitude wraps around at
+    /// ±180° instead of being clamped, so a client near the antimeridian
+    /// counts on both sides of it. Longitude always gets two boundary pairs;
+    /// see `gen_l_inf_ball_toroidal`.
+    pub fn gen_l_inf_ball_from_coords_wrapping((lat, long): (i16, i16), (lat_size, long_size): (i16, i16)) -> (Vec<Vec<IntervalKey>>, Vec<Vec<IntervalKey>>) {
+        const HALF_TURN: i32 = 18000;
+        assert!((0..HALF_TURN as i16).contains(&long_size), "ball covers every longitude");
+
+        let left_lat = lat.saturating_sub(lat_size).clamp(-9000, 9000);
+        let right_lat = lat.saturating_add(lat_size).clamp(-9000, 9000);
+        let lat_range = (OffsetBinary.encode(left_lat), OffsetBinary.encode(right_lat));
+
+        // Longitudes live in [-18000, 18000), so +180° and -180° are the
+        // same meridian.
+        let wrap = |v: i32| ((v + HALF_TURN).rem_euclid(2 * HALF_TURN) - HALF_TURN) as i16;
+        let left_long = wrap(long as i32 - long_size as i32);
+        let right_long = wrap(long as i32 + long_size as i32);
+        let long_ranges = if left_long <= right_long {
+            vec![(OffsetBinary.encode(left_long), OffsetBinary.encode(right_long))]
+        } else {
+            vec![
+                (OffsetBinary.encode(left_long), OffsetBinary.encode((HALF_TURN - 1) as i16)),
+                (OffsetBinary.encode(-HALF_TURN as i16), OffsetBinary.encode(right_long)),
+            ]
+        };
+
+        Self::gen_union(&[vec![lat_range], long_ranges], &[1, 2])
+    }
+
+
+    pub fn eval_bit(&self, state: &EvalState, dir: bool) -> EvalState {
+        let tau = state.seed.expand_dir(!dir, dir);
+        self.correct(state, &tau, dir)
+    }
+
+    // Applies the correction word at `state.level` to `tau`, the expansion
+    // of `state.seed` towards `dir`.
+    fn correct(&self, state: &EvalState, tau: &prg::PrgOutput, dir: bool) -> EvalState {
+        let mut seed = tau.seeds.get(dir).clone();
+        let mut new_bit = *tau.bits.get(dir);
+        let mut new_y_bit = *tau.y_bits.get(dir);
+
+        if state.bit {
+            seed = &seed ^ &self.cor_words[state.level].seed;
+            new_bit ^= self.cor_words[state.level].bits().get(dir);
+            new_y_bit ^= self.cor_words[state.level].y_bits().get(dir);
+        }
+        new_y_bit ^= state.y_bit;
+
+        EvalState {
+            level: state.level + 1,
+            seed,
+            bit: new_bit,
+            y_bit: new_y_bit,
+        }
+    }
+
+    /// The states at every prefix of length `len`, in order, expanding each
+    /// seed once for both children. With `len` the domain size, this
+    /// evaluates every leaf at once.
+    pub fn eval_all(&self, len: usize) -> Vec<EvalState> {
+        assert!(len <= self.domain_size());
+        let mut level = vec![self.eval_init()];
+        for _ in 0..len {
+            level = level
+                .iter()
+                .flat_map(|state| {
+                    let tau = state.seed.expand();
+                    vec![self.correct(state, &tau, false), self.correct(state, &tau, true)]
+                })
+                .collect();
+        }
+        level
+    }
+
+    pub fn eval_init(&self) -> EvalState {
+        EvalState {
+            level: 0,
+            seed: self.root_seed.clone(),
+            bit: self.key_idx,
+            y_bit: self.key_idx
+        }
+    }
+
+    pub fn eval_ibDCF(&self, idx: &[bool]) -> bool {
+        debug_assert!(idx.len() <= self.domain_size());
+        debug_assert!(!idx.is_empty());
+        let mut state = self.eval_init();
+
+        for i in 0..idx.len() {
+            let bit = idx[i];
+            let state_new = self.eval_bit(&state, bit);
+            state = state_new;
+        }
+
+        state.y_bit ^ state.bit
+    }
+
+    pub fn domain_size(&self) -> usize {
+        self.cor_words.len()
+    }
+
+    /// Adds this server's share of the checks on one step of the key to
+    /// `check`: from the state `parent` to `children`, its children towards
+    /// zero and one. `side` is the side the key was generated with: true
+    /// for the left boundary of an interval and false for the right one.
+    ///
+    /// If the two shares encode a comparison with `alpha` on `side`, then
+    /// where the parent is on the path to `alpha`, so is the child in the
+    /// direction of `alpha`'s bit and the other one is not; off the path,
+    /// neither child is; and each child's output bit is the one that a
+    /// comparison gives it. All but the test of `alpha`'s bit are linear in
+    /// the shares, so each server adds its side of them, times coefficients
+    /// from `rng`, to `check.linear`, and the coefficient that `alpha`'s bit
+    /// multiplies to `check.path`. Both servers must draw the coefficients
+    /// from the same stream, which the client must not know in advance.
+    ///
+    /// The root is on the path by construction, so if the check passes on
+    /// every step down to a node, the outputs there are those of the
+    /// comparison with `alpha`, whatever the seeds.
+    pub fn check_step(
+        &self,
+        side: bool,
+        parent: &EvalState,
+        children: (&EvalState, &EvalState),
+        rng: &mut impl Rng,
+        check: &mut LevelCheck,
+    ) {
+        debug_assert_eq!(parent.level + 1, children.0.level);
+        let coeffs = rng.gen::<[u128; 4]>();
+
+        check.linear ^= times(coeffs[0], parent.bit ^ children.0.bit ^ children.1.bit);
+        for (dir, child, r) in [(false, children.0, coeffs[1]), (true, children.1, coeffs[2])] {
+            let leaves_path = parent.bit ^ child.bit;
+            check.linear ^= times(r, parent.y_bit ^ child.y_bit ^ (leaves_path & !(dir ^ side)));
+        }
+        // The child towards one is on the path iff the parent is and the
+        // bit of `alpha` is one.
+        check.linear ^= times(coeffs[3], children.1.bit);
+        check.path ^= times(coeffs[3], parent.bit);
+    }
+
+    /// Compact binary encoding of the key; see `from_bytes`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let n = self.cor_words.len();
+        let mut out = Vec::with_capacity(HEADER_SIZE + n * SEED_SIZE + (n + 1) / 2 + (n + 7) / 8);
+        out.push(KEY_FORMAT_VERSION);
+        out.push(self.key_idx as u8);
+        out.extend_from_slice(&(n as u32).to_le_bytes());
+        out.extend_from_slice(&self.root_seed.key);
+        for cw in &self.cor_words {
+            out.extend_from_slice(&cw.seed.key);
+        }
+        for pair in self.cor_words.chunks(2) {
+            let high = pair.get(1).map_or(0, |cw| cw.ctrl);
+            out.push(pair[0].ctrl | high << 4);
+        }
+        for byte in self.alpha_share.chunks(8) {
+            out.push(byte.iter().enumerate().fold(0, |acc, (i, &b)| acc | (b as u8) << i));
+        }
+        out
+    }
+
+    /// Decodes a key written by `to_bytes`, rejecting anything that is not
+    /// exactly a well-formed encoding. The format is:
+    ///
+    /// * the version byte, `KEY_FORMAT_VERSION`;
+    /// * a flags byte, holding `key_idx` in its lowest bit;
+    /// * the number of levels `n`, as a little-endian `u32`;
+    /// * the 16-byte root seed;
+    /// * the `n` 16-byte correction-word seeds;
+    /// * the correction words' control bits, four per level and two levels
+    ///   per byte, starting from the low nibble of the first byte;
+    /// * the share of `alpha`, eight bits per byte, starting from the least
+    ///   significant bit of the first byte.
+    ///
+    /// Unused bits at the end of the last two sections must be zero.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ibDCFKey, &'static str> {
+        if bytes.len() < HEADER_SIZE {
+            return Err("ibDCF key is truncated");
+        }
+        if bytes[0] < KEY_FORMAT_VERSION {
+            return Err("obsolete ibDCF key version; the client must generate new keys");
+        }
+        if bytes[0] != KEY_FORMAT_VERSION {
+            return Err("unsupported ibDCF key version");
+        }
+        if bytes[1] > 1 {
+            return Err("unknown ibDCF key flags");
+        }
+        let key_idx = bytes[1] == 1;
+
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&bytes[2..6]);
+        let n = u32::from_le_bytes(len) as usize;
+        let ctrl_start = n
+            .checked_mul(SEED_SIZE)
+            .and_then(|s| s.checked_add(HEADER_SIZE))
+            .ok_or("ibDCF key is too long")?;
+        let alpha_start = ctrl_start + (n + 1) / 2;
+        if bytes.len() != alpha_start + (n + 7) / 8 {
+            return Err("ibDCF key has the wrong length");
+        }
+
+        let seed_at = |offset: usize| {
+            let mut key = [0u8; SEED_SIZE];
+            key.copy_from_slice(&bytes[offset..offset + SEED_SIZE]);
+            prg::PrgSeed { key }
+        };
+        let ctrl = &bytes[ctrl_start..alpha_start];
+        let alpha = &bytes[alpha_start..];
+        if n % 2 == 1 && ctrl[n / 2] >> 4 != 0 {
+            return Err("ibDCF key has nonzero padding");
+        }
+        if n % 8 != 0 && alpha[n / 8] >> (n % 8) != 0 {
+            return Err("ibDCF key has nonzero padding");
+        }
+
+        let cor_words = (0..n)
+            .map(|i| CorWord {
+                seed: seed_at(HEADER_SIZE + i * SEED_SIZE),
+                ctrl: (ctrl[i / 2] >> (4 * (i % 2))) & 0xf,
+            })
+            .collect();
+
+        Ok(ibDCFKey {
+            key_idx,
+            root_seed: seed_at(6),
+            cor_words,
+            alpha_share: (0..n).map(|i| alpha[i / 8] >> (i % 8) & 1 == 1).collect(),
+        })
+    }
+}
+
+/// Like `ibDCFKey`, but the output at each prefix is shared additively in
+/// `T` rather than by XOR: it is the first server's share minus the
+/// second's. The shares of a range's two keys then give shares of whether
+/// a prefix meets the range with no interaction between the servers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArithDCFKey<T> {
+    pub key: ibDCFKey,
+    // At each level, the corrections to the values of the left and right
+    // children of a node on the path to `alpha`.
+    pub val_words: Vec<(T, T)>,
+}
+
+/// One server's share of an interval with arithmetic outputs.
+pub type ArithIntervalKey<T> = (ArithDCFKey<T>, ArithDCFKey<T>);
+
+impl<T> ArithDCFKey<T>
+where
+    T: Group + prg::FromRng + Clone,
+{
+    /// Keys whose output at a prefix is one if every string with that
+    /// prefix is on the `side` of `alpha_bits` (see `gen_ibDCF`) and zero
+    /// otherwise.
+    pub fn gen(alpha_bits: &[bool], side: bool) -> (ArithDCFKey<T>, ArithDCFKey<T>) {
+        Self::gen_outputs(alpha_bits, side, |bit, dir| dir != bit && dir != side)
+    }
+
+    /// Keys whose output at a prefix is one if it is a prefix of
+    /// `alpha_bits` and zero otherwise, i.e., an incremental point function.
+    /// The boolean outputs of the underlying `ibDCFKey` mean nothing.
+    pub fn gen_point(alpha_bits: &[bool]) -> (ArithDCFKey<T>, ArithDCFKey<T>) {
+        Self::gen_outputs(alpha_bits, true, |bit, dir| dir == bit)
+    }
+
+    // Keys on the tree of `gen_ibDCF(alpha_bits, side)` whose output at the
+    // child in direction `dir` of a node on the path to `alpha`, where
+    // `alpha` has bit `bit`, is `output(bit, dir)`.
+    //
+    // A node's value is its parent's plus the conversion of its seed before
+    // correction, plus the node's value word if its parent's control bit is
+    // set. Off the path to `alpha` the two shares agree on both, so the
+    // values of a subtree are all that of its root; on the path, the words
+    // give each child the right value.
+    fn gen_outputs<F>(alpha_bits: &[bool], side: bool, output: F) -> (ArithDCFKey<T>, ArithDCFKey<T>)
+    where
+        F: Fn(bool, bool) -> bool,
+    {
+        let mut val_words = Vec::with_capacity(alpha_bits.len());
+        // The output at the node on the path, which starts at the root.
+        let mut on_path = T::zero();
+        let (key0, key1) = ibDCFKey::gen_with(alpha_bits, side, |bit, bits, seeds| {
+            let data = seeds.map(|s| s.expand());
+            let mut words = (T::zero(), T::zero());
+            for (dir, word) in words.iter_mut() {
+                // A child's value is its parent's plus what the level adds.
+                if output(bit, dir) {
+                    *word = T::one();
+                }
+                word.sub(&on_path);
+                word.sub(&data.0.seeds.get(dir).convert::<T>().word);
+                word.add(&data.1.seeds.get(dir).convert::<T>().word);
+                // The word counts towards the share whose control bit is set.
+                if bits.1 {
+                    word.negate();
+                }
+            }
+            val_words.push(words);
+            on_path = if output(bit, bit) { T::one() } else { T::zero() };
+        });
+
+        (
+            ArithDCFKey { key: key0, val_words: val_words.clone() },
+            ArithDCFKey { key: key1, val_words },
+        )
+    }
+
+    /// Keys for the interval [`left_bits`, `right_bits`]: at each prefix,
+    /// one minus the outputs of the two keys is whether the prefix meets
+    /// the interval.
+    pub fn gen_interval(left_bits: &[bool], right_bits: &[bool]) -> (ArithIntervalKey<T>, ArithIntervalKey<T>) {
+        let left_key = Self::gen(left_bits, true);
+        let right_key = Self::gen(right_bits, false);
+        ((left_key.0, right_key.0), (left_key.1, right_key.1))
+    }
+
+    /// Keys for the one-dimensional ball of radius `size` around `alpha`,
+    /// clipped to the domain as in `ibDCFKey::gen_l_inf_box`.
+    pub fn gen_l_inf_ball(alpha: &[bool], size: u32) -> (ArithIntervalKey<T>, ArithIntervalKey<T>) {
+        let (left, right) = ball_endpoints(alpha, size, false);
+        Self::gen_interval(&left, &right)
+    }
+
+    pub fn eval_init(&self) -> (EvalState, T) {
+        (self.key.eval_init(), T::zero())
+    }
+
+    pub fn eval_bit(&self, state: &(EvalState, T), dir: bool) -> (EvalState, T) {
+        let tau = state.0.seed.expand_dir(!dir, dir);
+        self.correct(state, &tau, dir)
+    }
+
+    fn correct(&self, (state, value): &(EvalState, T), tau: &prg::PrgOutput, dir: bool) -> (EvalState, T) {
+        let mut value = value.clone();
+        value.add(&tau.seeds.get(dir).convert::<T>().word);
+        if state.bit {
+            value.add(self.val_words[state.level].get(dir));
+        }
+        (self.key.correct(state, tau, dir), value)
+    }
+
+    /// Adds to `check` this server's side of the check that the outputs at
+    /// `children`, towards zero and one, follow from the output at `parent`
+    /// as in `gen(_, side)`: the child towards `side` has its parent's
+    /// output, and so has the other child, plus one if the child towards
+    /// `side` is on the path to `alpha`. With `ibDCFKey::check_step`, which
+    /// shows which nodes are on the path, this shows that the outputs at
+    /// the nodes checked are those of the comparison, whatever the value
+    /// words. The coefficients come from `rng`, as in `check_step`.
+    pub fn check_values(
+        &self,
+        side: bool,
+        parent: &(EvalState, T),
+        children: (&(EvalState, T), &(EvalState, T)),
+        rng: &mut impl Rng,
+        check: &mut ValueCheck<T>,
+    ) {
+        let (towards, away) = if side { (children.1, children.0) } else { (children.0, children.1) };
+        for (child, on_path) in [(towards, None), (away, Some(towards.0.bit))] {
+            let mut coeff = T::zero();
+            coeff.from_rng(rng);
+            let mut step = child.1.clone();
+            step.sub(&parent.1);
+            step.mul(&coeff);
+            check.linear.add(&step);
+            if let Some(bit) = on_path {
+                check.bits.push((bit, coeff));
+            }
+        }
+    }
+
+    /// Like `check_values`, for keys from `gen_point`: the output at each of
+    /// `children` must be one if it is on the path to `alpha` and zero
+    /// otherwise, whatever its parent's.
+    pub fn check_point_values(
+        &self,
+        children: (&(EvalState, T), &(EvalState, T)),
+        rng: &mut impl Rng,
+        check: &mut ValueCheck<T>,
+    ) {
+        for child in [children.0, children.1] {
+            let mut coeff = T::zero();
+            coeff.from_rng(rng);
+            let mut value = child.1.clone();
+            value.mul(&coeff);
+            check.linear.add(&value);
+            check.bits.push((child.0.bit, coeff));
+        }
+    }
+
+    /// The states and this server's shares of the outputs at every prefix
+    /// of length `len`, in order; see `ibDCFKey::eval_all`.
+    pub fn eval_all(&self, len: usize) -> Vec<(EvalState, T)> {
+        assert!(len <= self.domain_size());
+        let mut level = vec![self.eval_init()];
+        for _ in 0..len {
+            level = level
+                .iter()
+                .flat_map(|state| {
+                    let tau = state.0.seed.expand();
+                    vec![self.correct(state, &tau, false), self.correct(state, &tau, true)]
+                })
+                .collect();
+        }
+        level
+    }
+
+    /// This server's share of the output at the prefix `idx`.
+    pub fn eval(&self, idx: &[bool]) -> T {
+        let mut state = self.eval_init();
+        for &bit in idx {
+            state = self.eval_bit(&state, bit);
+        }
+        state.1
+    }
+
+    pub fn domain_size(&self) -> usize {
+        self.key.domain_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_expansions_match_eval_str() {
+        let nclients = 5;
+        let keys = (0..nclients)
+            .map(|c| {
+                let alpha = vec![MSB_u32_to_bits(3, c), MSB_u32_to_bits(5, 3 * c), MSB_u32_to_bits(4, 11)];
+                to_union(ibDCFKey::gen_l_inf_box(alpha, &[1, 4, 2]).1)
+            })
+            .collect::<Vec<_>>();
+        let mut states = keys
+            .iter()
+            .map(|k| {
+                k.iter()
+                    .map(|d| d.iter().map(|(l, r)| (l.eval_init(), r.eval_init())).collect::<Vec<_>>())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        for level in 0..5 {
+            let n_active = [3, 5, 4].iter().filter(|&&len| level < len).count();
+            let key_refs = keys.iter().collect::<Vec<_>>();
+            let state_refs = states.iter().collect::<Vec<_>>();
+            let steps = eval_both_batch(&key_refs, &state_refs);
+
+            // Every child of the crawl node.
+            let mut last = vec![];
+            for child in 0..(1u32 << n_active) {
+                let eval_string = (0..n_active).map(|i| (child >> i) & 1 == 1).collect::<Vec<_>>();
+                last = (0..nclients as usize)
+                    .map(|c| {
+                        let shared = child_states(&states[c], &steps[c], &eval_string);
+                        let single = eval_str(&keys[c], &states[c], &eval_string);
+                        for (d_shared, d_single) in shared.iter().zip(&single) {
+                            for ((al, ar), (bl, br)) in d_shared.iter().zip(d_single) {
+                                for (a, b) in &[(al, bl), (ar, br)] {
+                                    assert_eq!(a.level, b.level);
+                                    assert_eq!(a.seed.key, b.seed.key);
+                                    assert_eq!((a.bit, a.y_bit), (b.bit, b.y_bit));
+                                }
+                            }
+                        }
+                        shared
+                    })
+                    .collect::<Vec<_>>();
+            }
+            states = last;
+        }
+    }
+
+    // The integers modulo 2^64, for keys with arithmetic outputs.
+    #[derive(Clone, Debug, PartialEq)]
+    struct Wrapping(u64);
+
+    impl Group for Wrapping {
+        fn zero() -> Self {
+            Wrapping(0)
+        }
+
+        fn one() -> Self {
+            Wrapping(1)
+        }
+
+        fn negate(&mut self) {
+            self.0 = self.0.wrapping_neg();
+        }
+
+        fn reduce(&mut self) {}
+
+        fn add(&mut self, other: &Self) {
+            self.0 = self.0.wrapping_add(other.0);
+        }
+
+        fn add_lazy(&mut self, other: &Self) {
+            self.add(other);
+        }
+
+        fn mul(&mut self, other: &Self) {
+            self.0 = self.0.wrapping_mul(other.0);
+        }
+
+        fn mul_lazy(&mut self, other: &Self) {
+            self.mul(other);
+        }
+
+        fn sub(&mut self, other: &Self) {
+            self.0 = self.0.wrapping_sub(other.0);
+        }
+    }
+
+    impl prg::FromRng for Wrapping {
+        fn from_rng(&mut self, stream: &mut (impl rand::Rng + rand_core::RngCore)) {
+            self.0 = stream.gen();
+        }
+    }
+
+    #[test]
+    fn arith_expansions_match_eval_bit() {
+        let keys = (0..5u32)
+            .map(|c| ArithDCFKey::<Wrapping>::gen_l_inf_ball(&MSB_u32_to_bits(4, 3 * c), 2).1)
+            .collect::<Vec<_>>();
+        let mut states = keys
+            .iter()
+            .map(|(l, r)| vec![vec![l.eval_init(), r.eval_init()]])
+            .collect::<Vec<_>>();
+
+        for _ in 0..4 {
+            let key_refs = keys.iter().map(|(l, r)| vec![vec![l, r]]).collect::<Vec<_>>();
+            let state_refs = states.iter().collect::<Vec<_>>();
+            let steps = arith_both_batch(&key_refs, &state_refs);
+            for (c, (l, r)) in keys.iter().enumerate() {
+                for &bit in &[false, true] {
+                    let shared = child_states(&states[c], &steps[c], &[bit]);
+                    let single = [l.eval_bit(&states[c][0][0], bit), r.eval_bit(&states[c][0][1], bit)];
+                    for (a, b) in shared[0].iter().zip(&single) {
+                        assert_eq!(a.0.seed.key, b.0.seed.key);
+                        assert_eq!((a.0.bit, a.0.y_bit), (b.0.bit, b.0.y_bit));
+                        assert_eq!(a.1, b.1);
+                    }
+                }
+            }
+            // Follow the right child.
+            states = (0..keys.len()).map(|c| child_states(&states[c], &steps[c], &[true])).collect();
+        }
+    }
+}
@@ -0,0 +1,388 @@
+// Key generation and serialization for clients, with none of the
+// servers' dependencies: the servers' `counttree` crate builds on this one,
+// and clients, including the C API in `ffi/` and the Python module in
+// `python/`, depend on it alone. The items at the top level are the API
+// for clients, and they are kept stable: new items may be added, but
+// existing ones are not renamed or removed without bumping
+// `KEY_FORMAT_VERSION` or the crate's major version.
+//
+// A typical client encodes its point with an `Encoder`, generates a pair
+// of keys with one of the `ibDCFKey::gen_*` functions, and sends each
+// server its key, serialized with serde or `ibDCFKey::to_bytes`.
+
+pub mod encoding;
+pub mod hamming;
+pub mod ibDCF;
+pub mod prg;
+pub mod reference;
+
+pub use crate::encoding::{Encoder, OffsetBinary, OrderedF64};
+pub use crate::hamming::HammingKey;
+pub use crate::ibDCF::{coords_box, ibDCFKey, to_union, IntervalKey, KEY_FORMAT_VERSION};
+pub use crate::prg::{with_seed, PrgSeed};
+
+use std::convert::TryInto;
+
+// Additive group, such as (Z_n, +)
+pub trait Group {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn negate(&mut self);
+    fn reduce(&mut self);
+    fn add(&mut self, other: &Self);
+    fn add_lazy(&mut self, other: &Self);
+    fn mul(&mut self, other: &Self);
+    fn mul_lazy(&mut self, other: &Self);
+    fn sub(&mut self, other: &Self);
+}
+
+// Pairs of elements, e.g., of the two fields of a collection, added and
+// multiplied component-wise.
+impl<A: Group, B: Group> Group for (A, B) {
+    fn zero() -> Self {
+        (A::zero(), B::zero())
+    }
+
+    fn one() -> Self {
+        (A::one(), B::one())
+    }
+
+    fn negate(&mut self) {
+        self.0.negate();
+        self.1.negate();
+    }
+
+    fn reduce(&mut self) {
+        self.0.reduce();
+        self.1.reduce();
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.0.add(&other.0);
+        self.1.add(&other.1);
+    }
+
+    fn add_lazy(&mut self, other: &Self) {
+        self.0.add_lazy(&other.0);
+        self.1.add_lazy(&other.1);
+    }
+
+    fn mul(&mut self, other: &Self) {
+        self.0.mul(&other.0);
+        self.1.mul(&other.1);
+    }
+
+    fn mul_lazy(&mut self, other: &Self) {
+        self.0.mul_lazy(&other.0);
+        self.1.mul_lazy(&other.1);
+    }
+
+    fn sub(&mut self, other: &Self) {
+        self.0.sub(&other.0);
+        self.1.sub(&other.1);
+    }
+}
+
+// The integers modulo the 63-bit prime p = 2^63 - 25. Group and FromRng
+// are this crate's, so their implementations for `u64` must be here.
+const MODULUS_64: u64 = 9223372036854775783u64;
+const MODULUS_64_BIG: u128 = 9223372036854775783u128;
+
+impl Group for u64 {
+    #[inline]
+    fn zero() -> Self {
+        0u64
+    }
+
+    #[inline]
+    fn one() -> Self {
+        1u64
+    }
+
+    #[inline]
+    fn add(&mut self, other: &Self) {
+        debug_assert!(*self < MODULUS_64);
+        debug_assert!(*other < MODULUS_64);
+        *self += other;
+        *self %= MODULUS_64;
+    }
+
+    #[inline]
+    fn mul(&mut self, other: &Self) {
+        debug_assert!(*self < MODULUS_64);
+        debug_assert!(*other < MODULUS_64);
+        let s64: u64 = *self;
+        let o64: u64 = *other;
+        let a: u128 = s64.into();
+        let b: u128 = o64.into();
+
+        let res = (a * b) % MODULUS_64_BIG;
+        *self = res.try_into().unwrap();
+    }
+
+    #[inline]
+    fn add_lazy(&mut self, other: &Self) {
+        self.add(other);
+    }
+
+    #[inline]
+    fn mul_lazy(&mut self, other: &Self) {
+        self.mul(other);
+    }
+
+    #[inline]
+    fn reduce(&mut self) {}
+
+    #[inline]
+    fn sub(&mut self, other: &Self) {
+        debug_assert!(*self < MODULUS_64);
+        debug_assert!(*other < MODULUS_64);
+        let mut neg = *other;
+        neg.negate();
+        self.add(&neg);
+    }
+
+    #[inline]
+    fn negate(&mut self) {
+        debug_assert!(*self < MODULUS_64);
+        *self = MODULUS_64 - *self;
+        *self %= MODULUS_64;
+    }
+}
+
+impl prg::FromRng for u64 {
+    fn from_rng(&mut self, rng: &mut impl rand::Rng) {
+        *self = u64::MAX;
+        while *self >= MODULUS_64 {
+            *self = rng.next_u64();
+            *self &= 0x7fffffffffffffffu64;
+        }
+    }
+}
+
+pub fn u32_to_bits(nbits: u8, input: u32) -> Vec<bool> {
+    assert!(nbits <= 32);
+
+    let mut out: Vec<bool> = Vec::new();
+    for i in 0..nbits {
+        let bit = (input & (1 << i)) != 0;
+        out.push(bit);
+    }
+    out
+}
+
+pub fn  MSB_u32_to_bits(nbits: u8, input: u32) -> Vec<bool> {
+    assert!(nbits <= 32);
+
+    let mut out: Vec<bool> = Vec::new();
+    for i in (0..nbits).rev() {
+        let bit = (input & (1 << i)) != 0;
+        out.push(bit);
+    }
+    out
+}
+
+pub fn bits_to_u32(bits: &[bool]) -> u32 {
+    assert!(bits.len() <= 32);
+
+    let mut result: u32 = 0;
+    for (i, &bit) in bits.iter().enumerate() {
+        if bit {
+            result |= 1 << (bits.len() - 1 - i);
+        }
+    }
+    result
+}
+
+pub fn string_to_bits(s: &str) -> Vec<bool> {
+    let mut bits = vec![];
+    let byte_vec = s.to_string().into_bytes();
+    for byte in &byte_vec {
+        let mut b = u32_to_bits(8, (*byte).into());
+        bits.append(&mut b);
+    }
+    bits
+}
+
+pub fn bits_to_u8(bits: &[bool]) -> u8 {
+    assert_eq!(bits.len(), 8);
+    let mut out = 0u8;
+    for i in 0..8 {
+        let b8: u8 = bits[i].into();
+        out |= b8 << i;
+    }
+
+    out
+}
+
+pub fn bits_to_string(bits: &[bool]) -> String {
+    assert!(bits.len() % 8 == 0);
+
+    let mut out: String = "".to_string();
+    let byte_len = bits.len() / 8;
+    for b in 0..byte_len {
+        let byte = &bits[8 * b..8 * (b + 1)];
+        let ubyte = bits_to_u8(&byte);
+        out.push_str(std::str::from_utf8(&[ubyte]).unwrap());
+    }
+
+    out
+}
+
+// Every vector of `dim` bits, e.g., the children of a node of the crawl.
+pub fn all_bit_vectors(dim: usize) -> Vec<Vec<bool>> {
+    (0..1 << dim).map(|i| {
+        (0..dim).map(|j| (i >> j) & 1 == 1).collect()
+    }).collect()
+}
+
+pub fn add_bitstrings(alpha: &[bool], beta: &[bool]) -> Vec<bool> {
+    let max_len = alpha.len().max(beta.len());
+    let mut alpha_padded = vec![false; max_len - alpha.len()];
+    alpha_padded.extend(alpha);
+    let mut beta_padded = vec![false; max_len - beta.len()];
+    beta_padded.extend(beta);
+    let mut sum = Vec::new();
+    let mut carry = false;
+
+    // Iterate from LSB to MSB
+    for (a, b) in alpha_padded.iter().rev().zip(beta_padded.iter().rev()) {
+        let (s, c) = full_adder(*a, *b, carry);
+        sum.push(s);
+        carry = c;
+    }
+    if carry {
+        sum.push(true);
+    }
+    // Reverse to get MSB first ordering
+    sum.into_iter().rev().collect()
+}
+
+pub fn subtract_bitstrings(alpha: &[bool], beta: &[bool]) -> Vec<bool> {
+    let max_len = alpha.len().max(beta.len());
+    let mut alpha_padded = vec![false; max_len - alpha.len()];
+    alpha_padded.extend(alpha);
+    let mut beta_padded = vec![false; max_len - beta.len()];
+    beta_padded.extend(beta);
+
+    let mut beta_twos_complement: Vec<bool> = beta_padded.iter().map(|b| !b).collect();
+
+    let mut carry = true;
+    for bit in beta_twos_complement.iter_mut().rev() {
+        let sum = *bit ^ carry;
+        carry = *bit && carry;
+        *bit = sum;
+        if !carry { break; }
+    }
+
+    let mut result = Vec::new();
+    let mut carry = false;
+
+    for (a, b) in alpha_padded.iter().rev().zip(beta_twos_complement.iter().rev()) {
+        let (s, c) = full_adder(*a, *b, carry);
+        result.push(s);
+        carry = c;
+    }
+
+    // If there’s a carry-out, ignore it (overflow)
+
+    // Reverse to get MSB-first ordering
+    result.into_iter().rev().collect()
+}
+
+// Helper function for single-bit addition with carry
+fn full_adder(a: bool, b: bool, carry_in: bool) -> (bool, bool) {
+    let sum = a ^ b ^ carry_in;
+    let carry_out = (a & b) | (b & carry_in) | (a & carry_in);
+    (sum, carry_out)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_bits() {
+        let empty: Vec<bool> = vec![];
+        assert_eq!(u32_to_bits(0, 7), empty);
+        assert_eq!(u32_to_bits(1, 0), vec![false]);
+        assert_eq!(u32_to_bits(2, 0), vec![false, false]);
+        assert_eq!(u32_to_bits(2, 3), vec![true, true]);
+        assert_eq!(u32_to_bits(2, 1), vec![true, false]);
+        assert_eq!(u32_to_bits(12, 65535), vec![true; 12]);
+    }
+
+    #[test]
+    fn to_string() {
+        let empty: Vec<bool> = vec![];
+        assert_eq!(string_to_bits(""), empty);
+        let avec = vec![true, false, false, false, false, true, true, false];
+        assert_eq!(string_to_bits("a"), avec);
+
+        let mut aaavec = vec![];
+        for _i in 0..3 {
+            aaavec.append(&mut avec.clone());
+        }
+        assert_eq!(string_to_bits("aaa"), aaavec);
+    }
+
+    #[test]
+    fn to_from_string() {
+        let s = "basfsdfwefwf";
+        let bitvec = string_to_bits(s);
+        let s2 = bits_to_string(&bitvec);
+
+        assert_eq!(bitvec.len(), s.len() * 8);
+        assert_eq!(s, s2);
+    }
+
+    #[test]
+    fn add128() {
+        let mut res = u64::zero();
+        let one = 1u64;
+        let two = 2u64;
+        res.add(&one);
+        res.add(&one);
+        assert_eq!(two, res);
+    }
+
+    #[test]
+    fn add_big128() {
+        let mut res = 1u64;
+        let two = 2u64;
+        res.add(&two);
+        res.add(&(MODULUS_64 - 1));
+        assert_eq!(two, res);
+    }
+
+    #[test]
+    fn mul_big128() {
+        let mut res = 0u64;
+        let four = 4u64;
+        res.add(&four);
+        res.mul(&(MODULUS_64 - 1));
+        assert_eq!(res, MODULUS_64 - 4);
+    }
+
+    #[test]
+    fn mul_big2128() {
+        let mut res = u64::zero();
+        let two = 2u64;
+        let eight = 8u64;
+        res.add(&two);
+        res.mul(&eight);
+        assert_eq!(res, 16u64);
+    }
+
+    #[test]
+    fn negate128() {
+        let zero = u64::zero();
+        let x = 1123123u64;
+        let mut negx = 1123123u64;
+        let mut res = 0u64;
+
+        negx.negate();
+        res.add(&x);
+        res.add(&negx);
+        assert_eq!(zero, res);
+    }
+}
@@ -0,0 +1,741 @@
+#[cfg(all(target_arch = "x86_64", target_feature = "aes", target_feature = "sse2"))]
+use core::arch::x86_64::{
+    __m128i, _mm_add_epi64, _mm_loadu_si128, _mm_set_epi64x, _mm_storeu_si128,
+};
+
+use aes::block_cipher::consts::{U16, U8};
+use aes::block_cipher::{generic_array::GenericArray, BlockCipher, NewBlockCipher};
+use aes::Aes128;
+use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::Aes128Ctr;
+
+use rand::Rng;
+use rand_core::RngCore;
+
+use serde::Deserialize;
+use serde::Serialize;
+use std::ops;
+
+// AES key size in bytes. We always use AES-128,
+// which has 16-byte keys.
+const AES_KEY_SIZE: usize = 16;
+
+// AES block size in bytes. Always 16 bytes.
+pub const AES_BLOCK_SIZE: usize = 16;
+
+/// The fixed-key hash behind `expand`, `expand_all` and `convert`, which
+/// maps a 128-bit counter `x` to `AES_0(x) XOR x` under the all-zero key.
+/// Clients and servers may be built with different backends, so every
+/// backend must compute exactly the same function.
+pub trait Prg {
+    /// A counter, in whatever form the backend adds to fastest.
+    type Ctr: Copy;
+
+    fn new() -> Self;
+
+    fn load(bytes: &[u8; AES_BLOCK_SIZE]) -> Self::Ctr;
+
+    fn store(ctr: Self::Ctr, at: &mut [u8]);
+
+    /// Adds one to the high 64 bits of the counter, i.e., to bytes 8 to 15
+    /// read as a little-endian integer, wrapping around.
+    fn inc(ctr: Self::Ctr) -> Self::Ctr;
+
+    fn hash(&self, ctr: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE];
+
+    /// `hash` of eight counters at once.
+    fn hash8(&self, ctrs: &[[u8; AES_BLOCK_SIZE]; 8]) -> [[u8; AES_BLOCK_SIZE]; 8];
+}
+
+/// The backend in use: AES-NI on x86-64 builds with the `aes` target
+/// feature (e.g., with `-C target-cpu=native`), unless the `portable-prg`
+/// feature asks for software AES, which every other build uses.
+#[cfg(all(
+    target_arch = "x86_64",
+    target_feature = "aes",
+    target_feature = "sse2",
+    not(feature = "portable-prg")
+))]
+pub type FixedKeyPrg = AesNiPrg;
+
+#[cfg(not(all(
+    target_arch = "x86_64",
+    target_feature = "aes",
+    target_feature = "sse2",
+    not(feature = "portable-prg")
+)))]
+pub type FixedKeyPrg = SoftPrg;
+
+/// AES-NI, with the counters in SSE registers.
+#[cfg(all(target_arch = "x86_64", target_feature = "aes", target_feature = "sse2"))]
+pub struct AesNiPrg {
+    // With these target features, the `aes` crate's `Aes128` is AES-NI.
+    aes: Aes128,
+}
+
+/// Bitsliced software AES, with the counters as bytes. Runs anywhere.
+pub struct SoftPrg {
+    aes: aes_soft::Aes128,
+}
+
+// AES_0(ctr) XOR ctr for one counter.
+fn hash_with<C>(aes: &C, ctr: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE]
+where
+    C: BlockCipher<BlockSize = U16>,
+{
+    let mut block = GenericArray::clone_from_slice(ctr);
+    aes.encrypt_block(&mut block);
+
+    let mut out = [0u8; AES_BLOCK_SIZE];
+    out.iter_mut()
+        .zip(block.iter().zip(ctr.iter()))
+        .for_each(|(o, (x1, x2))| *o = *x1 ^ *x2);
+    out
+}
+
+// AES_0(ctr) XOR ctr for eight counters, encrypted in parallel.
+fn hash8_with<C>(aes: &C, cnts: &[[u8; AES_BLOCK_SIZE]; 8]) -> [[u8; AES_BLOCK_SIZE]; 8]
+where
+    C: BlockCipher<BlockSize = U16, ParBlocks = U8>,
+{
+    let block = GenericArray::clone_from_slice(&[0u8; 16]);
+    let mut block8 = GenericArray::clone_from_slice(&[block; 8]);
+    for i in 0..8 {
+        block8[i].copy_from_slice(&cnts[i]);
+    }
+
+    aes.encrypt_blocks(&mut block8);
+
+    let mut out = [[0u8; AES_BLOCK_SIZE]; 8];
+    for i in 0..8 {
+        out[i]
+            .iter_mut()
+            .zip(block8[i].iter().zip(cnts[i].iter()))
+            .for_each(|(o, (x1, x2))| *o = *x1 ^ *x2);
+    }
+    out
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "aes", target_feature = "sse2"))]
+impl Prg for AesNiPrg {
+    type Ctr = __m128i;
+
+    fn new() -> Self {
+        AesNiPrg {
+            aes: Aes128::new(GenericArray::from_slice(&[0; AES_KEY_SIZE])),
+        }
+    }
+
+    // Modified from RustCrypto aesni crate
+    #[inline(always)]
+    fn load(bytes: &[u8; AES_BLOCK_SIZE]) -> __m128i {
+        // Safety: `loadu` supports unaligned loads
+        #[allow(clippy::cast_ptr_alignment)]
+        unsafe {
+            _mm_loadu_si128(bytes.as_ptr() as *const __m128i)
+        }
+    }
+
+    #[inline(always)]
+    fn store(ctr: __m128i, at: &mut [u8]) {
+        debug_assert_eq!(at.len(), AES_BLOCK_SIZE);
+
+        #[allow(clippy::cast_ptr_alignment)]
+        unsafe {
+            _mm_storeu_si128(at.as_mut_ptr() as *mut __m128i, ctr)
+        }
+    }
+
+    // From RustCrypto aesni crate
+    #[inline(always)]
+    fn inc(ctr: __m128i) -> __m128i {
+        unsafe { _mm_add_epi64(ctr, _mm_set_epi64x(1, 0)) }
+    }
+
+    fn hash(&self, ctr: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+        hash_with(&self.aes, ctr)
+    }
+
+    fn hash8(&self, ctrs: &[[u8; AES_BLOCK_SIZE]; 8]) -> [[u8; AES_BLOCK_SIZE]; 8] {
+        hash8_with(&self.aes, ctrs)
+    }
+}
+
+impl Prg for SoftPrg {
+    type Ctr = [u8; AES_BLOCK_SIZE];
+
+    fn new() -> Self {
+        SoftPrg {
+            aes: aes_soft::Aes128::new(GenericArray::from_slice(&[0; AES_KEY_SIZE])),
+        }
+    }
+
+    #[inline(always)]
+    fn load(bytes: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+        *bytes
+    }
+
+    #[inline(always)]
+    fn store(ctr: [u8; AES_BLOCK_SIZE], at: &mut [u8]) {
+        at.copy_from_slice(&ctr);
+    }
+
+    #[inline(always)]
+    fn inc(mut ctr: [u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+        let mut high = [0u8; 8];
+        high.copy_from_slice(&ctr[8..]);
+        let high = u64::from_le_bytes(high).wrapping_add(1);
+        ctr[8..].copy_from_slice(&high.to_le_bytes());
+        ctr
+    }
+
+    fn hash(&self, ctr: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+        hash_with(&self.aes, ctr)
+    }
+
+    fn hash8(&self, ctrs: &[[u8; AES_BLOCK_SIZE]; 8]) -> [[u8; AES_BLOCK_SIZE]; 8] {
+        hash8_with(&self.aes, ctrs)
+    }
+}
+
+// XXX Todo try using 8-way parallelism
+pub struct FixedKeyPrgStream<P: Prg = FixedKeyPrg> {
+    prg: P,
+    ctr: P::Ctr,
+    buf: [u8; AES_BLOCK_SIZE * 8],
+    have: usize,
+    buf_ptr: usize,
+    count: usize,
+}
+
+use std::cell::RefCell;
+
+thread_local!(static FIXED_KEY_STREAM: RefCell<FixedKeyPrgStream> = RefCell::new(FixedKeyPrgStream::new()));
+
+// The stream that `thread_rng` draws from inside `with_seed`.
+thread_local!(static SEEDED_STREAM: RefCell<Option<PrgStream>> = RefCell::new(None));
+
+/// The randomness behind key generation: `rand::thread_rng`, unless the
+/// calling thread is inside `with_seed`. The servers' protocol steps draw
+/// theirs from the seed of their `KeyCollection` instead.
+pub fn thread_rng() -> ThreadRng {
+    ThreadRng { _private: () }
+}
+
+/// Runs `f` with `thread_rng` on this thread replaced by a stream from
+/// `seed`, so that every key generated in `f` is the same on each run.
+/// Other threads, e.g., rayon's, still draw fresh randomness.
+pub fn with_seed<R>(seed: &PrgSeed, f: impl FnOnce() -> R) -> R {
+    // Restores the outer stream, if any, even if `f` panics.
+    struct Restore(Option<PrgStream>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let outer = self.0.take();
+            SEEDED_STREAM.with(|s| *s.borrow_mut() = outer);
+        }
+    }
+
+    let _restore = Restore(SEEDED_STREAM.with(|s| s.borrow_mut().replace(seed.to_rng())));
+    f()
+}
+
+/// See `thread_rng`.
+pub struct ThreadRng {
+    _private: (),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PrgSeed {
+    pub key: [u8; AES_KEY_SIZE],
+}
+
+pub trait FromRng {
+    fn from_rng(&mut self, stream: &mut (impl rand::Rng + rand_core::RngCore));
+
+    fn randomize(&mut self) {
+        self.from_rng(&mut thread_rng());
+    }
+}
+
+impl<A: FromRng, B: FromRng> FromRng for (A, B) {
+    fn from_rng(&mut self, stream: &mut (impl rand::Rng + rand_core::RngCore)) {
+        self.0.from_rng(stream);
+        self.1.from_rng(stream);
+    }
+}
+
+#[derive(Clone)]
+pub struct PrgStream {
+    stream: Aes128Ctr,
+}
+
+// The uses of a seed, each XORed into the low half of the seed to give
+// the first counter of its blocks. The counters only ever grow in their
+// high half, so the blocks of different domains never meet.
+const DOMAIN_CHILDREN: u64 = 0;
+const DOMAIN_CONTROL: u64 = 1;
+const DOMAIN_CONVERT: u64 = 2;
+
+// The control bits in the low nibble of `block`: (bits, y_bits).
+fn control_bits(block: &[u8; AES_BLOCK_SIZE]) -> ((bool, bool), (bool, bool)) {
+    let b = block[0];
+    ((b & 1 != 0, b & 2 != 0), (b & 4 != 0, b & 8 != 0))
+}
+
+pub struct PrgOutput {
+    pub bits: (bool, bool),
+    pub y_bits: (bool, bool),
+    pub seeds: (PrgSeed, PrgSeed),
+}
+
+pub struct ConvertOutput<T: FromRng> {
+    pub seed: PrgSeed,
+    pub word: T,
+}
+
+impl ops::BitXor for &PrgSeed {
+    type Output = PrgSeed;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        let mut out = PrgSeed::zero();
+
+        for ((out, left), right) in out.key.iter_mut().zip(&self.key).zip(&rhs.key) {
+            *out = left ^ right;
+        }
+
+        out
+    }
+}
+
+impl PrgSeed {
+    pub fn to_rng(&self) -> PrgStream {
+        let iv: [u8; AES_BLOCK_SIZE] = [0; AES_BLOCK_SIZE];
+
+        let key = GenericArray::from_slice(&self.key);
+        let nonce = GenericArray::from_slice(&iv);
+        PrgStream {
+            stream: Aes128Ctr::new(key, nonce),
+        }
+    }
+
+    /// Like `to_rng`, but a different stream for each `index`. Streams for
+    /// different indices do not overlap unless one runs for 2^64 blocks.
+    pub fn to_rng_at(&self, index: u64) -> PrgStream {
+        let mut iv: [u8; AES_BLOCK_SIZE] = [0; AES_BLOCK_SIZE];
+        iv[..8].copy_from_slice(&index.to_be_bytes());
+
+        let key = GenericArray::from_slice(&self.key);
+        let nonce = GenericArray::from_slice(&iv);
+        PrgStream {
+            stream: Aes128Ctr::new(key, nonce),
+        }
+    }
+
+    // The first counter of this seed's blocks for `domain`.
+    fn counter(&self, domain: u64) -> [u8; AES_BLOCK_SIZE] {
+        let mut ctr = self.key;
+        for (c, d) in ctr[..8].iter_mut().zip(&domain.to_le_bytes()) {
+            *c ^= d;
+        }
+        ctr
+    }
+
+    /// The seeds of the children in the directions asked for, and the
+    /// control bits of both children. The whole seed feeds the fixed-key
+    /// hash: the left child's seed is the hash of the seed's first counter
+    /// in `DOMAIN_CHILDREN`, the right child's that of the next counter, and
+    /// the control bits come from the hash of its counter in
+    /// `DOMAIN_CONTROL`.
+    pub fn expand_dir(self: &PrgSeed, left: bool, right: bool) -> PrgOutput {
+        FIXED_KEY_STREAM.with(|s_in| {
+            let s = s_in.borrow();
+            let (bits, y_bits) = control_bits(&s.prg.hash(&self.counter(DOMAIN_CONTROL)));
+
+            let mut out = PrgOutput {
+                bits,
+                y_bits,
+                seeds: (PrgSeed::zero(), PrgSeed::zero()),
+            };
+
+            let ctr = self.counter(DOMAIN_CHILDREN);
+            if left {
+                out.seeds.0.key = s.prg.hash(&ctr);
+            }
+            if right {
+                let mut next = [0u8; AES_BLOCK_SIZE];
+                FixedKeyPrg::store(FixedKeyPrg::inc(FixedKeyPrg::load(&ctr)), &mut next);
+                out.seeds.1.key = s.prg.hash(&next);
+            }
+
+            out
+        })
+    }
+
+    pub fn expand(self: &PrgSeed) -> PrgOutput {
+        self.expand_dir(true, true)
+    }
+
+    /// `seeds[i].expand()` for every `i`, with the three blocks of each
+    /// seed (both children and the control bits) going through the
+    /// pipelined `Prg::hash8`, eight blocks at a time, instead of one block
+    /// at a time.
+    pub fn expand_all(seeds: &[&PrgSeed]) -> Vec<PrgOutput> {
+        FIXED_KEY_STREAM.with(|s_in| {
+            let s = s_in.borrow();
+
+            // The counters of each seed's left child, right child and
+            // control bits; the right child's block is the one after the
+            // left's.
+            let mut ctrs = Vec::with_capacity(3 * seeds.len());
+            for seed in seeds {
+                let left = seed.counter(DOMAIN_CHILDREN);
+                let mut right = [0u8; AES_BLOCK_SIZE];
+                FixedKeyPrg::store(FixedKeyPrg::inc(FixedKeyPrg::load(&left)), &mut right);
+                ctrs.push(left);
+                ctrs.push(right);
+                ctrs.push(seed.counter(DOMAIN_CONTROL));
+            }
+
+            let mut blocks = Vec::with_capacity(ctrs.len());
+            for chunk in ctrs.chunks(8) {
+                let mut ctrs8 = [[0u8; AES_BLOCK_SIZE]; 8];
+                ctrs8[..chunk.len()].copy_from_slice(chunk);
+                blocks.extend_from_slice(&s.prg.hash8(&ctrs8)[..chunk.len()]);
+            }
+
+            blocks
+                .chunks(3)
+                .map(|b| {
+                    let (bits, y_bits) = control_bits(&b[2]);
+                    PrgOutput {
+                        bits,
+                        y_bits,
+                        seeds: (PrgSeed { key: b[0] }, PrgSeed { key: b[1] }),
+                    }
+                })
+                .collect()
+        })
+    }
+
+    pub fn convert<T: FromRng + crate::Group>(self: &PrgSeed) -> ConvertOutput<T> {
+        let mut out = ConvertOutput {
+            seed: PrgSeed::zero(),
+            word: T::zero(),
+        };
+
+        FIXED_KEY_STREAM.with(|s_in| {
+            let mut s = s_in.borrow_mut();
+            s.set_key(&self.counter(DOMAIN_CONVERT));
+            s.fill_bytes(&mut out.seed.key);
+            unsafe {
+                let sp = s_in.as_ptr();
+                out.word.from_rng(&mut *sp);
+            }
+        });
+
+        out
+    }
+
+    pub fn zero() -> PrgSeed {
+        PrgSeed {
+            key: [0; AES_KEY_SIZE],
+        }
+    }
+
+    pub fn random() -> PrgSeed {
+        Self::random_from(&mut thread_rng())
+    }
+
+    pub fn random_from(rng: &mut impl Rng) -> PrgSeed {
+        let mut key: [u8; AES_KEY_SIZE] = [0; AES_KEY_SIZE];
+        rng.fill(&mut key);
+
+        PrgSeed { key }
+    }
+}
+
+impl rand::RngCore for PrgStream {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for v in dest.iter() {
+            debug_assert_eq!(*v, 0u8);
+        }
+
+        self.stream.apply_keystream(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl rand::RngCore for ThreadRng {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        SEEDED_STREAM.with(|s| match s.borrow_mut().as_mut() {
+            Some(stream) => {
+                // `PrgStream` XORs its output into `dest`.
+                dest.iter_mut().for_each(|v| *v = 0);
+                stream.fill_bytes(dest);
+            }
+            None => rand::thread_rng().fill_bytes(dest),
+        })
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl<P: Prg> FixedKeyPrgStream<P> {
+    fn new() -> Self {
+        FixedKeyPrgStream {
+            prg: P::new(),
+            ctr: P::load(&[0; AES_BLOCK_SIZE]),
+            buf: [0; AES_BLOCK_SIZE * 8],
+            buf_ptr: AES_BLOCK_SIZE,
+            have: AES_BLOCK_SIZE,
+            count: 0,
+        }
+    }
+
+    fn set_key(&mut self, key: &[u8; 16]) {
+        self.ctr = P::load(key);
+        self.buf_ptr = AES_BLOCK_SIZE;
+        self.have = AES_BLOCK_SIZE;
+    }
+
+    fn refill(&mut self) {
+        //println!("Refill");
+        debug_assert_eq!(self.buf_ptr, AES_BLOCK_SIZE);
+
+        self.have = AES_BLOCK_SIZE;
+        self.buf_ptr = 0;
+
+        // Compute:   AES_0000(ctr) XOR ctr
+        let mut cnt = [0u8; AES_BLOCK_SIZE];
+        P::store(self.ctr, &mut cnt);
+        self.buf[0..AES_BLOCK_SIZE].copy_from_slice(&self.prg.hash(&cnt));
+
+        self.ctr = P::inc(self.ctr);
+        self.count += AES_BLOCK_SIZE;
+    }
+
+    fn refill8(&mut self) {
+        self.have = 8 * AES_BLOCK_SIZE;
+        self.buf_ptr = 0;
+
+        let mut cnts = [[0u8; AES_BLOCK_SIZE]; 8];
+        for cnt in cnts.iter_mut() {
+            // Write counter into buffer
+            P::store(self.ctr, cnt);
+            self.ctr = P::inc(self.ctr);
+        }
+
+        let blocks = self.prg.hash8(&cnts);
+        for i in 0..8 {
+            self.buf[i * AES_BLOCK_SIZE..(i + 1) * AES_BLOCK_SIZE].copy_from_slice(&blocks[i]);
+        }
+
+        self.count += 8 * AES_BLOCK_SIZE;
+    }
+}
+
+impl<P: Prg> rand::RngCore for FixedKeyPrgStream<P> {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut dest_ptr = 0;
+        while dest_ptr < dest.len() {
+            if self.buf_ptr == self.have {
+                if dest.len() > 4 * AES_BLOCK_SIZE {
+                    self.refill8();
+                //self.refill();
+                } else {
+                    self.refill();
+                }
+            }
+
+            let to_copy = std::cmp::min(self.have - self.buf_ptr, dest.len() - dest_ptr);
+            dest[dest_ptr..dest_ptr + to_copy]
+                .copy_from_slice(&self.buf[self.buf_ptr..self.buf_ptr + to_copy]);
+
+            self.buf_ptr += to_copy;
+            dest_ptr += to_copy;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero() {
+        let zero = PrgSeed::zero();
+        assert_eq!(zero.key.len(), AES_KEY_SIZE);
+        for i in 0..AES_KEY_SIZE {
+            assert_eq!(zero.key[i], 0u8);
+        }
+    }
+
+    #[test]
+    fn xor_zero() {
+        let zero = PrgSeed::zero();
+        let rand = PrgSeed::random();
+        assert_ne!(rand.key, zero.key);
+
+        let out = &zero ^ &rand;
+        assert_eq!(out.key, rand.key);
+
+        let out = &rand ^ &rand;
+        assert_eq!(out.key, zero.key);
+    }
+
+    #[test]
+    fn expand_all_matches_expand() {
+        // Three blocks a seed, so the last batch of eight is partial.
+        let seeds = (0..19).map(|_| PrgSeed::random()).collect::<Vec<_>>();
+        let refs = seeds.iter().collect::<Vec<_>>();
+
+        let batch = PrgSeed::expand_all(&refs);
+        assert_eq!(batch.len(), seeds.len());
+        for (seed, b) in seeds.iter().zip(&batch) {
+            let single = seed.expand();
+            assert_eq!(b.seeds.0.key, single.seeds.0.key);
+            assert_eq!(b.seeds.1.key, single.seeds.1.key);
+            assert_eq!(b.bits, single.bits);
+            assert_eq!(b.y_bits, single.y_bits);
+        }
+    }
+
+    // AES-128 of the zero block under the zero key is 66e94bd4...; the
+    // hash XORs in the counter, which is zero here.
+    fn known_answers<P: Prg>() {
+        let prg = P::new();
+        let zero_hash = [
+            0x66, 0xe9, 0x4b, 0xd4, 0xef, 0x8a, 0x2c, 0x3b, 0x88, 0x4c, 0xfa, 0x59, 0xca, 0x34, 0x2b, 0x2e,
+        ];
+        assert_eq!(prg.hash(&[0; AES_BLOCK_SIZE]), zero_hash);
+
+        // The high half of the counter wraps around without carrying into
+        // the low half.
+        let mut ctr = [0xab; AES_BLOCK_SIZE];
+        ctr[8..].copy_from_slice(&[0xff; 8]);
+        let mut out = [0; AES_BLOCK_SIZE];
+        P::store(P::inc(P::load(&ctr)), &mut out);
+        assert_eq!(out[..8], [0xab; 8]);
+        assert_eq!(out[8..], [0; 8]);
+        ctr[8] = 0x41;
+        P::store(P::inc(P::load(&ctr)), &mut out);
+        assert_eq!(out[8], 0x42);
+
+        let mut ctrs = [[0; AES_BLOCK_SIZE]; 8];
+        for (i, c) in ctrs.iter_mut().enumerate() {
+            c[15] = i as u8;
+        }
+        for (c, h) in ctrs.iter().zip(prg.hash8(&ctrs).iter()) {
+            assert_eq!(prg.hash(c), *h);
+        }
+    }
+
+    #[test]
+    fn soft_prg_known_answers() {
+        known_answers::<SoftPrg>();
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_feature = "aes", target_feature = "sse2"))]
+    #[test]
+    fn aesni_prg_matches_soft_prg() {
+        known_answers::<AesNiPrg>();
+
+        let mut aesni = FixedKeyPrgStream::<AesNiPrg>::new();
+        let mut soft = FixedKeyPrgStream::<SoftPrg>::new();
+        for _ in 0..10 {
+            let key = PrgSeed::random().key;
+            aesni.set_key(&key);
+            soft.set_key(&key);
+            // Long reads go through `refill8`, short ones through `refill`.
+            for &len in &[200, 16, 5] {
+                let (mut a, mut b) = (vec![0; len], vec![0; len]);
+                aesni.fill_bytes(&mut a);
+                soft.fill_bytes(&mut b);
+                assert_eq!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_thread_rng() {
+        let seed = PrgSeed { key: [7; AES_KEY_SIZE] };
+        let draw = || (PrgSeed::random().key, thread_rng().gen::<u64>());
+        let first = with_seed(&seed, draw);
+        assert_eq!(with_seed(&seed, draw), first);
+        // Nested seeds do not leak out of their scope.
+        let nested = with_seed(&seed, || {
+            with_seed(&PrgSeed::zero(), draw);
+            draw()
+        });
+        assert_eq!(nested, first);
+        assert_ne!(draw(), first);
+    }
+
+    #[test]
+    fn expansion_uses_whole_seed() {
+        // Seeds that differ only in the low nibble of their first byte.
+        let mut seed = PrgSeed::random();
+        seed.key[0] &= 0xF0;
+        let mut other = seed.clone();
+        other.key[0] |= 0x01;
+        let (a, b) = (seed.expand(), other.expand());
+        assert_ne!(a.seeds.0.key, b.seeds.0.key);
+        assert_ne!(a.seeds.1.key, b.seeds.1.key);
+
+        // Every combination of control bits comes up.
+        let bits = (0..1000)
+            .map(|_| {
+                let out = PrgSeed::random().expand();
+                (out.bits, out.y_bits)
+            })
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(bits.len(), 16);
+    }
+
+    #[test]
+    fn from_stream() {
+        let rand = PrgSeed::random();
+        let zero = PrgSeed::zero();
+        let out = rand.expand();
+
+        assert_ne!(out.seeds.0.key, zero.key);
+        assert_ne!(out.seeds.1.key, zero.key);
+        assert_ne!(out.seeds.0.key, out.seeds.1.key);
+    }
+}
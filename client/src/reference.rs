@@ -0,0 +1,104 @@
+// Heavy hitters computed in the clear, as a reference for the servers'
+// crawl.
+//
+// Each client holds a box, given by its inclusive (left, right) endpoints
+// on every dimension. Like the crawl, `heavy_hitters` extends the prefix
+// of every dimension by one bit per level, until the dimension runs out
+// of bits, and keeps the nodes whose region meets at least `threshold`
+// clients' boxes. At the last level a node is a single point, so a client
+// counts there if its box contains the point.
+
+use crate::all_bit_vectors;
+
+/// Inclusive (left, right) endpoints of a box on each dimension, as
+/// MSB-first bit strings.
+pub type BoxEndpoints = Vec<(Vec<bool>, Vec<bool>)>;
+
+// Whether the points that start with `path` on every dimension meet the
+// box `b`.
+fn meets(b: &BoxEndpoints, path: &[Vec<bool>]) -> bool {
+    b.iter().zip(path).all(|((left, right), prefix)| {
+        let pad = |bit| {
+            let mut bits = prefix.clone();
+            bits.resize(left.len(), bit);
+            bits
+        };
+        // MSB-first bit strings of equal length compare as integers.
+        pad(false) <= *right && pad(true) >= *left
+    })
+}
+
+/// The nodes of the crawl's last level that meet at least `threshold` of
+/// `boxes`, with the number they meet, in the order the crawl lists them.
+pub fn heavy_hitters(boxes: &[BoxEndpoints], threshold: usize) -> Vec<(Vec<Vec<bool>>, usize)> {
+    assert!(!boxes.is_empty());
+    let dim_lens = boxes[0].iter().map(|(left, _)| left.len()).collect::<Vec<_>>();
+    let depth = *dim_lens.iter().max().unwrap();
+
+    let mut frontier = vec![(vec![vec![]; dim_lens.len()], boxes.len())];
+    for _ in 0..depth {
+        let mut next = vec![];
+        for (path, _) in &frontier {
+            let active = path.iter().zip(&dim_lens).map(|(p, &len)| p.len() < len).collect::<Vec<_>>();
+            let n_active = active.iter().filter(|&&a| a).count();
+            for bits in all_bit_vectors(n_active) {
+                let mut bits = bits.into_iter();
+                let child = path
+                    .iter()
+                    .zip(&active)
+                    .map(|(p, &a)| {
+                        let mut p = p.clone();
+                        if a {
+                            p.push(bits.next().unwrap());
+                        }
+                        p
+                    })
+                    .collect::<Vec<_>>();
+                let count = boxes.iter().filter(|b| meets(b, &child)).count();
+                if count >= threshold {
+                    next.push((child, count));
+                }
+            }
+        }
+        frontier = next;
+    }
+    frontier
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MSB_u32_to_bits;
+
+    #[test]
+    fn matches_brute_force() {
+        // Two dimensions of different lengths.
+        let boxes = [((1u32, 5u32), (0u32, 2u32)), ((4, 6), (1, 3)), ((5, 5), (2, 2)), ((0, 7), (2, 3))]
+            .iter()
+            .map(|&((l0, r0), (l1, r1))| {
+                vec![
+                    (MSB_u32_to_bits(3, l0), MSB_u32_to_bits(3, r0)),
+                    (MSB_u32_to_bits(2, l1), MSB_u32_to_bits(2, r1)),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        for threshold in 1..=4 {
+            let mut expected = vec![];
+            for x in 0..8u32 {
+                for y in 0..4u32 {
+                    let path = vec![MSB_u32_to_bits(3, x), MSB_u32_to_bits(2, y)];
+                    let count = boxes.iter().filter(|b| meets(b, &path)).count();
+                    if count >= threshold {
+                        expected.push((path, count));
+                    }
+                }
+            }
+            let mut got = heavy_hitters(&boxes, threshold);
+            got.sort();
+            assert_eq!(got, expected, "threshold {}", threshold);
+        }
+        // A point in all four boxes.
+        assert_eq!(heavy_hitters(&boxes, 4), vec![(vec![MSB_u32_to_bits(3, 5), MSB_u32_to_bits(2, 2)], 4)]);
+    }
+}
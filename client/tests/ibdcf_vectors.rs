@@ -0,0 +1,117 @@
+// Known-answer tests for ibDCF key generation and evaluation. Each vector
+// fixes the seed of `prg::with_seed`, so key generation is deterministic,
+// and records both keys in the wire format of `ibDCFKey::to_bytes`, which
+// holds the root seeds, every correction word and the shares of `alpha`,
+// along with the XOR of the two servers' outputs at every point of the
+// domain.
+//
+// After an intended change to key generation or to the wire format,
+// regenerate the vectors in `client/` with
+//
+//     cargo test --test ibdcf_vectors -- --ignored --nocapture print_vectors > tests/vectors/ibdcf.json
+//
+// and strip the test harness's lines from the output. Keep the old file's
+// keys under their version's name for `old_keys_are_rejected`.
+
+use counttree_client::ibDCF::ibDCFKey;
+use counttree_client::prg::{with_seed, PrgSeed};
+use counttree_client::MSB_u32_to_bits;
+
+const VECTORS: &str = include_str!("vectors/ibdcf.json");
+// Keys of format version 2, whose seeds expanded differently, and of
+// version 3, which had no shares of `alpha`.
+const OLD_VECTORS: &[(u8, &str)] = &[
+    (2, include_str!("vectors/ibdcf_v2.json")),
+    (3, include_str!("vectors/ibdcf_v3.json")),
+];
+
+// (seed byte, bit length, alpha, side) of each vector.
+const CASES: &[(u8, u8, u32, bool)] = &[
+    (1, 1, 0, false),
+    (2, 1, 1, true),
+    (3, 4, 0, false),
+    (4, 4, 9, true),
+    (5, 4, 15, false),
+    (6, 6, 37, false),
+    (7, 6, 37, true),
+    (8, 8, 200, true),
+];
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &serde_json::Value) -> Vec<u8> {
+    let s = s.as_str().unwrap();
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn outputs(k0: &ibDCFKey, k1: &ibDCFKey, nbits: u8) -> String {
+    (0..1u32 << nbits)
+        .map(|x| {
+            let bits = MSB_u32_to_bits(nbits, x);
+            if k0.eval_ibDCF(&bits) ^ k1.eval_ibDCF(&bits) { '1' } else { '0' }
+        })
+        .collect()
+}
+
+fn vector(&(seed, nbits, alpha, side): &(u8, u8, u32, bool)) -> serde_json::Value {
+    let seed = PrgSeed { key: [seed; 16] };
+    let alpha_bits = MSB_u32_to_bits(nbits, alpha);
+    let (k0, k1) = with_seed(&seed, || ibDCFKey::gen_ibDCF(&alpha_bits, side));
+    serde_json::json!({
+        "seed": to_hex(&seed.key),
+        "alpha": alpha_bits.iter().map(|&b| if b { '1' } else { '0' }).collect::<String>(),
+        "side": side,
+        "key0": to_hex(&k0.to_bytes()),
+        "key1": to_hex(&k1.to_bytes()),
+        "outputs": outputs(&k0, &k1, nbits),
+    })
+}
+
+#[test]
+fn known_answers() {
+    let expected: Vec<serde_json::Value> = serde_json::from_str(VECTORS).unwrap();
+    assert_eq!(expected.len(), CASES.len());
+    for (case, expected) in CASES.iter().zip(&expected) {
+        assert_eq!(&vector(case), expected, "case {:?}", case);
+    }
+}
+
+#[test]
+fn known_keys_decode() {
+    let expected: Vec<serde_json::Value> = serde_json::from_str(VECTORS).unwrap();
+    for (&(_, nbits, _, _), v) in CASES.iter().zip(&expected) {
+        let k0 = ibDCFKey::from_bytes(&from_hex(&v["key0"])).unwrap();
+        let k1 = ibDCFKey::from_bytes(&from_hex(&v["key1"])).unwrap();
+        assert_eq!(outputs(&k0, &k1, nbits), v["outputs"].as_str().unwrap());
+    }
+}
+
+// Keys from before the current format version must not be evaluated,
+// whether they come as bytes or through serde, as over RPC.
+#[test]
+fn old_keys_are_rejected() {
+    for &(version, vectors) in OLD_VECTORS {
+        let old: Vec<serde_json::Value> = serde_json::from_str(vectors).unwrap();
+        assert_eq!(old.len(), CASES.len());
+        for v in &old {
+            for key in &[&v["key0"], &v["key1"]] {
+                let bytes = from_hex(key);
+                assert_eq!(bytes[0], version);
+                assert!(ibDCFKey::from_bytes(&bytes).is_err());
+                assert!(bincode::deserialize::<ibDCFKey>(&bincode::serialize(&bytes).unwrap()).is_err());
+            }
+        }
+    }
+}
+
+#[test]
+#[ignore]
+fn print_vectors() {
+    let vectors = CASES.iter().map(vector).collect::<Vec<_>>();
+    println!("{}", serde_json::to_string_pretty(&vectors).unwrap());
+}
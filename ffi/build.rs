@@ -0,0 +1,20 @@
+// Generates the C header from the `extern "C"` items in src/lib.rs into
+// OUT_DIR, where the `header_is_up_to_date` test compares it with the
+// checked-in include/counttree.h, which C builds use without running
+// cargo. Building with COUNTTREE_UPDATE_HEADER=1 also rewrites that file;
+// no other build touches it.
+
+fn main() {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=COUNTTREE_UPDATE_HEADER");
+
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).unwrap();
+    let header = cbindgen::generate_with_config(&dir, config).expect("Unable to generate the C header");
+    header.write_to_file(format!("{}/counttree.h", out_dir));
+    if std::env::var_os("COUNTTREE_UPDATE_HEADER").is_some() {
+        header.write_to_file(format!("{}/include/counttree.h", dir));
+    }
+}
@@ -0,0 +1,79 @@
+#ifndef COUNTTREE_H
+#define COUNTTREE_H
+
+/* Generated by cbindgen from ffi/src/lib.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The result of every call.
+typedef enum {
+  // Success: both shares were written.
+  COUNTTREE_STATUS_OK = 0,
+  // A pointer argument was null.
+  COUNTTREE_STATUS_NULL_POINTER = 1,
+  // An argument was out of range, e.g., a coordinate or a radius that
+  // does not fit in `data_len` bits.
+  COUNTTREE_STATUS_INVALID_ARGUMENT = 2,
+  // Key generation failed unexpectedly. This is a bug in the library.
+  COUNTTREE_STATUS_INTERNAL = 3,
+} CounttreeStatus;
+
+// A byte buffer allocated by this library. An empty buffer has a null
+// `data` and a zero `len`.
+typedef struct {
+  uint8_t *data;
+  size_t len;
+} CounttreeBuffer;
+
+// Key shares for the L-infinity ball of radius `radius` around `point`,
+// which has `n_dims` coordinates of `data_len` bits each, with `data_len`
+// between 1 and 32. The radius, like the coordinates, must fit in
+// `data_len` bits.
+//
+// # Safety
+//
+// `point` must point to `n_dims` values, and `share0` and `share1` must
+// be valid for writes.
+CounttreeStatus counttree_gen_l_inf_ball(const uint32_t *point,
+                                         size_t n_dims,
+                                         uint8_t data_len,
+                                         uint32_t radius,
+                                         CounttreeBuffer *share0,
+                                         CounttreeBuffer *share1);
+
+// Key shares for the box around the point (`lat`, `lon`), in hundredths
+// of a degree, that extends `lat_size` and `lon_size` hundredths of a
+// degree on each side. Latitudes lie in [-9000, 9000], longitudes in
+// [-18000, 18000], and the sizes must not be negative.
+//
+// # Safety
+//
+// `share0` and `share1` must be valid for writes.
+CounttreeStatus counttree_gen_l_inf_ball_from_coords(int16_t lat,
+                                                     int16_t lon,
+                                                     int16_t lat_size,
+                                                     int16_t lon_size,
+                                                     CounttreeBuffer *share0,
+                                                     CounttreeBuffer *share1);
+
+// Frees a buffer written by this library and empties it. Freeing an
+// empty buffer, or a null pointer, does nothing.
+//
+// # Safety
+//
+// `buf` must be null or point to a buffer written by this library, or to
+// an empty one.
+void counttree_buffer_free(CounttreeBuffer *buf);
+
+// A static, NUL-terminated description of `status`.
+const char *counttree_status_message(CounttreeStatus status);
+
+// The version of the key wire format in the shares, which the servers
+// must support.
+uint8_t counttree_key_format_version(void);
+
+#endif /* COUNTTREE_H */
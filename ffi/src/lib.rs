@@ -0,0 +1,179 @@
+// C bindings for client-side key generation; the header is
+// include/counttree.h.
+//
+// Each generator encodes a client's point, makes the keys of the ball of
+// the given radius around it, and returns one opaque serialized key share
+// per server. A share is the bincode encoding of the client's
+// `Vec<Vec<IntervalKey>>` for that server, i.e., the payload of a
+// `ClientKey::Box`, with every key in the wire format of
+// `ibDCFKey::to_bytes`. The caller owns both shares and releases them with
+// `counttree_buffer_free`.
+
+use std::os::raw::c_char;
+use std::panic;
+use std::ptr;
+
+use counttree_client::{ibDCFKey, to_union, IntervalKey, MSB_u32_to_bits, KEY_FORMAT_VERSION};
+
+/// The result of every call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CounttreeStatus {
+    /// Success: both shares were written.
+    Ok = 0,
+    /// A pointer argument was null.
+    NullPointer = 1,
+    /// An argument was out of range, e.g., a coordinate or a radius that
+    /// does not fit in `data_len` bits.
+    InvalidArgument = 2,
+    /// Key generation failed unexpectedly. This is a bug in the library.
+    Internal = 3,
+}
+
+/// A byte buffer allocated by this library. An empty buffer has a null
+/// `data` and a zero `len`.
+#[repr(C)]
+pub struct CounttreeBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl CounttreeBuffer {
+    fn empty() -> CounttreeBuffer {
+        CounttreeBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn from_vec(bytes: Vec<u8>) -> CounttreeBuffer {
+        let len = bytes.len();
+        let data = Box::into_raw(bytes.into_boxed_slice()) as *mut u8;
+        CounttreeBuffer { data, len }
+    }
+}
+
+// Runs `gen`, which returns each server's keys, and writes their
+// serializations to the outputs. On any failure, the outputs are empty.
+unsafe fn write_shares<F>(share0: *mut CounttreeBuffer, share1: *mut CounttreeBuffer, gen: F) -> CounttreeStatus
+where
+    F: FnOnce() -> Result<(Vec<Vec<IntervalKey>>, Vec<Vec<IntervalKey>>), CounttreeStatus> + panic::UnwindSafe,
+{
+    if share0.is_null() || share1.is_null() {
+        return CounttreeStatus::NullPointer;
+    }
+    *share0 = CounttreeBuffer::empty();
+    *share1 = CounttreeBuffer::empty();
+
+    let shares = panic::catch_unwind(|| {
+        let (keys0, keys1) = gen()?;
+        let bytes0 = bincode::serialize(&keys0).map_err(|_| CounttreeStatus::Internal)?;
+        let bytes1 = bincode::serialize(&keys1).map_err(|_| CounttreeStatus::Internal)?;
+        Ok((bytes0, bytes1))
+    });
+    match shares {
+        Ok(Ok((bytes0, bytes1))) => {
+            *share0 = CounttreeBuffer::from_vec(bytes0);
+            *share1 = CounttreeBuffer::from_vec(bytes1);
+            CounttreeStatus::Ok
+        }
+        Ok(Err(status)) => status,
+        Err(_) => CounttreeStatus::Internal,
+    }
+}
+
+/// Key shares for the L-infinity ball of radius `radius` around `point`,
+/// which has `n_dims` coordinates of `data_len` bits each, with `data_len`
+/// between 1 and 32. The radius, like the coordinates, must fit in
+/// `data_len` bits.
+///
+/// # Safety
+///
+/// `point` must point to `n_dims` values, and `share0` and `share1` must
+/// be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn counttree_gen_l_inf_ball(
+    point: *const u32,
+    n_dims: usize,
+    data_len: u8,
+    radius: u32,
+    share0: *mut CounttreeBuffer,
+    share1: *mut CounttreeBuffer,
+) -> CounttreeStatus {
+    if point.is_null() {
+        return CounttreeStatus::NullPointer;
+    }
+    let point = std::slice::from_raw_parts(point, n_dims).to_vec();
+    write_shares(share0, share1, move || {
+        if n_dims == 0 || data_len == 0 || data_len > 32 {
+            return Err(CounttreeStatus::InvalidArgument);
+        }
+        if data_len < 32 && (point.iter().any(|&x| x >> data_len != 0) || radius >> data_len != 0) {
+            return Err(CounttreeStatus::InvalidArgument);
+        }
+        let alpha = point.iter().map(|&x| MSB_u32_to_bits(data_len, x)).collect();
+        let (keys0, keys1) = ibDCFKey::gen_l_inf_ball(alpha, radius);
+        Ok((to_union(keys0), to_union(keys1)))
+    })
+}
+
+/// Key shares for the box around the point (`lat`, `lon`), in hundredths
+/// of a degree, that extends `lat_size` and `lon_size` hundredths of a
+/// degree on each side. Latitudes lie in [-9000, 9000], longitudes in
+/// [-18000, 18000], and the sizes must not be negative.
+///
+/// # Safety
+///
+/// `share0` and `share1` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn counttree_gen_l_inf_ball_from_coords(
+    lat: i16,
+    lon: i16,
+    lat_size: i16,
+    lon_size: i16,
+    share0: *mut CounttreeBuffer,
+    share1: *mut CounttreeBuffer,
+) -> CounttreeStatus {
+    write_shares(share0, share1, move || {
+        if !(-9000..=9000).contains(&lat) || !(-18000..=18000).contains(&lon) || lat_size < 0 || lon_size < 0 {
+            return Err(CounttreeStatus::InvalidArgument);
+        }
+        let (keys0, keys1) = ibDCFKey::gen_l_inf_ball_from_coords((lat, lon), (lat_size, lon_size));
+        Ok((to_union(keys0), to_union(keys1)))
+    })
+}
+
+/// Frees a buffer written by this library and empties it. Freeing an
+/// empty buffer, or a null pointer, does nothing.
+///
+/// # Safety
+///
+/// `buf` must be null or point to a buffer written by this library, or to
+/// an empty one.
+#[no_mangle]
+pub unsafe extern "C" fn counttree_buffer_free(buf: *mut CounttreeBuffer) {
+    if buf.is_null() || (*buf).data.is_null() {
+        return;
+    }
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut((*buf).data, (*buf).len)));
+    *buf = CounttreeBuffer::empty();
+}
+
+/// A static, NUL-terminated description of `status`.
+#[no_mangle]
+pub extern "C" fn counttree_status_message(status: CounttreeStatus) -> *const c_char {
+    let message: &'static [u8] = match status {
+        CounttreeStatus::Ok => b"ok\0",
+        CounttreeStatus::NullPointer => b"null pointer argument\0",
+        CounttreeStatus::InvalidArgument => b"argument out of range\0",
+        CounttreeStatus::Internal => b"internal error\0",
+    };
+    message.as_ptr() as *const c_char
+}
+
+/// The version of the key wire format in the shares, which the servers
+/// must support.
+#[no_mangle]
+pub extern "C" fn counttree_key_format_version() -> u8 {
+    KEY_FORMAT_VERSION
+}
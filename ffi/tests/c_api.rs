@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use counttree_client::IntervalKey;
+use counttree_ffi::*;
+
+// Builds the static library, which `cargo test` does not, into its own
+// target directory next to the test binaries, so the nested build does
+// not wait on the lock of the one running the tests. Returns that
+// directory.
+fn build_static_lib() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let dir = exe.parent().unwrap().parent().unwrap().join("c_api");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--lib", "--manifest-path"])
+        .arg(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success());
+    dir.join("debug")
+}
+
+#[test]
+fn c_program() {
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = build_static_lib();
+    let lib = out_dir.join("libcounttree_ffi.a");
+    assert!(lib.exists(), "no static library at {:?}", lib);
+    let exe = out_dir.join("c_api_test");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(manifest.join("tests/c_api_test.c"))
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .arg(&lib)
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&exe)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success());
+
+    let out = Command::new(&exe).output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "ok\n");
+}
+
+// Whether the keys put `point` in the box: it is on neither side of any
+// boundary pair.
+fn contains(keys0: &[Vec<IntervalKey>], keys1: &[Vec<IntervalKey>], point: &[Vec<bool>]) -> bool {
+    keys0.iter().zip(keys1).zip(point).all(|((d0, d1), x)| {
+        d0.iter()
+            .zip(d1)
+            .all(|((l0, r0), (l1, r1))| !(l0.eval_ibDCF(x) ^ l1.eval_ibDCF(x)) && !(r0.eval_ibDCF(x) ^ r1.eval_ibDCF(x)))
+    })
+}
+
+#[test]
+fn shares_decode_to_the_ball() {
+    let point = [1000u32, 70000];
+    let (mut share0, mut share1) = (
+        CounttreeBuffer { data: std::ptr::null_mut(), len: 0 },
+        CounttreeBuffer { data: std::ptr::null_mut(), len: 0 },
+    );
+    let status = unsafe { counttree_gen_l_inf_ball(point.as_ptr(), 2, 20, 16, &mut share0, &mut share1) };
+    assert_eq!(status, CounttreeStatus::Ok);
+
+    let decode = |share: &CounttreeBuffer| -> Vec<Vec<IntervalKey>> {
+        let bytes = unsafe { std::slice::from_raw_parts(share.data, share.len) };
+        bincode::deserialize(bytes).unwrap()
+    };
+    let (keys0, keys1) = (decode(&share0), decode(&share1));
+    unsafe {
+        counttree_buffer_free(&mut share0);
+        counttree_buffer_free(&mut share1);
+    }
+
+    let at = |x: u32, y: u32| vec![counttree_client::MSB_u32_to_bits(20, x), counttree_client::MSB_u32_to_bits(20, y)];
+    assert!(contains(&keys0, &keys1, &at(1000, 70000)));
+    assert!(contains(&keys0, &keys1, &at(984, 70016)));
+    assert!(!contains(&keys0, &keys1, &at(983, 70000)));
+    assert!(!contains(&keys0, &keys1, &at(1000, 70017)));
+}
+
+#[test]
+fn radius_must_fit_in_the_domain() {
+    let point = [3u32, 9];
+    let (mut share0, mut share1) = (
+        CounttreeBuffer { data: std::ptr::null_mut(), len: 0 },
+        CounttreeBuffer { data: std::ptr::null_mut(), len: 0 },
+    );
+    let status = unsafe { counttree_gen_l_inf_ball(point.as_ptr(), 2, 4, 16, &mut share0, &mut share1) };
+    assert_eq!(status, CounttreeStatus::InvalidArgument);
+    assert!(share0.data.is_null() && share1.data.is_null());
+
+    let status = unsafe { counttree_gen_l_inf_ball(point.as_ptr(), 2, 4, 15, &mut share0, &mut share1) };
+    assert_eq!(status, CounttreeStatus::Ok);
+    unsafe {
+        counttree_buffer_free(&mut share0);
+        counttree_buffer_free(&mut share1);
+    }
+}
+
+// The checked-in header must be the one that cbindgen generates from
+// src/lib.rs. After a change to the C API, update it with
+//
+//     COUNTTREE_UPDATE_HEADER=1 cargo build
+#[test]
+fn header_is_up_to_date() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/counttree.h"));
+    let checked_in = std::fs::read_to_string(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("include/counttree.h")).unwrap();
+    assert!(
+        generated == checked_in,
+        "include/counttree.h differs from the generated header; run COUNTTREE_UPDATE_HEADER=1 cargo build"
+    );
+}
@@ -0,0 +1,71 @@
+// Exercises the C API the way a C client would; run by tests/c_api.rs.
+// Exits with a nonzero status, after printing the failed check, if any
+// check fails.
+
+#include <stdio.h>
+#include <string.h>
+
+#include "counttree.h"
+
+#define CHECK(cond)                                                  \
+  do {                                                               \
+    if (!(cond)) {                                                   \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,         \
+              __LINE__, #cond);                                      \
+      return 1;                                                      \
+    }                                                                \
+  } while (0)
+
+static int check_shares(CounttreeBuffer *share0, CounttreeBuffer *share1) {
+  CHECK(share0->data != NULL && share0->len > 0);
+  CHECK(share1->data != NULL && share1->len > 0);
+  CHECK(share0->len == share1->len);
+  CHECK(memcmp(share0->data, share1->data, share0->len) != 0);
+  counttree_buffer_free(share0);
+  counttree_buffer_free(share1);
+  CHECK(share0->data == NULL && share0->len == 0);
+  // Freeing twice is harmless.
+  counttree_buffer_free(share0);
+  counttree_buffer_free(NULL);
+  return 0;
+}
+
+int main(void) {
+  CounttreeBuffer share0, share1;
+  uint32_t point[2] = {1000, 70000};
+
+  CHECK(counttree_key_format_version() > 0);
+
+  CHECK(counttree_gen_l_inf_ball(point, 2, 20, 16, &share0, &share1) ==
+        COUNTTREE_STATUS_OK);
+  if (check_shares(&share0, &share1)) return 1;
+
+  CHECK(counttree_gen_l_inf_ball_from_coords(4236, -7106, 50, 50, &share0,
+                                             &share1) == COUNTTREE_STATUS_OK);
+  if (check_shares(&share0, &share1)) return 1;
+
+  // Errors leave both outputs empty.
+  CHECK(counttree_gen_l_inf_ball(point, 2, 16, 16, &share0, &share1) ==
+        COUNTTREE_STATUS_INVALID_ARGUMENT);
+  CHECK(share0.data == NULL && share1.data == NULL);
+  CHECK(counttree_gen_l_inf_ball(point, 2, 0, 16, &share0, &share1) ==
+        COUNTTREE_STATUS_INVALID_ARGUMENT);
+  CHECK(counttree_gen_l_inf_ball(point, 0, 20, 16, &share0, &share1) ==
+        COUNTTREE_STATUS_INVALID_ARGUMENT);
+  CHECK(counttree_gen_l_inf_ball(NULL, 2, 20, 16, &share0, &share1) ==
+        COUNTTREE_STATUS_NULL_POINTER);
+  CHECK(counttree_gen_l_inf_ball(point, 2, 20, 16, &share0, NULL) ==
+        COUNTTREE_STATUS_NULL_POINTER);
+  CHECK(counttree_gen_l_inf_ball_from_coords(9001, 0, 10, 10, &share0,
+                                             &share1) ==
+        COUNTTREE_STATUS_INVALID_ARGUMENT);
+  CHECK(counttree_gen_l_inf_ball_from_coords(0, 0, -1, 10, &share0,
+                                             &share1) ==
+        COUNTTREE_STATUS_INVALID_ARGUMENT);
+
+  CHECK(strcmp(counttree_status_message(COUNTTREE_STATUS_OK), "ok") == 0);
+  CHECK(strlen(counttree_status_message(COUNTTREE_STATUS_INTERNAL)) > 0);
+
+  printf("ok\n");
+  return 0;
+}
@@ -0,0 +1,212 @@
+// Python bindings for driving experiments from notebooks: generating a
+// client's key shares from coordinates, decoding the paths of the
+// servers' results into boxes of latitude and longitude, and computing
+// the heavy hitters in the clear for comparison.
+//
+// Coordinates are in degrees, and are rounded to the whole centidegrees
+// that the keys encode. The key shares are in the format of the C API's:
+// the bincode encoding of each server's `Vec<Vec<IntervalKey>>`.
+
+use std::convert::TryInto;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use counttree_client::{coords_box, ibDCFKey, to_union, with_seed, Encoder, IntervalKey, OffsetBinary, PrgSeed};
+use counttree_client::encoding::{from_centidegrees, to_centidegrees};
+use counttree_client::reference;
+
+type Path = Vec<Vec<bool>>;
+
+// The point and the sizes of its box, in centidegrees.
+fn centidegrees(lat: f64, lon: f64, lat_size: f64, lon_size: f64) -> PyResult<((i16, i16), (i16, i16))> {
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(PyValueError::new_err("coordinates out of range"));
+    }
+    if !(0.0..=180.0).contains(&lat_size) || !(0.0..=180.0).contains(&lon_size) {
+        return Err(PyValueError::new_err("box sizes must be between 0 and 180 degrees"));
+    }
+    Ok((
+        (to_centidegrees(lat), to_centidegrees(lon)),
+        (to_centidegrees(lat_size), to_centidegrees(lon_size)),
+    ))
+}
+
+/// gen_keys(lat, lon, lat_size, lon_size, wrap=False, seed=None)
+///
+/// The two servers' key shares, as bytes, for the box that extends
+/// `lat_size` and `lon_size` degrees on either side of (`lat`, `lon`).
+/// With `wrap`, longitude wraps around at ±180° instead of being clamped.
+/// A 16-byte `seed` makes the keys the same on every call.
+#[pyfunction]
+#[pyo3(signature = (lat, lon, lat_size, lon_size, wrap = false, seed = None))]
+fn gen_keys(
+    py: Python,
+    lat: f64,
+    lon: f64,
+    lat_size: f64,
+    lon_size: f64,
+    wrap: bool,
+    seed: Option<&[u8]>,
+) -> PyResult<(PyObject, PyObject)> {
+    let (coords, sizes) = centidegrees(lat, lon, lat_size, lon_size)?;
+    if wrap && sizes.1 >= 18000 {
+        return Err(PyValueError::new_err("a wrapping box must not cover every longitude"));
+    }
+
+    let gen = || -> (Vec<Vec<IntervalKey>>, Vec<Vec<IntervalKey>>) {
+        if wrap {
+            ibDCFKey::gen_l_inf_ball_from_coords_wrapping(coords, sizes)
+        } else {
+            let (keys0, keys1) = ibDCFKey::gen_l_inf_ball_from_coords(coords, sizes);
+            (to_union(keys0), to_union(keys1))
+        }
+    };
+    let (keys0, keys1) = match seed {
+        Some(seed) => {
+            let key = seed.try_into().map_err(|_| PyValueError::new_err("the seed must be 16 bytes"))?;
+            with_seed(&PrgSeed { key }, gen)
+        }
+        None => gen(),
+    };
+
+    let encode = |keys: &Vec<Vec<IntervalKey>>| -> PyResult<PyObject> {
+        let bytes = bincode::serialize(keys).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyBytes::new(py, &bytes).into())
+    };
+    Ok((encode(&keys0)?, encode(&keys1)?))
+}
+
+/// decode_box(path) -> ((lat_min, lat_max), (lon_min, lon_max))
+///
+/// The box, in degrees, of the points under a node of the crawl, given by
+/// its path: the (latitude, longitude) prefixes of a result.
+#[pyfunction]
+fn decode_box(path: Path) -> PyResult<((f64, f64), (f64, f64))> {
+    if path.len() != 2 || path.iter().any(|p| p.len() > OffsetBinary.bit_len()) {
+        return Err(PyValueError::new_err("expected a latitude and a longitude prefix of at most 16 bits"));
+    }
+    let extent = |prefix: &[bool]| {
+        let (lo, hi) = OffsetBinary.decode_prefix(prefix);
+        (from_centidegrees(lo), from_centidegrees(hi))
+    };
+    Ok((extent(&path[0]), extent(&path[1])))
+}
+
+/// plaintext_heavy_hitters(points, lat_size, lon_size, threshold) -> [(path, count)]
+///
+/// What the servers output for clients at `points`, a list of (lat, lon)
+/// pairs, with the boxes of `gen_keys` without `wrap`: the paths of the
+/// points that at least `threshold` boxes contain, with their counts.
+#[pyfunction]
+fn plaintext_heavy_hitters(points: Vec<(f64, f64)>, lat_size: f64, lon_size: f64, threshold: usize) -> PyResult<Vec<(Path, usize)>> {
+    if points.is_empty() {
+        return Ok(vec![]);
+    }
+    let boxes = points
+        .iter()
+        .map(|&(lat, lon)| {
+            let (coords, sizes) = centidegrees(lat, lon, lat_size, lon_size)?;
+            Ok(coords_box(coords, sizes))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(reference::heavy_hitters(&boxes, threshold.max(1)))
+}
+
+#[pymodule]
+#[pyo3(name = "counttree")]
+fn counttree_module(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(gen_keys, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_box, m)?)?;
+    m.add_function(wrap_pyfunction!(plaintext_heavy_hitters, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both servers' shares from `gen_keys`, without `wrap`.
+    fn shares(lat: f64, lon: f64, lat_size: f64, lon_size: f64, seed: Option<&[u8]>) -> (Vec<u8>, Vec<u8>) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let (share0, share1) = gen_keys(py, lat, lon, lat_size, lon_size, false, seed).unwrap();
+            (share0.extract(py).unwrap(), share1.extract(py).unwrap())
+        })
+    }
+
+    // Whether the shares put `path` in the box: it is on neither side of
+    // the boundary pair of any dimension.
+    fn contains(share0: &[u8], share1: &[u8], path: &[Vec<bool>]) -> bool {
+        let keys0: Vec<Vec<IntervalKey>> = bincode::deserialize(share0).unwrap();
+        let keys1: Vec<Vec<IntervalKey>> = bincode::deserialize(share1).unwrap();
+        keys0.iter().zip(&keys1).zip(path).all(|((d0, d1), x)| {
+            d0.iter()
+                .zip(d1)
+                .all(|((l0, r0), (l1, r1))| !(l0.eval_ibDCF(x) ^ l1.eval_ibDCF(x)) && !(r0.eval_ibDCF(x) ^ r1.eval_ibDCF(x)))
+        })
+    }
+
+    fn path(lat: f64, lon: f64) -> Path {
+        vec![OffsetBinary.encode(to_centidegrees(lat)), OffsetBinary.encode(to_centidegrees(lon))]
+    }
+
+    #[test]
+    fn keys_decode_to_their_box() {
+        let (lat, lon) = (42.36, -71.06);
+        let seed = [7u8; 16];
+        let (share0, share1) = shares(lat, lon, 0.05, 0.1, Some(&seed));
+        assert_eq!(shares(lat, lon, 0.05, 0.1, Some(&seed)), (share0.clone(), share1.clone()));
+
+        // The corners of the box are in it, and the points just past them
+        // are not.
+        for &(dlat, dlon, inside) in &[
+            (0.0, 0.0, true),
+            (0.05, 0.1, true),
+            (-0.05, -0.1, true),
+            (0.06, 0.0, false),
+            (0.0, -0.11, false),
+        ] {
+            assert_eq!(contains(&share0, &share1, &path(lat + dlat, lon + dlon)), inside);
+        }
+
+        // The center's path decodes back to it, and each of its prefixes
+        // to a box around it.
+        let center = path(lat, lon);
+        assert_eq!(decode_box(center.clone()).unwrap(), ((lat, lat), (lon, lon)));
+        for len in 0..=16 {
+            let prefix = center.iter().map(|p| p[..len].to_vec()).collect();
+            let ((lat_min, lat_max), (lon_min, lon_max)) = decode_box(prefix).unwrap();
+            assert!(lat_min <= lat && lat <= lat_max);
+            assert!(lon_min <= lon && lon <= lon_max);
+        }
+        assert!(decode_box(vec![center[0].clone()]).is_err());
+    }
+
+    #[test]
+    fn plaintext_heavy_hitters_match_the_keys() {
+        let points = [(42.36, -71.06), (42.37, -71.06), (42.37, -71.05), (10.0, 20.0)];
+        let (lat_size, lon_size) = (0.01, 0.02);
+        let found = plaintext_heavy_hitters(points.to_vec(), lat_size, lon_size, 2).unwrap();
+        assert!(!found.is_empty());
+
+        let sizes = (to_centidegrees(lat_size), to_centidegrees(lon_size));
+        let boxes = points
+            .iter()
+            .map(|&(lat, lon)| coords_box((to_centidegrees(lat), to_centidegrees(lon)), sizes))
+            .collect::<Vec<_>>();
+        assert_eq!(found, reference::heavy_hitters(&boxes, 2));
+
+        // Each count is the number of clients whose keys hold the point.
+        let keys = points
+            .iter()
+            .map(|&(lat, lon)| shares(lat, lon, lat_size, lon_size, None))
+            .collect::<Vec<_>>();
+        for (path, count) in &found {
+            assert_eq!(keys.iter().filter(|(share0, share1)| contains(share0, share1, path)).count(), *count);
+        }
+
+        assert!(plaintext_heavy_hitters(vec![], lat_size, lon_size, 1).unwrap().is_empty());
+    }
+}
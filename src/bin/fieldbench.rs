@@ -0,0 +1,75 @@
+use csv::Writer;
+use std::io;
+use std::time::Instant;
+
+use num_bigint::{BigUint, RandBigInt};
+use counttree::{FieldElm, Group};
+
+// Time for `rounds` passes of `op` over every pair of `xs` and `ys`, in
+// seconds. Each pass folds into an accumulator, as the servers do when they
+// sum shares, so the work can't be optimized away.
+fn time_op<T: Clone>(xs: &[T], ys: &[T], rounds: usize, op: impl Fn(&mut T, &T, &T)) -> (f64, T) {
+    let mut acc = xs[0].clone();
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for (x, y) in xs.iter().zip(ys) {
+            op(&mut acc, x, y);
+        }
+    }
+    (start.elapsed().as_secs_f64(), acc)
+}
+
+fn main() -> io::Result<()> {
+    let mut rng = rand::thread_rng();
+    let modulus = (BigUint::from(1u32) << 255) - 19u32;
+
+    let num_elms = 1000;
+    let rounds = 1000;
+    let big_xs = (0..num_elms).map(|_| rng.gen_biguint_below(&modulus)).collect::<Vec<_>>();
+    let big_ys = (0..num_elms).map(|_| rng.gen_biguint_below(&modulus)).collect::<Vec<_>>();
+    let xs = big_xs.iter().cloned().map(FieldElm::from).collect::<Vec<_>>();
+    let ys = big_ys.iter().cloned().map(FieldElm::from).collect::<Vec<_>>();
+
+    let mut wtr = Writer::from_path("src/bin/benchmarks/fieldbench.csv")?;
+    wtr.write_record(["op", "number_ops", "biguint_time", "fixed_time", "speedup"])?;
+
+    let num_ops = num_elms * rounds;
+    let mut record = |op: &str, big: (f64, BigUint), fixed: (f64, FieldElm)| -> io::Result<()> {
+        assert_eq!(FieldElm::from(big.1), fixed.1);
+        println!("{}: BigUint {:.3}s, fixed {:.3}s", op, big.0, fixed.0);
+        wtr.write_record(&[op.to_string(), num_ops.to_string(), big.0.to_string(), fixed.0.to_string(), (big.0 / fixed.0).to_string()])?;
+        Ok(())
+    };
+
+    record(
+        "add",
+        time_op(&big_xs, &big_ys, rounds, |acc, x, _| {
+            *acc += x;
+            *acc %= &modulus;
+        }),
+        time_op(&xs, &ys, rounds, |acc, x, _| acc.add(x)),
+    )?;
+    record(
+        "sub",
+        time_op(&big_xs, &big_ys, rounds, |acc, x, _| {
+            *acc += &modulus - x;
+            *acc %= &modulus;
+        }),
+        time_op(&xs, &ys, rounds, |acc, x, _| acc.sub(x)),
+    )?;
+    record(
+        "mul_add",
+        time_op(&big_xs, &big_ys, rounds, |acc, x, y| {
+            *acc += x * y;
+            *acc %= &modulus;
+        }),
+        time_op(&xs, &ys, rounds, |acc, x, y| {
+            let mut prod = x.clone();
+            prod.mul(y);
+            acc.add(&prod);
+        }),
+    )?;
+    wtr.flush()?;
+
+    Ok(())
+}
@@ -7,9 +7,15 @@ use rayon::prelude::*;
 use rand::distributions::Alphanumeric;
 
 use std::time::{Duration, SystemTime};
+use counttree::collect::{ClientKey, KeyCollection, KeyKind, KeyLayout};
 use counttree::config::Config;
-use counttree::ibDCF::ibDCFKey;
-use counttree::string_to_bits;
+use counttree::fastfield::FE;
+use counttree::ibDCF::{eval_str, ibDCFKey, to_union, ArithDCFKey};
+use counttree::prg::PrgSeed;
+use counttree::{string_to_bits, FieldElm, MSB_u32_to_bits};
+use scuttlebutt::SyncChannel;
+use std::io::{BufReader, BufWriter};
+use std::net::{TcpListener, TcpStream};
 
 
 fn sample_string(len: usize) -> String {
@@ -48,6 +54,135 @@ fn generate_ibDCF_keys(string_length : usize, num_keys : usize) -> (Vec<ibDCFKey
 //         .unzip()
 // }
 
+// Time of each level of a crawl over `num_keys` clients with two 16-bit
+// dimensions that keeps the first `width` nodes of each level: that of the
+// crawl's own evaluation of the keys (`KeyCollection::tree_expand`), and
+// that of as many calls to `eval_str`, one per client and child, as the
+// crawl made before it batched and shared the expansions.
+fn crawl_levels(num_keys: usize, width: usize) -> Vec<(usize, usize, f64, f64)> {
+    let nbits = 16;
+    let mut rng = rand::thread_rng();
+    let keys = (0..num_keys)
+        .map(|_| {
+            let alpha = vec![MSB_u32_to_bits(16, rng.gen::<u16>().into()), MSB_u32_to_bits(16, rng.gen::<u16>().into())];
+            to_union(ibDCFKey::gen_l_inf_ball(alpha, 5).0)
+        })
+        .collect::<Vec<_>>();
+    let layout = KeyLayout {
+        kind: KeyKind::Box,
+        dim_lens: vec![nbits, nbits],
+        pairs_per_dim: vec![1, 1],
+        max_weight: 1,
+        n_attributes: 0,
+    };
+    let mut coll = KeyCollection::<FE, FieldElm>::new(&PrgSeed::random(), nbits, layout);
+    for k in &keys {
+        coll.add_key(ClientKey::Box(k.clone()), None, vec![]);
+    }
+    coll.tree_init();
+
+    // Each client's states along one path, for `eval_str`.
+    let mut states = keys
+        .iter()
+        .map(|k| {
+            k.iter()
+                .map(|d| d.iter().map(|(l, r)| (l.eval_init(), r.eval_init())).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let children = [vec![false, false], vec![false, true], vec![true, false], vec![true, true]];
+
+    let mut out = vec![];
+    for level in 0..nbits - 1 {
+        let start = Instant::now();
+        let n_nodes = coll.tree_expand();
+        let crawl_time = start.elapsed().as_secs_f64();
+
+        let start = Instant::now();
+        for _ in 0..n_nodes / children.len() {
+            for eval_string in &children {
+                let single: Vec<_> = keys
+                    .par_iter()
+                    .zip(states.par_iter())
+                    .map(|(k, s)| eval_str(k, s, eval_string))
+                    .collect();
+                assert_eq!(single.len(), num_keys);
+            }
+        }
+        let single_time = start.elapsed().as_secs_f64();
+
+        states = keys.par_iter().zip(states.par_iter()).map(|(k, s)| eval_str(k, s, &children[0])).collect();
+        coll.tree_prune(&(0..n_nodes).map(|i| i < width).collect::<Vec<bool>>());
+        out.push((level, n_nodes, single_time, crawl_time));
+    }
+    out
+}
+
+type Channel = SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>;
+
+// Time of each level of a one-dimensional crawl over `num_keys` clients
+// with arithmetic keys on 16 bits that keeps the first `width` nodes of
+// each level: that of the evaluation of the keys alone
+// (`KeyCollection::tree_expand`), and that of the evaluation and the check
+// of every client's keys with the other server, over a local connection
+// (`KeyCollection::tree_expand_checked`), which the crawl runs on every
+// level. Each server runs on one thread of its own.
+fn check_levels(num_keys: usize, width: usize) -> Vec<(usize, usize, f64, f64)> {
+    let nbits = 16;
+    let mut rng = rand::thread_rng();
+    let layout = KeyLayout {
+        kind: KeyKind::Arith,
+        dim_lens: vec![nbits],
+        pairs_per_dim: vec![],
+        max_weight: 1,
+        n_attributes: 0,
+    };
+    let seed = PrgSeed::random();
+    let new_coll = || KeyCollection::<FE, FieldElm>::new(&seed, nbits, layout.clone());
+    let (mut alone, mut checked0, mut checked1) = (new_coll(), new_coll(), new_coll());
+    for _ in 0..num_keys {
+        let alpha = MSB_u32_to_bits(16, rng.gen::<u16>().into());
+        let (k0, k1) = ArithDCFKey::gen_l_inf_ball(&alpha, 5);
+        alone.add_key(ClientKey::Arith(k0.clone()), None, vec![]);
+        checked0.add_key(ClientKey::Arith(k0), None, vec![]);
+        checked1.add_key(ClientKey::Arith(k1), None, vec![]);
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream0 = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream1, _) = listener.accept().unwrap();
+    let channel = |s: TcpStream| SyncChannel::new(BufReader::new(s.try_clone().unwrap()), BufWriter::new(s));
+    let (mut channel0, mut channel1): (Channel, Channel) = (channel(stream0), channel(stream1));
+    let pool = || rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    let (pool0, pool1) = (pool(), pool());
+
+    alone.tree_init();
+    checked0.tree_init();
+    checked1.tree_init();
+    let mut out = vec![];
+    for level in 0..nbits - 1 {
+        let start = Instant::now();
+        let n_nodes = pool0.install(|| alone.tree_expand());
+        let expand_time = start.elapsed().as_secs_f64();
+
+        let start = Instant::now();
+        let disabled = crossbeam::scope(|s| {
+            let first = s.spawn(|_| pool0.install(|| checked0.tree_expand_checked(true, &mut channel0)));
+            let second = pool1.install(|| checked1.tree_expand_checked(false, &mut channel1));
+            first.join().unwrap() + second
+        })
+        .unwrap();
+        let checked_time = start.elapsed().as_secs_f64();
+        assert_eq!(disabled, 0);
+
+        let keep = (0..n_nodes).map(|i| i < width).collect::<Vec<bool>>();
+        for coll in [&mut alone, &mut checked0, &mut checked1] {
+            coll.tree_prune(&keep);
+        }
+        out.push((level, n_nodes, expand_time, checked_time));
+    }
+    out
+}
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
@@ -85,5 +220,26 @@ async fn main() -> io::Result<()> {
     }
     wtr.flush()?;
 
+    // Each level of a crawl over 100k clients, client by client and as the
+    // crawl evaluates them
+    let mut wtr = Writer::from_path("src/bin/benchmarks/ibDCFevalbench.csv")?;
+    wtr.write_record(&["level", "frontier_nodes", "number_keys", "eval_str_time", "crawl_time"])?;
+
+    let num_keys = 100000;
+    for (level, n_nodes, single_time, crawl_time) in crawl_levels(num_keys, 4) {
+        wtr.write_record(&[level.to_string(), n_nodes.to_string(), num_keys.to_string(), single_time.to_string(), crawl_time.to_string()])?;
+    }
+    wtr.flush()?;
+
+    // Each level of a one-dimensional crawl over 100k clients with
+    // arithmetic keys, with and without the servers' check of the keys
+    let mut wtr = Writer::from_path("src/bin/benchmarks/ibDCFcheckbench.csv")?;
+    wtr.write_record(&["level", "frontier_nodes", "number_keys", "expand_time", "checked_time"])?;
+
+    for (level, n_nodes, expand_time, checked_time) in check_levels(num_keys, 4) {
+        wtr.write_record(&[level.to_string(), n_nodes.to_string(), num_keys.to_string(), expand_time.to_string(), checked_time.to_string()])?;
+    }
+    wtr.flush()?;
+
     Ok(())
 }
\ No newline at end of file
@@ -1,8 +1,8 @@
-use counttree::{add_bitstrings, collect, config, fastfield, rpc::{
+use counttree::{add_bitstrings, collect, config, rpc::{
     AddKeysRequest, FinalSharesRequest, ResetRequest,
     TreeInitRequest,
     TreeCrawlRequest,
-}, string_to_bits, FieldElm, MSB_u32_to_bits};
+}, share_attributes, share_weight, share_weight_bits, bits_to_string, string_to_bits, MSB_u32_to_bits};
 
 use std::time::Instant;
 
@@ -21,12 +21,15 @@ use tarpc::{
 use rand::distributions::Alphanumeric;
 
 use std::time::{Duration, SystemTime};
-use counttree::ibDCF::{eval_str, ibDCFKey};
-use counttree::rpc::{TreeCrawlLastRequest, TreePruneLastRequest, TreePruneRequest};
+use counttree::collect::{ClientKey, KeyKind, WeightShare};
+use counttree::config::{Backend, Mode, ShareType};
+use counttree::hamming::HammingKey;
+use counttree::mpc::BeaverKey;
+use counttree::ibDCF::{eval_str, ibDCFKey, to_union, ArithDCFKey, IntervalKey};
+use counttree::rpc::{FieldGroups, RingGroups, ShareGroups, TreeAttributesRequest, TreeCrawlLastRequest, TreePruneLastRequest, TreePruneRequest, VerifyKeysRequest};
 use counttree::sample_covid_data::sample_covid_locations;
 use counttree::sample_driving_data::{sample_start_locations, save_heavy_hitters};
 
-type IntervalKey = (ibDCFKey, ibDCFKey);
 fn long_context() -> context::Context {
     let mut ctx = context::current();
 
@@ -42,10 +45,10 @@ fn sample_string(len: usize) -> String {
         .take(len / 8)
         .collect()
 }
-fn generate_random_bit_vectors(len: usize, d: usize) -> Vec<Vec<bool>> {
+fn generate_random_bit_vectors(lens: &[usize]) -> Vec<Vec<bool>> {
     let mut rng = rand::thread_rng();
-    (0..d)
-        .map(|_| {
+    lens.iter()
+        .map(|&len| {
             let s: String = std::iter::repeat(())
                 .map(|()| rng.sample(Alphanumeric))
                 .take((len + 7) / 8) // Round up to ensure enough bits
@@ -57,12 +60,24 @@ fn generate_random_bit_vectors(len: usize, d: usize) -> Vec<Vec<bool>> {
         .collect()
 }
 
-fn generate_strings(cfg: &config::Config, aug_len : usize) -> Vec<Vec<Vec<bool>>> {
-    (0..cfg.num_sites)
+fn generate_strings(cfg: &config::Config, aug_len : usize) -> io::Result<Vec<Vec<Vec<bool>>>> {
+    //leaving space for later per-client augmentation
+    let lens = cfg
+        .data_len
+        .iter()
+        .map(|len| len.checked_sub(aug_len))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("every dimension needs at least {} bits for the per-client random bits", aug_len),
+            )
+        })?;
+    Ok((0..cfg.num_sites)
         .map(|_| {
-            generate_random_bit_vectors(cfg.data_len - aug_len, cfg.n_dims) //leaving space for later per-client augmentation
+            generate_random_bit_vectors(&lens)
         })
-        .collect::<Vec<Vec<Vec<bool>>>>()
+        .collect::<Vec<Vec<Vec<bool>>>>())
 }
 fn generate_covid_samples(nreq : usize, aug_len : usize) -> Vec<Vec<Vec<bool>>> {
     let covid_path = "data/COVID-19_Case_Surveillance_Public_Use_Data_with_Geography_20250430.csv";
@@ -91,7 +106,7 @@ fn generate_keys(cfg: &config::Config) -> (Vec<Vec<IntervalKey>>, Vec<Vec<Interv
     let (keys0, keys1): (Vec<Vec<IntervalKey>>, Vec<Vec<IntervalKey>>) = rayon::iter::repeat(0)
         .take(cfg.num_sites)
         .map(|_| {
-            let data = generate_random_bit_vectors(cfg.data_len, cfg.n_dims);
+            let data = generate_random_bit_vectors(&cfg.data_len);
             let keys = ibDCFKey::gen_l_inf_ball(data, 1);
             (keys.0.clone(), keys.1.clone())
         })
@@ -115,19 +130,83 @@ async fn reset_servers(
     Ok(())
 }
 
+async fn verify_keys(
+    client0: &mut counttree::CollectorClient,
+    client1: &mut counttree::CollectorClient,
+) -> io::Result<()> {
+    let req0 = VerifyKeysRequest { gc_sender: true };
+    let req1 = VerifyKeysRequest { gc_sender: false };
+    let response0 = client0.verify_keys(long_context(), req0);
+    let response1 = client1.verify_keys(long_context(), req1);
+    let (disabled0, disabled1) = try_join!(response0, response1).unwrap();
+    assert_eq!(disabled0, disabled1);
+    println!("Disabled {:?} clients with malformed or oversized keys", disabled0);
+
+    Ok(())
+}
+
 async fn tree_init(
+    cfg: &config::Config,
     client0: &mut counttree::CollectorClient,
     client1: &mut counttree::CollectorClient,
 ) -> io::Result<()> {
-    let req = TreeInitRequest {};
-    let response0 = client0.tree_init(long_context(), req.clone());
-    let response1 = client1.tree_init(long_context(), req);
-    try_join!(response0, response1).unwrap();
+    let req0 = TreeInitRequest { full_domain: cfg.full_domain, gc_sender: true };
+    let req1 = TreeInitRequest { full_domain: cfg.full_domain, gc_sender: false };
+    let response0 = client0.tree_init(long_context(), req0);
+    let response1 = client1.tree_init(long_context(), req1);
+    let (done0, done1) = try_join!(response0, response1).unwrap();
+    for done in [done0, done1] {
+        if done != "Done" {
+            return Err(io::Error::new(io::ErrorKind::Other, done));
+        }
+    }
 
     Ok(())
 }
 
-async fn add_fuzzy_keys(
+type WeightShares<G> = Option<Vec<WeightShare<<G as ShareGroups>::T, <G as ShareGroups>::U>>>;
+
+// Shares of a random weight in [1, max_weight] for each of `nreqs` clients,
+// or None if the collection is unweighted.
+fn generate_weights<G: ShareGroups>(cfg: &config::Config, nreqs: usize) -> (WeightShares<G>, WeightShares<G>) {
+    if cfg.max_weight == 1 {
+        return (None, None);
+    }
+
+    let mut rng = thread_rng();
+    let (weights0, weights1) = (0..nreqs)
+        .map(|_| {
+            let weight = rng.gen_range(1, cfg.max_weight + 1);
+            let (t0, t1) = share_weight::<G::T>(weight, cfg.max_weight);
+            let (u0, u1) = share_weight::<G::U>(weight, cfg.max_weight);
+            let (bits0, bits1) = share_weight_bits(weight, cfg.max_weight);
+            (WeightShare { value: (t0, u0), bits: bits0 }, WeightShare { value: (t1, u1), bits: bits1 })
+        })
+        .unzip();
+    (Some(weights0), Some(weights1))
+}
+
+type AttributeShares<G> = Option<Vec<Vec<<G as ShareGroups>::U>>>;
+
+// Shares of a random one-hot attribute vector (say, a client's hour-of-day
+// bucket) for each of `nreqs` clients, or None if clients have no attributes.
+fn generate_attributes<G: ShareGroups>(cfg: &config::Config, nreqs: usize) -> (AttributeShares<G>, AttributeShares<G>) {
+    if cfg.n_attributes == 0 {
+        return (None, None);
+    }
+
+    let mut rng = thread_rng();
+    let (attrs0, attrs1) = (0..nreqs)
+        .map(|_| {
+            let mut one_hot = vec![0u32; cfg.n_attributes];
+            one_hot[rng.gen_range(0, cfg.n_attributes)] = 1;
+            share_attributes::<G::U>(&one_hot)
+        })
+        .unzip();
+    (Some(attrs0), Some(attrs1))
+}
+
+async fn add_fuzzy_keys<G: ShareGroups>(
     cfg: &config::Config,
     client0: counttree::CollectorClient,
     client1: counttree::CollectorClient,
@@ -141,18 +220,51 @@ async fn add_fuzzy_keys(
 
     let mut addkey0 = Vec::with_capacity(nreqs);
     let mut addkey1 = Vec::with_capacity(nreqs);
+    let sizes = cfg.ball_size.iter().map(|&s| s as u32).collect::<Vec<_>>();
+    let layout = cfg.key_layout();
 
     for i in 0..nreqs {
         let sample = zipf.sample(&mut rng) - 1;
         let key_str = augment_string(strings[sample].clone(), aug_len);
-        let (key0, key1) = ibDCFKey::gen_l_inf_ball(key_str, cfg.ball_size as u32);
+        let (key0, key1) = match layout.kind {
+            KeyKind::Hamming => {
+                let (key0, key1) = HammingKey::gen_hamming_ball(key_str);
+                (ClientKey::Hamming(key0), ClientKey::Hamming(key1))
+            }
+            KeyKind::Point => {
+                let (key0, key1) = ArithDCFKey::gen_point(&key_str[0]);
+                (ClientKey::Point(key0), ClientKey::Point(key1))
+            }
+            KeyKind::Arith => {
+                let (key0, key1) = ArithDCFKey::gen_l_inf_ball(&key_str[0], sizes[0]);
+                (ClientKey::Arith(key0), ClientKey::Arith(key1))
+            }
+            KeyKind::Beaver => {
+                let (key0, key1) = BeaverKey::gen_l_inf_box(&key_str, &sizes, cfg.beaver_triples);
+                (ClientKey::Beaver(key0), ClientKey::Beaver(key1))
+            }
+            KeyKind::BoxUnion => {
+                let (key0, key1) = ibDCFKey::gen_l2_ball(key_str, sizes[0], cfg.n_boxes);
+                (ClientKey::BoxUnion(key0), ClientKey::BoxUnion(key1))
+            }
+            KeyKind::Box if cfg.toroidal.iter().any(|&t| t) => {
+                let (key0, key1) = ibDCFKey::gen_l_inf_ball_toroidal(key_str, &sizes, &cfg.toroidal);
+                (ClientKey::Box(key0), ClientKey::Box(key1))
+            }
+            KeyKind::Box => {
+                let (key0, key1) = ibDCFKey::gen_l_inf_box(key_str, &sizes);
+                (ClientKey::Box(to_union(key0)), ClientKey::Box(to_union(key1)))
+            }
+        };
         addkey0.push(key0);
         addkey1.push(key1);
     }
 
 
-    let req0 = AddKeysRequest { keys: addkey0 };
-    let req1 = AddKeysRequest { keys: addkey1 };
+    let (weights0, weights1) = generate_weights::<G>(cfg, nreqs);
+    let (attrs0, attrs1) = generate_attributes::<G>(cfg, nreqs);
+    let req0 = G::add_keys(AddKeysRequest { keys: addkey0, weights: weights0, attributes: attrs0 });
+    let req1 = G::add_keys(AddKeysRequest { keys: addkey1, weights: weights1, attributes: attrs1 });
 
     let response0 = client0.add_keys(long_context(), req0.clone());
     let response1 = client1.add_keys(long_context(), req1.clone());
@@ -162,17 +274,19 @@ async fn add_fuzzy_keys(
     Ok(())
 }
 
-async fn add_keys(
+async fn add_keys<G: ShareGroups>(
     cfg: &config::Config,
     client0: counttree::CollectorClient,
     client1: counttree::CollectorClient,
-    keys0: Vec<Vec<IntervalKey>>,
-    keys1: Vec<Vec<IntervalKey>>,
+    keys0: Vec<ClientKey<G::T, G::U>>,
+    keys1: Vec<ClientKey<G::T, G::U>>,
     nreqs: usize,
 ) -> io::Result<()> {
 
-    let req0 = AddKeysRequest { keys: keys0 };
-    let req1 = AddKeysRequest { keys: keys1 };
+    let (weights0, weights1) = generate_weights::<G>(cfg, keys0.len());
+    let (attrs0, attrs1) = generate_attributes::<G>(cfg, keys0.len());
+    let req0 = G::add_keys(AddKeysRequest { keys: keys0, weights: weights0, attributes: attrs0 });
+    let req1 = G::add_keys(AddKeysRequest { keys: keys1, weights: weights1, attributes: attrs1 });
 
     let response0 = client0.add_keys(long_context(), req0.clone());
     let response1 = client1.add_keys(long_context(), req1.clone());
@@ -182,7 +296,35 @@ async fn add_keys(
     Ok(())
 }
 
-async fn run_level(
+// The largest value that any node can have: every client, each with the
+// largest weight.
+fn max_total(cfg: &config::Config, nreqs: usize) -> io::Result<usize> {
+    nreqs.checked_mul(cfg.max_weight as usize).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "the total weight of the clients overflows")
+    })
+}
+
+// Adds to `used` the Beaver triples of each client that the crawl's step
+// from `n_nodes` nodes at `level` needs, and refuses the step if that is
+// more than the clients hold; the servers would abort it.
+fn use_triples(cfg: &config::Config, used: &mut usize, n_nodes: usize, level: usize) -> io::Result<()> {
+    if cfg.key_layout().kind != KeyKind::Beaver {
+        return Ok(());
+    }
+    *used += cfg.triples_needed(n_nodes, level);
+    if *used > cfg.beaver_triples {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "the crawl needs {} Beaver triples from each client by level {}, which has only {}; raise beaver_triples",
+                used, level, cfg.beaver_triples
+            ),
+        ));
+    }
+    Ok(())
+}
+
+async fn run_level<G: ShareGroups>(
     cfg: &config::Config,
     client0: &mut counttree::CollectorClient,
     client1: &mut counttree::CollectorClient,
@@ -190,8 +332,8 @@ async fn run_level(
     nreqs: usize,
     start_time: Instant,
 ) -> io::Result<usize> {
-    let threshold64 = core::cmp::max(1, (cfg.threshold * (nreqs as f64)) as u64);
-    let threshold = fastfield::FE::new(threshold64);
+    let threshold32 = core::cmp::max(1, (cfg.threshold * (nreqs as f64)) as u32);
+    let threshold = G::T::from(threshold32);
 
     // Tree crawl
     println!(
@@ -208,6 +350,7 @@ async fn run_level(
     let response1 = client1.tree_crawl(long_context(), req1);
 
     let (vals0, vals1) = try_join!(response0, response1).unwrap();
+    let (vals0, vals1) = (G::crawl(vals0), G::crawl(vals1));
 
     println!(
         "TreeCrawlDone {:?} {:?} {:?}",
@@ -217,7 +360,8 @@ async fn run_level(
     );
 
     assert_eq!(vals0.len(), vals1.len());
-    let keep = collect::KeyCollection::<fastfield::FE,FieldElm>::keep_values(nreqs, &threshold, &vals0, &vals1);
+    let keep = collect::KeyCollection::<G::T, G::U>::keep_values(max_total(cfg, nreqs)?, &threshold, &vals0, &vals1)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
 
     println!("Keep: {:?}", &keep);
     let mut ap = 0;
@@ -232,18 +376,18 @@ async fn run_level(
     let response1 = client1.tree_prune(long_context(), req);
     try_join!(response0, response1).unwrap();
 
-    Ok(vals0.len())
+    Ok(ap)
 }
 
-async fn run_level_last(
+async fn run_level_last<G: ShareGroups>(
     cfg: &config::Config,
     client0: &mut counttree::CollectorClient,
     client1: &mut counttree::CollectorClient,
     nreqs: usize,
     start_time: Instant,
 ) -> io::Result<usize> {
-    let threshold64 = core::cmp::max(1, (cfg.threshold * (nreqs as f64)) as u32);
-    let threshold = FieldElm::from(threshold64);
+    let threshold32 = core::cmp::max(1, (cfg.threshold * (nreqs as f64)) as u32);
+    let threshold = G::U::from(threshold32);
 
     // Tree crawl
     println!(
@@ -259,6 +403,7 @@ async fn run_level_last(
     let response1 = client1.tree_crawl_last(long_context(), req1);
 
     let (vals0, vals1) = try_join!(response0, response1).unwrap();
+    let (vals0, vals1) = (G::crawl_last(vals0), G::crawl_last(vals1));
 
     println!(
         "TreeCrawlDone LAST {:?} {:?}",
@@ -267,7 +412,12 @@ async fn run_level_last(
     );
 
     assert_eq!(vals0.len(), vals1.len());
-    let keep = collect::KeyCollection::<fastfield::FE,FieldElm>::keep_values_last(nreqs, &threshold, &vals0, &vals1);
+    let keep = if cfg.release_histogram {
+        vec![true; vals0.len()]
+    } else {
+        collect::KeyCollection::<G::T, G::U>::keep_values_last(max_total(cfg, nreqs)?, &threshold, &vals0, &vals1)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+    };
 
     println!("Keep: {:?}", keep);
 
@@ -279,18 +429,39 @@ async fn run_level_last(
     Ok(vals0.len())
 }
 
-async fn final_shares(
+async fn final_shares<G: ShareGroups>(
+    cfg: &config::Config,
     client0: &mut counttree::CollectorClient,
     client1: &mut counttree::CollectorClient,
 ) -> io::Result<()> {
+    // Attribute histograms of the heavy leaves
+    let attrs = if cfg.n_attributes > 0 {
+        let req0 = TreeAttributesRequest { gc_sender: true };
+        let req1 = TreeAttributesRequest { gc_sender: false };
+        let response0 = client0.tree_attributes(long_context(), req0);
+        let response1 = client1.tree_attributes(long_context(), req1);
+        let (attrs0, attrs1) = try_join!(response0, response1).unwrap();
+        collect::KeyCollection::<G::T, G::U>::final_attributes(&G::attributes(attrs0), &G::attributes(attrs1))
+    } else {
+        vec![]
+    };
+
     // Final shares
     let req = FinalSharesRequest {};
     let response0 = client0.final_shares(long_context(), req.clone());
     let response1 = client1.final_shares(long_context(), req);
     let (vals0, vals1) = try_join!(response0, response1).unwrap();
-    for res in &collect::KeyCollection::<fastfield::FE,FieldElm>::final_values(&vals0, &vals1) {
+    let (vals0, vals1) = (G::final_shares(vals0), G::final_shares(vals1));
+    for (i, res) in collect::KeyCollection::<G::T, G::U>::final_values(&vals0, &vals1).iter().enumerate() {
         println!("Path = {:?}", res.path);
-        save_heavy_hitters(res.path.as_slice(), "data/ride_heavy_hitters.csv");
+        if let Some(a) = attrs.get(i) {
+            println!("Attributes = {:?}", a);
+        }
+        if cfg.mode == Mode::Exact {
+            println!("String = {:?}", bits_to_string(&res.path[0]));
+        } else {
+            save_heavy_hitters(res.path.as_slice(), "data/ride_heavy_hitters.csv");
+        }
     }
 
     Ok(())
@@ -302,15 +473,15 @@ async fn main() -> io::Result<()> {
     rayon::ThreadPoolBuilder::new().num_threads(1).build_global().unwrap();
 
     env_logger::init();
-    let (cfg, _, nreqs) = config::get_args("Leader", false, true);
-    debug_assert_eq!(cfg.data_len % 8, 0);
+    let (cfg, _, nreqs, _) = config::get_args("Leader", false, true);
+    debug_assert!(cfg.data_len.iter().all(|len| len % 8 == 0));
 
     // XXX WARNING: THERE IS NO TLS HERE!!!
-    let mut client0 =
+    let client0 =
         counttree::CollectorClient::new(client::Config::default(),
                                         tcp::connect(cfg.server0, Bincode::default).await?
         ).spawn();
-    let mut client1 =
+    let client1 =
         counttree::CollectorClient::new(client::Config::default(),
                                         tcp::connect(cfg.server1, Bincode::default).await?
         ).spawn();
@@ -328,10 +499,25 @@ async fn main() -> io::Result<()> {
         delta / (bench_keys0.len() as f64)
     );
 
-    let aug_len = 8;
+    match cfg.share_type {
+        ShareType::Field => run::<FieldGroups>(&cfg, client0, client1, nreqs).await,
+        ShareType::Ring => run::<RingGroups>(&cfg, client0, client1, nreqs).await,
+    }
+}
+
+// Adds the clients' keys, in the groups of `G`, and runs the crawl.
+async fn run<G: ShareGroups>(
+    cfg: &config::Config,
+    mut client0: counttree::CollectorClient,
+    mut client1: counttree::CollectorClient,
+    nreqs: usize,
+) -> io::Result<()> {
+    // Fuzzy modes give each client a few random low bits of its own; exact
+    // matches need whole strings in common.
+    let aug_len = if cfg.mode == Mode::Exact { 0 } else { 8 };
     if cfg.distribution.as_str() == "zipf" {
         println!("Zipf distribution sampling...");
-        let strings = generate_strings(&cfg, aug_len);
+        let strings = generate_strings(cfg, aug_len)?;
         println!("Generated {:?} samples", strings.len());
 
 
@@ -347,8 +533,8 @@ async fn main() -> io::Result<()> {
                 left_to_go -= this_batch;
 
                 if this_batch > 0 {
-                    resps.push(add_fuzzy_keys(
-                        &cfg,
+                    resps.push(add_fuzzy_keys::<G>(
+                        cfg,
                         client0.clone(),
                         client1.clone(),
                         &strings,
@@ -380,8 +566,23 @@ async fn main() -> io::Result<()> {
         let mut addkey0 = Vec::with_capacity(nreqs);
         let mut addkey1 = Vec::with_capacity(nreqs);
 
+        // Coordinates are not bit strings, so Hamming distance and exact
+        // string matches make no sense here.
+        assert!(cfg.mode == Mode::LInf || cfg.mode == Mode::L2, "the rides distribution needs the l_inf or l2 mode");
+        assert!(cfg.backend != Backend::Beaver, "the rides distribution needs the gc backend");
+        assert!(!cfg.toroidal[0], "the rides distribution only wraps longitude");
+        let sizes = (cfg.ball_size[0] as i16, cfg.ball_size[1] as i16);
         for _j in 0..nreqs {
-            let (key0, key1) = ibDCFKey::gen_l_inf_ball_from_coords(strings[_j], cfg.ball_size as i16);
+            let (key0, key1) = if cfg.mode == Mode::L2 {
+                let (key0, key1) = ibDCFKey::gen_l2_ball_from_coords(strings[_j], sizes.0, cfg.n_boxes);
+                (ClientKey::BoxUnion(key0), ClientKey::BoxUnion(key1))
+            } else if cfg.toroidal[1] {
+                let (key0, key1) = ibDCFKey::gen_l_inf_ball_from_coords_wrapping(strings[_j], sizes);
+                (ClientKey::Box(key0), ClientKey::Box(key1))
+            } else {
+                let (key0, key1) = ibDCFKey::gen_l_inf_ball_from_coords(strings[_j], sizes);
+                (ClientKey::Box(to_union(key0)), ClientKey::Box(to_union(key1)))
+            };
             addkey0.push(key0);
             addkey1.push(key1);
         }
@@ -398,8 +599,8 @@ async fn main() -> io::Result<()> {
                 left_to_go -= this_batch;
 
                 if this_batch > 0 {
-                    resps.push(add_keys(
-                        &cfg,
+                    resps.push(add_keys::<G>(
+                        cfg,
                         client0.clone(),
                         client1.clone(),
                         addkey0[nreqs-left_to_go - this_batch..nreqs-left_to_go].to_vec(),
@@ -414,13 +615,24 @@ async fn main() -> io::Result<()> {
             }
         }
     }
-    tree_init(&mut client0, &mut client1).await?;
+    verify_keys(&mut client0, &mut client1).await?;
+    tree_init(cfg, &mut client0, &mut client1).await?;
 
 
     let start = Instant::now();
-    let mut active_paths = 0;
-    for level in 0..cfg.data_len-1 {
-        active_paths = run_level(&cfg, &mut client0, &mut client1, level, nreqs, start).await?;
+    // With the full domain, the frontier already holds every node of the
+    // level above the leaves.
+    let levels = if cfg.full_domain { 0 } else { cfg.depth() - 1 };
+    let mut active_paths = if cfg.full_domain {
+        1 << cfg.data_len.iter().map(|&len| len.min(cfg.depth() - 1)).sum::<usize>()
+    } else {
+        1
+    };
+    // The crawl and the last level draw on separate triples.
+    let mut triples_used = 0;
+    for level in 0..levels {
+        use_triples(cfg, &mut triples_used, active_paths, level)?;
+        active_paths = run_level::<G>(cfg, &mut client0, &mut client1, level, nreqs, start).await?;
 
         println!(
             "Level {:?} {:?}",
@@ -429,15 +641,16 @@ async fn main() -> io::Result<()> {
         );
     }
 
-    let active_paths = run_level_last(&cfg, &mut client0, &mut client1, nreqs, start).await?;
+    use_triples(cfg, &mut 0, active_paths, cfg.depth() - 1)?;
+    let active_paths = run_level_last::<G>(cfg, &mut client0, &mut client1, nreqs, start).await?;
     println!(
         "Level {:?} active_paths={:?} {:?}",
-        cfg.data_len,
+        cfg.depth(),
         active_paths,
         start.elapsed().as_secs_f64()
     );
 
-    final_shares(&mut client0, &mut client1).await?;
+    final_shares::<G>(cfg, &mut client0, &mut client1).await?;
 
     Ok(())
 }
\ No newline at end of file
@@ -3,13 +3,19 @@
 
 use counttree::{
     collect, config,
-    FieldElm,
+    FieldElm, RingElm,
     fastfield::FE, prg,
+    config::ShareType,
+    hamming::HammingBall,
     rpc::Collector,
     rpc::{
-        AddKeysRequest, FinalSharesRequest, ResetRequest, TreeCrawlRequest, TreeInitRequest,
+        AddKeys, AddKeysRequest, AttributeShares, CrawlLastShares, CrawlShares, FieldGroups,
+        FinalShares, FinalSharesRequest, ResetRequest, RingGroups, ShareGroups, Shares,
+        TreeCrawlRequest, TreeInitRequest,
         TreePruneRequest,
         TreePruneLastRequest,
+        TreeAttributesRequest,
+        VerifyKeysRequest,
     },
 };
 
@@ -41,44 +47,131 @@ extern crate num_cpus;
 type MyChannel = scuttlebutt::SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>;
 
 
+// The server's collection, in the groups of the config's `share_type`.
+enum Collection {
+    Field(collect::KeyCollection<FE, FieldElm>),
+    Ring(collect::KeyCollection<RingElm, RingElm>),
+}
+
+// Runs `$body` with `$c` bound to the collection, whichever its groups;
+// with `shares`, wraps the result in the matching variant of `Shares`.
+macro_rules! on_collection {
+    (shares $coll:expr, |$c:ident| $body:expr) => {
+        match $coll {
+            Collection::Field($c) => Shares::Field($body),
+            Collection::Ring($c) => Shares::Ring($body),
+        }
+    };
+    ($coll:expr, |$c:ident| $body:expr) => {
+        match $coll {
+            Collection::Field($c) => $body,
+            Collection::Ring($c) => $body,
+        }
+    };
+}
+
+impl Collection {
+    fn new(
+        share_type: ShareType,
+        seed: &prg::PrgSeed,
+        data_len: usize,
+        layout: &collect::KeyLayout,
+        hamming_ball: &Option<HammingBall>,
+        beaver_triples: usize,
+    ) -> Collection {
+        let mut coll = match share_type {
+            ShareType::Field => Collection::Field(collect::KeyCollection::new(seed, data_len, layout.clone())),
+            ShareType::Ring => Collection::Ring(collect::KeyCollection::new(seed, data_len, layout.clone())),
+        };
+        on_collection!(&mut coll, |c| {
+            if let Some(ball) = hamming_ball {
+                c.set_hamming_ball(ball.clone());
+            }
+            c.set_beaver_triples(beaver_triples);
+        });
+        coll
+    }
+}
+
+fn add_all<G: ShareGroups>(coll: &mut collect::KeyCollection<G::T, G::U>, add: AddKeysRequest<G::T, G::U>) {
+    for (i, k) in add.keys.into_iter().enumerate() {
+        let weight = add.weights.as_ref().map(|w| w[i].clone());
+        let attributes = add.attributes.as_ref().map_or(vec![], |a| a[i].clone());
+        coll.add_key(k, weight, attributes);
+    }
+}
+
+// The seed of the next run's collection.
+fn next_seed(seeds: &Mutex<prg::PrgStream>) -> prg::PrgSeed {
+    let mut seed = prg::PrgSeed::zero();
+    rand::RngCore::fill_bytes(&mut *seeds.lock().unwrap(), &mut seed.key);
+    seed
+}
+
 #[derive(Clone)]
 struct CollectorServer {
-    seed: prg::PrgSeed,
+    // The stream of seeds of the collections, one per run, from the seed
+    // the server starts with.
+    seeds: Arc<Mutex<prg::PrgStream>>,
     data_len: usize,
+    layout: collect::KeyLayout,
+    ball_size: Vec<usize>,
+    hamming_ball: Option<HammingBall>,
+    beaver_triples: usize,
+    share_type: ShareType,
     server_idx: u16,
-    arc: Arc<Mutex<collect::KeyCollection<FE, FieldElm>>>,
+    arc: Arc<Mutex<Collection>>,
     // gc_channel: Option<Arc<Mutex<MyChannel>>>
     gc_channels: Vec<Arc<Mutex<MyChannel>>>
 }
 
 impl Collector for CollectorServer {
     type AddKeysFut = Ready<String>;
+    type VerifyKeysFut = Ready<usize>;
     type TreeInitFut = Ready<String>;
-    type TreeCrawlFut = Ready<Vec<FE>>;
-    type TreeCrawlLastFut = Ready<Vec<FieldElm>>;
+    type TreeCrawlFut = Ready<CrawlShares>;
+    type TreeCrawlLastFut = Ready<CrawlLastShares>;
     type TreePruneFut = Ready<String>;
     type TreePruneLastFut = Ready<String>;
-    type FinalSharesFut = Ready<Vec<collect::Result<FieldElm>>>;
+    type TreeAttributesFut = Ready<AttributeShares>;
+    type FinalSharesFut = Ready<FinalShares>;
     type ResetFut = Ready<String>;
 
     fn reset(self, _: context::Context, _rst: ResetRequest) -> Self::ResetFut {
         let mut coll = self.arc.lock().unwrap();
-        *coll = collect::KeyCollection::new(&self.seed, self.data_len);
+        let seed = next_seed(&self.seeds);
+        *coll = Collection::new(self.share_type, &seed, self.data_len, &self.layout, &self.hamming_ball, self.beaver_triples);
 
         future::ready("Done".to_string())
     }
 
-    fn add_keys(self, _: context::Context, add: AddKeysRequest) -> Self::AddKeysFut {
+    fn add_keys(self, _: context::Context, add: AddKeys) -> Self::AddKeysFut {
         let mut coll = self.arc.lock().unwrap();
-        for k in add.keys {
-            coll.add_key(k);
+        match (&mut *coll, add) {
+            (Collection::Field(c), Shares::Field(add)) => add_all::<FieldGroups>(c, add),
+            (Collection::Ring(c), Shares::Ring(add)) => add_all::<RingGroups>(c, add),
+            _ => return future::ready("The keys' shares are not in the collection's groups".to_string()),
         }
         future::ready("".to_string())
     }
 
-    fn tree_init(self, _: context::Context, _req: TreeInitRequest) -> Self::TreeInitFut {
+    fn verify_keys(self, _: context::Context, req: VerifyKeysRequest) -> Self::VerifyKeysFut {
         let mut coll = self.arc.lock().unwrap();
-        coll.tree_init();
+        let mut channel = self.gc_channels[0].lock().unwrap();
+        let disabled = on_collection!(&mut *coll, |c| c.verify_keys(req.gc_sender, &self.ball_size, &mut channel));
+        future::ready(disabled)
+    }
+
+    fn tree_init(self, _: context::Context, req: TreeInitRequest) -> Self::TreeInitFut {
+        let mut coll = self.arc.lock().unwrap();
+        if req.full_domain {
+            let mut channel = self.gc_channels[0].lock().unwrap();
+            if let Err(e) = on_collection!(&mut *coll, |c| c.tree_init_all(req.gc_sender, &mut channel)) {
+                return future::ready(e);
+            }
+        } else {
+            on_collection!(&mut *coll, |c| c.tree_init());
+        }
         future::ready("Done".to_string())
     }
 
@@ -112,7 +205,7 @@ impl Collector for CollectorServer {
             .map(|guard| &mut **guard)
             .collect();
 
-        let results = coll.tree_crawl(req.gc_sender, &mut channel_refs[..]);
+        let results = on_collection!(shares &mut *coll, |c| c.tree_crawl(req.gc_sender, &mut channel_refs[..]));
 
         future::ready(results)
     }
@@ -147,26 +240,50 @@ impl Collector for CollectorServer {
             .map(|guard| &mut **guard)
             .collect();
 
-        let results = coll.tree_crawl_last(req.gc_sender, &mut channel_refs[..]);
+        let results = on_collection!(shares &mut *coll, |c| c.tree_crawl_last(req.gc_sender, &mut channel_refs[..]));
 
         future::ready(results)
     }
 
     fn tree_prune(self, _: context::Context, req: TreePruneRequest) -> Self::TreePruneFut {
         let mut coll = self.arc.lock().unwrap();
-        coll.tree_prune(&req.keep);
+        on_collection!(&mut *coll, |c| c.tree_prune(&req.keep));
         future::ready("Done".to_string())
     }
 
     fn tree_prune_last(self, _: context::Context, req: TreePruneLastRequest) -> Self::TreePruneLastFut {
         let mut coll = self.arc.lock().unwrap();
-        coll.tree_prune_last(&req.keep);
+        on_collection!(&mut *coll, |c| c.tree_prune_last(&req.keep));
         future::ready("Done".to_string())
     }
 
+    fn tree_attributes(
+        self,
+        _: context::Context,
+        req: TreeAttributesRequest
+    ) -> Self::TreeAttributesFut {
+        let mut coll = self.arc.lock().unwrap();
+
+        // Lock all channels
+        let mut locked_channels: Vec<_> = self.gc_channels
+            .iter()
+            .map(|c| c.lock().unwrap())
+            .collect();
+
+        // Get mutable references to inner channels
+        let mut channel_refs: Vec<&mut MyChannel> = locked_channels
+            .iter_mut()
+            .map(|guard| &mut **guard)
+            .collect();
+
+        let results = on_collection!(shares &mut *coll, |c| c.tree_attributes(req.gc_sender, &mut channel_refs[..]));
+
+        future::ready(results)
+    }
+
     fn final_shares(self, _: context::Context, _req: FinalSharesRequest) -> Self::FinalSharesFut {
         let coll = self.arc.lock().unwrap();
-        let out = coll.final_shares();
+        let out = on_collection!(shares &*coll, |c| c.final_shares());
         future::ready(out)
     }
 }
@@ -315,7 +432,7 @@ fn connect_with_retries_tcp(addr: SocketAddr) -> io::Result<MyChannel> {
 async fn main() -> io::Result<()> {
     env_logger::init();
 
-    let (cfg, sid, _) = config::get_args("Server", true, false);
+    let (cfg, sid, _, seed) = config::get_args("Server", true, false);
     let server_addr = match sid {
         0 => cfg.server0,
         1 => cfg.server1,
@@ -328,10 +445,10 @@ async fn main() -> io::Result<()> {
         _ => panic!("Oh no!"),
     };
 
-    // XXX This is bogus
-    let seed = prg::PrgSeed { key: [1u8; 16] };
+    // A fixed seed repeats a run; each server keeps its own secret.
+    let seeds = Arc::new(Mutex::new(seed.unwrap_or_else(prg::PrgSeed::random).to_rng()));
 
-    let coll = collect::KeyCollection::new(&seed, cfg.data_len);
+    let coll = Collection::new(cfg.share_type, &next_seed(&seeds), cfg.depth(), &cfg.key_layout(), &cfg.hamming_ball(), cfg.beaver_triples);
     let arc = Arc::new(Mutex::new(coll));
 
     // let gc_channel = match setup_unix_socket(server_idx) {
@@ -363,8 +480,13 @@ async fn main() -> io::Result<()> {
         .map(|channel| {
             let coll_server = CollectorServer {
                 server_idx,
-                seed: seed.clone(),
-                data_len: cfg.data_len,
+                seeds: seeds.clone(),
+                data_len: cfg.depth(),
+                layout: cfg.key_layout(),
+                ball_size: cfg.ball_size.clone(),
+                hamming_ball: cfg.hamming_ball(),
+                beaver_triples: cfg.beaver_triples,
+                share_type: cfg.share_type,
                 arc: arc.clone(),
                 gc_channels: gc_channels.clone(),
             };
@@ -1,37 +1,302 @@
-use std::convert::{TryFrom, TryInto};
 use std::io::{BufReader, BufWriter};
 use std::os::unix::net::UnixStream;
-use crate::{all_bit_vectors, prg, Group, Share};
+use crate::{all_bit_vectors, prg, weight_bits, Group, Share};
 
 use rayon::prelude::*;
-use scuttlebutt::{AesRng, Block, SyncChannel};
+use scuttlebutt::{AbstractChannel, AesRng, Block, SyncChannel};
+use rand::Rng;
+use rand_core::RngCore;
+use rand_core_06::SeedableRng;
 use serde::{Deserialize, Serialize};
-use crate::ibDCF::{ibDCFKey, EvalState, eval_str};
+use crate::ibDCF::{ibDCFKey, EvalState, LevelCheck, ValueCheck, IntervalKey, ArithDCFKey, ArithIntervalKey, DimStep, eval_both_batch, arith_both_batch, child_states};
 use ocelot::{ot::AlszReceiver as OtReceiver, ot::AlszSender as OtSender};
 use ocelot::ot::{Receiver, Sender};
-use crate::equalitytest::{multiple_gb_equality_test, multiple_ev_equality_test};
-use crate::field::BlockPair;
+use crate::equalitytest::{multiple_gb_membership_test, multiple_ev_membership_test, multiple_gb_width_test, multiple_ev_width_test, multiple_gb_hamming_test, multiple_ev_hamming_test, multiple_gb_box_union_test, multiple_ev_box_union_test};
+use crate::hamming::{HammingBall, HammingKey};
+use crate::mpc::{check_triples, multiply_all, BeaverKey};
+use crate::field::OtBlocks;
+use std::convert::TryFrom;
 use std::marker::PhantomData;
 use std::net::TcpStream;
 use std::time::Instant;
 
+// Number of clients whose keys `expand_node` evaluates together through
+// the pipelined AES.
+const EVAL_BATCH_SIZE: usize = 256;
+
+/// The most bits that `KeyCollection::tree_init_all` evaluates in full,
+/// over all dimensions, i.e., at most 2^20 nodes.
+pub const MAX_FULL_DOMAIN_BITS: usize = 20;
+
+/// The most client states that `KeyCollection::tree_init_all` holds at
+/// once, one for each client at each node, which takes a few gigabytes.
+pub const MAX_FULL_DOMAIN_STATES: usize = 1 << 24;
+
+// The state of an arithmetic key, and its output there in both groups.
+type ArithState<T, U> = (EvalState, (T, U));
+
+// A stream seeded from `rng`, for drawing the masks of OT messages with
+// the groups' `FromRng`.
+fn mask_stream(rng: &mut AesRng) -> prg::PrgStream {
+    let mut seed = prg::PrgSeed::zero();
+    rand_core_06::RngCore::fill_bytes(rng, &mut seed.key);
+    seed.to_rng()
+}
+
+// Every client's steps from a node in both directions on each active
+// dimension, for its interval keys or for its arithmetic keys; the other
+// kind is empty.
+struct NodeSteps<T, U> {
+    intervals: Vec<Vec<DimStep>>,
+    arith: Vec<Vec<DimStep<ArithState<T, U>>>>,
+}
+
 #[derive(Clone)]
-struct TreeNode {
+struct TreeNode<T, U> {
     path: Vec<Vec<bool>>,
-    key_states: Vec<Vec<(EvalState, EvalState)>>,
+    key_states: Vec<Vec<Vec<(EvalState, EvalState)>>>,
+    // Each client's states of its arithmetic keys on each dimension.
+    arith_states: Vec<Vec<Vec<ArithState<T, U>>>>,
+}
+
+
+/// One server's share of a client's submission: interval keys for a box
+/// or for a union of boxes, a share of a point for the Hamming-ball mode,
+/// or, for one-dimensional data, an interval key with arithmetic outputs in
+/// both of the collection's groups, or such keys on every dimension along
+/// with Beaver triples for the Beaver backend, or, for exact matches on
+/// one-dimensional data, a point key with arithmetic outputs. A collection
+/// holds only one kind.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ClientKey<T, U> {
+    Box(Vec<Vec<IntervalKey>>),
+    // Pair `j` of every dimension is box `j`; see `ibDCFKey::gen_l2_ball`.
+    BoxUnion(Vec<Vec<IntervalKey>>),
+    Hamming(HammingKey),
+    Arith(ArithIntervalKey<(T, U)>),
+    Beaver(BeaverKey<T, U>),
+    Point(ArithDCFKey<(T, U)>),
 }
 
-unsafe impl Send for TreeNode {}
-unsafe impl Sync for TreeNode {}
+impl<T, U> ClientKey<T, U> {
+    /// Bit length of each dimension.
+    pub fn dim_lens(&self) -> Vec<usize> {
+        match self {
+            ClientKey::Box(key) | ClientKey::BoxUnion(key) => key.iter().map(|d| d[0].0.domain_size()).collect(),
+            ClientKey::Hamming(key) => key.dim_lens(),
+            ClientKey::Arith(_) | ClientKey::Beaver(_) | ClientKey::Point(_) => {
+                self.arith_dims().unwrap().iter().map(|keys| keys[0].key.domain_size()).collect()
+            }
+        }
+    }
+
+    fn intervals(&self) -> Option<&Vec<Vec<IntervalKey>>> {
+        match self {
+            ClientKey::Box(key) | ClientKey::BoxUnion(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    // The arithmetic keys of each dimension: the (left, right) keys of an
+    // interval, or a point key.
+    fn arith_dims(&self) -> Option<Vec<Vec<&ArithDCFKey<(T, U)>>>> {
+        match self {
+            ClientKey::Arith((left, right)) => Some(vec![vec![left, right]]),
+            ClientKey::Beaver(key) => Some(key.dims.iter().map(|(left, right)| vec![left, right]).collect()),
+            ClientKey::Point(key) => Some(vec![vec![key]]),
+            _ => None,
+        }
+    }
+
+    /// Which kind of key this is.
+    pub fn kind(&self) -> KeyKind {
+        match self {
+            ClientKey::Box(_) => KeyKind::Box,
+            ClientKey::BoxUnion(_) => KeyKind::BoxUnion,
+            ClientKey::Hamming(_) => KeyKind::Hamming,
+            ClientKey::Arith(_) => KeyKind::Arith,
+            ClientKey::Beaver(_) => KeyKind::Beaver,
+            ClientKey::Point(_) => KeyKind::Point,
+        }
+    }
+
+    // Whether the key is of the kind in `layout` and has dimensions of its
+    // lengths, with, for interval keys, its number of boundary pairs.
+    fn fits(&self, layout: &KeyLayout) -> bool {
+        if self.kind() != layout.kind {
+            return false;
+        }
+        match self {
+            ClientKey::Box(key) | ClientKey::BoxUnion(key) => {
+                key.len() == layout.dim_lens.len()
+                    && key.iter().zip(&layout.dim_lens).zip(&layout.pairs_per_dim).all(|((dim_keys, &len), &n_pairs)| {
+                        dim_keys.len() == n_pairs
+                            && dim_keys.iter().all(|(left, right)| left.domain_size() == len && right.domain_size() == len)
+                    })
+            }
+            ClientKey::Hamming(key) => key.dim_lens() == layout.dim_lens,
+            ClientKey::Arith(_) | ClientKey::Beaver(_) | ClientKey::Point(_) => {
+                let dims = self.arith_dims().unwrap();
+                dims.len() == layout.dim_lens.len()
+                    && dims.iter().zip(&layout.dim_lens).all(|(keys, &len)| {
+                        keys.iter().all(|k| k.key.domain_size() == len && k.val_words.len() == len)
+                    })
+            }
+        }
+    }
+
+    // Whether the key fits `layout` and is the share with `key_idx`, and
+    // for the Beaver backend, has `n_triples` triples in each group along
+    // with as many to check them against.
+    fn well_formed(&self, key_idx: bool, layout: &KeyLayout, n_triples: usize) -> bool {
+        if !self.fits(layout) {
+            return false;
+        }
+        match self {
+            ClientKey::Box(key) | ClientKey::BoxUnion(key) => {
+                key.iter().flatten().all(|(left, right)| left.key_idx == key_idx && right.key_idx == key_idx)
+            }
+            ClientKey::Hamming(key) => key.key_idx == key_idx,
+            ClientKey::Beaver(key) if !(key.triples.well_formed(key_idx, 2 * n_triples) && key.triples_last.well_formed(key_idx, 2 * n_triples)) => false,
+            ClientKey::Arith(_) | ClientKey::Beaver(_) | ClientKey::Point(_) => {
+                self.arith_dims().unwrap().iter().flatten().all(|k| k.key.key_idx == key_idx)
+            }
+        }
+    }
 
+    // This server's shares of the bits of the endpoints of the key's
+    // intervals: on each dimension, for each boundary pair, those of the
+    // left endpoint and then those of the right one. Empty for keys
+    // without intervals.
+    fn endpoint_shares(&self) -> Vec<bool> {
+        let mut bits = vec![];
+        match self {
+            ClientKey::Box(key) | ClientKey::BoxUnion(key) => {
+                for (left, right) in key.iter().flatten() {
+                    bits.extend(&left.alpha_share);
+                    bits.extend(&right.alpha_share);
+                }
+            }
+            ClientKey::Arith(_) | ClientKey::Beaver(_) => {
+                for keys in self.arith_dims().unwrap() {
+                    bits.extend(&keys[0].key.alpha_share);
+                    bits.extend(&keys[1].key.alpha_share);
+                }
+            }
+            ClientKey::Hamming(_) | ClientKey::Point(_) => {}
+        }
+        bits
+    }
+
+    // The key's boundary keys on dimension `d`, each with the side it was
+    // generated with, in the order of `KeyCollection::boundary_states`.
+    fn boundary_keys(&self, d: usize) -> Vec<(bool, &ibDCFKey)> {
+        match self {
+            ClientKey::Box(key) | ClientKey::BoxUnion(key) => {
+                key[d].iter().flat_map(|(left, right)| vec![(true, left), (false, right)]).collect()
+            }
+            ClientKey::Arith(_) | ClientKey::Beaver(_) => {
+                let keys = self.arith_dims().unwrap().swap_remove(d);
+                vec![(true, &keys[0].key), (false, &keys[1].key)]
+            }
+            // A point key is on the tree of a left boundary; see
+            // `ArithDCFKey::gen_point`.
+            ClientKey::Point(key) => vec![(true, &key.key)],
+            ClientKey::Hamming(_) => vec![],
+        }
+    }
+}
+
+/// The kinds of `ClientKey`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyKind {
+    Box,
+    BoxUnion,
+    Hamming,
+    Arith,
+    Beaver,
+    Point,
+}
+
+/// What every client's submission to a collection looks like: the kind of
+/// key, the bit length of each dimension, for interval keys the number of
+/// boundary pairs on each dimension, the largest weight a client may have
+/// (one if clients have no weights) and how many attributes. The servers
+/// take it from their configuration (see `config::Config::key_layout`),
+/// not from the clients.
+#[derive(Clone, Debug)]
+pub struct KeyLayout {
+    pub kind: KeyKind,
+    pub dim_lens: Vec<usize>,
+    pub pairs_per_dim: Vec<usize>,
+    pub max_weight: u32,
+    pub n_attributes: usize,
+}
+
+/// One server's share of a client's weight: additive shares in both of the
+/// collection's groups (see `share_weight`), and XOR shares of the bits
+/// that `verify_keys` checks them against (see `share_weight_bits`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WeightShare<T, U> {
+    pub value: (T, U),
+    pub bits: Vec<bool>,
+}
+
+// What the crawl's garbled circuit tests for each client at one level.
+enum CrawlTest<'a> {
+    // Membership in a union of intervals, with this many boundary pairs on
+    // each dimension.
+    Box(Vec<usize>),
+    // Membership in a union of boxes, with this many dimensions and boxes.
+    BoxUnion(usize, usize),
+    // Membership in the ball, with paths of these lengths.
+    Hamming(Vec<usize>, &'a HammingBall),
+}
+
+impl CrawlTest<'_> {
+    fn run(
+        &self,
+        gc_sender: bool,
+        rng: &mut AesRng,
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        inputs: &[Vec<u16>]
+    ) -> Vec<bool> {
+        match self {
+            CrawlTest::Box(pairs_per_dim) if gc_sender => multiple_gb_membership_test(rng, channel, inputs, pairs_per_dim),
+            CrawlTest::Box(pairs_per_dim) => multiple_ev_membership_test(rng, channel, inputs, pairs_per_dim),
+            CrawlTest::BoxUnion(n_dims, n_boxes) if gc_sender => multiple_gb_box_union_test(rng, channel, inputs, *n_dims, *n_boxes),
+            CrawlTest::BoxUnion(n_dims, n_boxes) => multiple_ev_box_union_test(rng, channel, inputs, *n_dims, *n_boxes),
+            CrawlTest::Hamming(prefix_lens, ball) if gc_sender => multiple_gb_hamming_test(rng, channel, inputs, prefix_lens, &ball.radius, ball.symbol_bits),
+            CrawlTest::Hamming(prefix_lens, ball) => multiple_ev_hamming_test(rng, channel, inputs, prefix_lens, &ball.radius, ball.symbol_bits),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct KeyCollection<T,U>
 {
     depth: usize,
-    pub keys: Vec<(bool, Vec<(ibDCFKey, ibDCFKey)>)>,
-    frontier: Vec<TreeNode>,
+    layout: KeyLayout,
+    pub keys: Vec<(bool, ClientKey<T, U>)>,
+    // This server's share of each client's weight, or None for every
+    // client if the collection is unweighted.
+    weights: Vec<Option<WeightShare<T, U>>>,
+    // This server's shares of each client's auxiliary attributes.
+    attributes: Vec<Vec<U>>,
+    frontier: Vec<TreeNode<T, U>>,
     frontier_last: Vec<Result<U>>,
+    // This server's share of each client's membership bit in each node of
+    // `frontier_last`, kept for `tree_attributes`.
+    frontier_last_bits: Vec<Vec<bool>>,
+    // The ball around each client's point, for Hamming keys.
+    hamming_ball: Option<HammingBall>,
+    // How many triples each client with Beaver keys has in each group for
+    // the crawl, and how many of them the crawl has used.
+    beaver_triples: usize,
+    triples_used: (usize, usize),
+    // The servers' joint seed for the coefficients of `check_level`,
+    // chosen in `verify_keys`.
+    check_seed: prg::PrgSeed,
     rand_stream: prg::PrgStream,
     _phantom: PhantomData<(T, U)>,
 }
@@ -44,24 +309,725 @@ pub struct Result<T> {
 
 impl<T,U> KeyCollection<T,U>
 where
-    T: Share + Clone + std::fmt::Debug + PartialOrd + From<u32> + Send + Sync + TryFrom<Block> + Into<Block>,
-    U: Share + Clone + std::fmt::Debug + PartialOrd + From<u32> + Send + Sync + TryFrom<BlockPair> + Into<BlockPair>,
-    <U as TryFrom<BlockPair>>::Error: std::fmt::Debug,
+    T: Share + Clone + std::fmt::Debug + PartialOrd + From<u32> + Send + Sync + OtBlocks,
+    U: Share + Clone + std::fmt::Debug + PartialOrd + From<u32> + Send + Sync + OtBlocks,
 {
-    pub fn new(seed: &prg::PrgSeed, depth: usize) -> KeyCollection<T,U> {
+    /// An empty collection of clients whose submissions fit `layout`.
+    /// `seed` starts all of this server's randomness in the protocol with
+    /// the other server, which must not learn it: each server needs a
+    /// secret seed of its own, and a fixed one repeats a run exactly.
+    pub fn new(seed: &prg::PrgSeed, depth: usize, layout: KeyLayout) -> KeyCollection<T,U> {
         KeyCollection::<T,U> {
             depth,
+            layout,
             keys: vec![],
+            weights: vec![],
+            attributes: vec![],
             frontier: vec![],
             frontier_last: vec![],
+            frontier_last_bits: vec![],
+            hamming_ball: None,
+            beaver_triples: 0,
+            triples_used: (0, 0),
+            check_seed: prg::PrgSeed::zero(),
             rand_stream: seed.to_rng(),
             _phantom: PhantomData,
         }
     }
 
-    pub fn add_key(&mut self, key: Vec<(ibDCFKey, ibDCFKey)>) {
-        self.keys.push((true, key)); //TODO: come back and remove this bool
+    // A fresh RNG for garbling and OT, drawn from the collection's own
+    // stream, which the seed given to `new` starts. Protocol steps draw
+    // theirs on the calling thread before starting any others, so a
+    // collection with a fixed seed sends the same messages on every run.
+    fn step_rng(&mut self) -> AesRng {
+        let mut seed = [0u8; 16];
+        self.rand_stream.fill_bytes(&mut seed);
+        AesRng::from_seed(Block::from(seed))
+    }
 
+    /// Add a client's key. `weight` is this server's share of the client's
+    /// weight; a node's value is then the sum of the weights of the clients
+    /// in it rather than their number, and `verify_keys` disables clients
+    /// whose weight is above the layout's `max_weight`. `attributes` are this server's
+    /// shares of the client's auxiliary attributes, which `tree_attributes`
+    /// sums over the clients in each heavy leaf. The collection's layout
+    /// says whether clients have a weight and how many attributes; clients
+    /// whose submission does not fit it are disabled.
+    pub fn add_key(&mut self, key: ClientKey<T, U>, weight: Option<WeightShare<T, U>>, attributes: Vec<U>) {
+        let weight_fits = match &weight {
+            Some(w) => self.is_weighted() && w.bits.len() == 2 * weight_bits(self.layout.max_weight),
+            None => !self.is_weighted(),
+        };
+        let fits = key.fits(&self.layout)
+            && weight_fits
+            && attributes.len() == self.layout.n_attributes;
+        if fits {
+            self.keys.push((true, key));
+            self.weights.push(weight);
+            self.attributes.push(attributes);
+        } else {
+            self.keys.push((false, key));
+            self.weights.push(None);
+            self.attributes.push(vec![U::zero(); self.layout.n_attributes]);
+        }
+    }
+
+    /// Sets the ball within which a client with Hamming keys counts in a
+    /// node. Both servers must use the same ball.
+    pub fn set_hamming_ball(&mut self, ball: HammingBall) {
+        self.hamming_ball = Some(ball);
+    }
+
+    /// Sets how many triples, in each group, a client with Beaver keys must
+    /// have for the crawl; it sends as many again for the servers to check
+    /// them against in `verify_keys`. Both servers must use the same number.
+    pub fn set_beaver_triples(&mut self, n: usize) {
+        self.beaver_triples = n;
+    }
+
+    fn is_hamming(&self) -> bool {
+        self.layout.kind == KeyKind::Hamming
+    }
+
+    // Whether the servers count clients with arithmetic shares rather than
+    // with garbled circuits.
+    fn is_arith(&self) -> bool {
+        matches!(self.layout.kind, KeyKind::Arith | KeyKind::Beaver | KeyKind::Point)
+    }
+
+    fn is_weighted(&self) -> bool {
+        self.layout.max_weight > 1
+    }
+
+    // This server's share of client `i`'s weight. Unweighted clients have a
+    // public weight of one, which the garbler holds in full.
+    fn weight_share(&self, i: usize, gc_sender: bool) -> (T, U) {
+        match &self.weights[i] {
+            Some(w) => w.value.clone(),
+            None if gc_sender => (T::one(), U::one()),
+            None => (T::zero(), U::zero()),
+        }
+    }
+
+    // Bit length of each dimension, which is the same for every client.
+    fn dim_lens(&self) -> Vec<usize> {
+        self.layout.dim_lens.clone()
+    }
+
+    // Which dimensions of `node` still have bits left to crawl. Shorter
+    // dimensions finish early and are carried along unchanged afterwards.
+    fn active_dims(&self, node: &TreeNode<T, U>) -> Vec<bool> {
+        node.path
+            .iter()
+            .zip(self.dim_lens())
+            .map(|(dim_path, len)| dim_path.len() < len)
+            .collect()
+    }
+
+    // Each client's string for the membership test: for every dimension,
+    // the (left, right) output shares of each of its boundary pairs.
+    fn client_string(states: &[Vec<(EvalState, EvalState)>]) -> Vec<bool> {
+        let mut bits = vec![];
+        for dim in states {
+            for (left, right) in dim {
+                bits.push(left.y_bit ^ left.bit);
+                bits.push(right.y_bit ^ right.bit);
+            }
+        }
+        bits
+    }
+
+    // Each client's string for the test at `node`.
+    fn node_client_strings(&self, node: &TreeNode<T, U>) -> Vec<Vec<bool>> {
+        if !self.is_hamming() {
+            // Disabled clients have no states, but still need a string.
+            let string_len = 2 * self.layout.pairs_per_dim.iter().sum::<usize>();
+            return node
+                .key_states
+                .par_iter()
+                .zip(self.keys.par_iter())
+                .map(|(state, (live, _))| match live {
+                    true => Self::client_string(state),
+                    false => vec![false; string_len],
+                })
+                .collect();
+        }
+
+        let prefix_len = node.path.iter().map(|p| p.len()).sum();
+        self.keys
+            .par_iter()
+            .map(|(live, key)| match key {
+                ClientKey::Hamming(key) if *live => key.mismatch_share(&node.path),
+                // Disabled clients may not fit the path.
+                _ => vec![false; prefix_len],
+            })
+            .collect()
+    }
+
+    // This server's share of client `i`'s indicator of `node` on each
+    // dimension, in both groups: one minus the outputs of its two interval
+    // keys, where the first server holds the one, or its point key's output.
+    fn arith_indicators(&self, node: &TreeNode<T, U>, i: usize, gc_sender: bool) -> Vec<(T, U)> {
+        node.arith_states[i]
+            .iter()
+            .map(|states| match &self.keys[i].1 {
+                ClientKey::Point(_) => states[0].1.clone(),
+                _ => {
+                    let mut v = self.weight_share(i, gc_sender);
+                    v.sub(&states[0].1);
+                    v.sub(&states[1].1);
+                    v
+                }
+            })
+            .collect()
+    }
+
+    // This server's share of the number of live clients in each node of
+    // `level`, in the group that `pick` takes from the pair. A client is in
+    // a node if the product of its indicators is one; with one dimension
+    // that takes no talking to the other server, and otherwise the servers
+    // multiply with the clients' triples from `triples`, starting with
+    // triple `used`.
+    fn arith_counts<F, P, Q, S>(
+        &self,
+        level: &[TreeNode<T, U>],
+        gc_sender: bool,
+        used: usize,
+        pick: P,
+        triples: Q,
+        swap: S,
+    ) -> Vec<F>
+    where
+        F: Share + Send + Sync,
+        P: Fn(&(T, U)) -> F + Sync,
+        Q: Fn(&BeaverKey<T, U>) -> &crate::mpc::TripleShares<F> + Sync,
+        S: FnMut(&[F]) -> Vec<F>,
+    {
+        let live = (0..self.keys.len()).filter(|&i| self.keys[i].0).collect::<Vec<_>>();
+        if live.is_empty() {
+            return vec![F::zero(); level.len()];
+        }
+
+        // The shares are of the form value = first - second, so the second
+        // server negates its shares before multiplying and after.
+        let pick = &pick;
+        let factors = level
+            .par_iter()
+            .flat_map(|node| {
+                live.par_iter().map(move |&i| {
+                    self.arith_indicators(node, i, gc_sender)
+                        .iter()
+                        .map(|v| {
+                            let mut f = pick(v);
+                            if !gc_sender {
+                                f.negate();
+                            }
+                            f
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let per_node = self.dim_lens().len() - 1;
+        let triple = |j: usize, k: usize| match &self.keys[live[j % live.len()]].1 {
+            ClientKey::Beaver(key) => triples(key).get(used + (j / live.len()) * per_node + k),
+            _ => unreachable!("only Beaver keys multiply"),
+        };
+        let products = multiply_all(gc_sender, factors, triple, swap);
+
+        products
+            .par_chunks(live.len())
+            .map(|node_products| {
+                let mut sum = F::zero();
+                for p in node_products {
+                    sum.add_lazy(p);
+                }
+                sum.reduce();
+                if !gc_sender {
+                    sum.negate();
+                }
+                sum
+            })
+            .collect()
+    }
+
+    // How many triples of each client the crawl of `level` takes.
+    fn triples_needed(&self, level: &[TreeNode<T, U>]) -> usize {
+        level.len() * (self.dim_lens().len() - 1)
+    }
+
+    // The test for the children in `level`, which all have paths of the
+    // same lengths.
+    fn crawl_test(&self, level: &[TreeNode<T, U>]) -> CrawlTest<'_> {
+        match &self.hamming_ball {
+            Some(ball) if self.is_hamming() => {
+                let prefix_lens = match level.first() {
+                    Some(node) => node.path.iter().map(|p| p.len()).collect(),
+                    None => vec![0; ball.radius.len()],
+                };
+                CrawlTest::Hamming(prefix_lens, ball)
+            }
+            None if self.is_hamming() => panic!("Hamming keys need a ball; see set_hamming_ball"),
+            _ => {
+                let pairs_per_dim = self.layout.pairs_per_dim.clone();
+                match self.layout.kind {
+                    KeyKind::BoxUnion => CrawlTest::BoxUnion(pairs_per_dim.len(), pairs_per_dim[0]),
+                    _ => CrawlTest::Box(pairs_per_dim),
+                }
+            }
+        }
+    }
+
+    /// Checks with the other server that every client's keys fit the
+    /// collection's layout, and that on each dimension the client's
+    /// intervals hold no more points than a ball of radius `ball_size[i]`
+    /// (2 * `ball_size[i]` + 1 of them), and disables the clients whose keys
+    /// do not. The intervals are those whose endpoints the keys' shares of
+    /// `alpha` give; that the keys encode them, the servers check level by
+    /// level as they crawl (see `check_level`), before counting anything
+    /// from that level. The servers learn nothing else about the intervals.
+    /// Both servers call this after the last `add_key` and before
+    /// `tree_init`, and end up disabling the same clients. Returns how many
+    /// clients are disabled. Each box of a union of boxes must fit in the
+    /// ball on its own, so such a client covers at most as many points as
+    /// that many balls. Hamming keys only need the right lengths, since the
+    /// servers choose the radius themselves. The crawl also checks that
+    /// arithmetic keys output their comparisons, whatever their value
+    /// words, and that point keys output their point functions, and Beaver
+    /// triples are checked here, each against one of the client's spare
+    /// triples, as are the weights of a weighted collection (see
+    /// `check_weights`).
+    pub fn verify_keys(
+        &mut self,
+        gc_sender: bool,
+        ball_size: &[usize],
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>
+    ) -> usize {
+        if self.keys.is_empty() {
+            return 0;
+        }
+
+        // The coefficients of the crawl's checks must be chosen after the
+        // clients have sent their keys.
+        let mut rng = self.step_rng();
+        let mut check_seed = prg::PrgSeed::zero();
+        rand_core_06::RngCore::fill_bytes(&mut rng, &mut check_seed.key);
+        let other = Self::swap_bytes(gc_sender, channel, &check_seed.key);
+        check_seed.key.iter_mut().zip(&other).for_each(|(x1, x2)| *x1 ^= *x2);
+        self.check_seed = check_seed;
+
+        // The server with `gc_sender` holds the shares with `key_idx` false.
+        // A client may send a malformed share to one server only, so the
+        // servers disable the clients that either of them rejects.
+        let key_idx = !gc_sender;
+        let well_formed = self
+            .keys
+            .par_iter()
+            .map(|(live, key)| *live && key.well_formed(key_idx, &self.layout, self.beaver_triples))
+            .collect::<Vec<bool>>();
+        let well_formed_bytes = well_formed.iter().map(|&b| b as u8).collect::<Vec<u8>>();
+        let other_well_formed = Self::swap_bytes(gc_sender, channel, &well_formed_bytes);
+
+        let dim_lens = &self.layout.dim_lens;
+        let pairs_per_dim = match self.layout.kind {
+            KeyKind::Arith | KeyKind::Beaver => vec![1; dim_lens.len()],
+            _ => self.layout.pairs_per_dim.clone(),
+        };
+        let n_endpoint_bits = 2 * pairs_per_dim.iter().zip(dim_lens).map(|(n, len)| n * len).sum::<usize>();
+        let endpoints = self
+            .keys
+            .par_iter()
+            .zip(well_formed.par_iter())
+            .map(|((_, key), &ok)| match ok {
+                true => key.endpoint_shares().iter().map(|&b| b as u16).collect(),
+                false => vec![0; n_endpoint_bits],
+            })
+            .collect::<Vec<Vec<u16>>>();
+
+        let in_bounds = match self.layout.kind {
+            KeyKind::Hamming | KeyKind::Point => vec![true; endpoints.len()],
+            // Each box on its own, as if each pair had its own dimension.
+            KeyKind::BoxUnion => {
+                let n_boxes = pairs_per_dim.first().map_or(0, |&n| n);
+                let repeat = |v: &[usize]| v.iter().flat_map(|&x| vec![x; n_boxes]).collect::<Vec<_>>();
+                let pairs = vec![1; n_boxes * dim_lens.len()];
+                Self::check_widths(gc_sender, &repeat(dim_lens), &repeat(ball_size), &pairs, &endpoints, &mut rng, channel)
+            }
+            KeyKind::Box | KeyKind::Arith | KeyKind::Beaver => Self::check_widths(gc_sender, dim_lens, ball_size, &pairs_per_dim, &endpoints, &mut rng, channel),
+        };
+
+        let both_well_formed = (0..self.keys.len()).map(|i| well_formed[i] && other_well_formed[i] == 1).collect::<Vec<_>>();
+        let good_triples = match self.layout.kind {
+            KeyKind::Beaver => self.check_beaver_triples(gc_sender, &both_well_formed, channel),
+            _ => vec![true; self.keys.len()],
+        };
+        let good_weights = match self.is_weighted() {
+            true => self.check_weights(gc_sender, &both_well_formed, &mut rng, channel),
+            false => vec![true; self.keys.len()],
+        };
+
+        for (i, (live, _)) in self.keys.iter_mut().enumerate() {
+            if !(both_well_formed[i] && in_bounds[i] && good_triples[i] && good_weights[i]) {
+                *live = false;
+            }
+        }
+        self.keys.iter().filter(|(live, _)| !live).count()
+    }
+
+    // Whether the triples of each client in `live` check out against its
+    // spare ones in both groups (see `mpc::check_triples`), with
+    // coefficients from the joint `check_seed` on streams past those of
+    // `check_level`. Clients not in `live` do not take part.
+    fn check_beaver_triples(
+        &self,
+        gc_sender: bool,
+        live: &[bool],
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>
+    ) -> Vec<bool> {
+        let clients = (0..self.keys.len()).filter(|&i| live[i]).collect::<Vec<_>>();
+        let n = self.beaver_triples;
+        let jobs = clients
+            .par_iter()
+            .map(|&i| {
+                let key = match &self.keys[i].1 {
+                    ClientKey::Beaver(key) => key,
+                    _ => unreachable!("only Beaver keys have triples"),
+                };
+                let mut rng = self.check_seed.to_rng_at(1 << 63 | i as u64);
+                let mut coeffs = || {
+                    let (mut r, mut weight) = (<(T, U)>::zero(), <(T, U)>::zero());
+                    prg::FromRng::from_rng(&mut r, &mut rng);
+                    prg::FromRng::from_rng(&mut weight, &mut rng);
+                    (r, weight)
+                };
+                let (mut jobs_t, mut jobs_u) = (Vec::with_capacity(n), Vec::with_capacity(n));
+                for k in 0..n {
+                    let (r, weight) = coeffs();
+                    jobs_t.push((key.triples.get(k), key.triples.get(n + k), r.0, weight.0));
+                    jobs_u.push((key.triples_last.get(k), key.triples_last.get(n + k), r.1, weight.1));
+                }
+                (jobs_t, jobs_u)
+            })
+            .collect::<Vec<_>>();
+        let (jobs_t, jobs_u): (Vec<_>, Vec<_>) = jobs.into_iter().unzip();
+
+        let passed_t = check_triples(gc_sender, &jobs_t, |ours: &[T]| Self::swap_shares(gc_sender, channel, ours));
+        let passed_u = check_triples(gc_sender, &jobs_u, |ours: &[U]| Self::swap_shares(gc_sender, channel, ours));
+        let mut out = vec![false; self.keys.len()];
+        for (j, &i) in clients.iter().enumerate() {
+            out[i] = passed_t[j] && passed_u[j];
+        }
+        let failed = clients.len() - out.iter().filter(|&&ok| ok).count();
+        if failed > 0 {
+            println!("Disabled {:?} clients whose Beaver triples failed the check", failed);
+        }
+        out
+    }
+
+    // Whether the weight of each client in `live` is between zero and the
+    // layout's `max_weight`. The servers turn the XOR shares of the bits of
+    // the two numbers from `share_weight_bits` into additive shares, with
+    // one OT per bit in each group, and check that the first is the weight
+    // and the second `max_weight` minus it; both are below
+    // 2^`weight_bits`, which bounds the weight from both sides. The
+    // servers learn nothing about the weights of clients that pass.
+    fn check_weights(
+        &self,
+        gc_sender: bool,
+        live: &[bool],
+        rng: &mut AesRng,
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>
+    ) -> Vec<bool> {
+        let mut out = vec![false; self.keys.len()];
+        let clients = (0..self.keys.len()).filter(|&i| live[i]).collect::<Vec<_>>();
+        if clients.is_empty() {
+            return out;
+        }
+        let nbits = weight_bits(self.layout.max_weight);
+        let weights = clients.iter().map(|&i| self.weights[i].as_ref().unwrap()).collect::<Vec<_>>();
+        let bits = weights.iter().flat_map(|w| w.bits.iter().copied()).collect::<Vec<bool>>();
+        let powers = (0..bits.len()).map(|j| 1u32 << (j % nbits)).collect::<Vec<u32>>();
+        let powers_t = powers.iter().map(|&p| T::from(p)).collect::<Vec<T>>();
+        let powers_u = powers.iter().map(|&p| U::from(p)).collect::<Vec<U>>();
+        let products_t = Self::crawl_products(channel, rng, gc_sender, false, &bits, &powers_t);
+        let products_u = Self::crawl_products(channel, rng, gc_sender, false, &bits, &powers_u);
+
+        // The weight minus the first number, and `max_weight` minus the
+        // weight minus the second, with the evaluator's shares negated, so
+        // that the servers' shares are equal iff both are zero.
+        let max_weight = (T::from(self.layout.max_weight), U::from(self.layout.max_weight));
+        let mut ours = Vec::with_capacity(2 * clients.len());
+        for (c, w) in weights.iter().enumerate() {
+            let (mut weight, mut rest) = (w.value.clone(), w.value.clone());
+            if gc_sender {
+                rest.negate();
+                rest.add(&max_weight);
+            } else {
+                weight.negate();
+            }
+            for j in 0..2 * nbits {
+                let k = 2 * nbits * c + j;
+                let product = (products_t[k].clone(), products_u[k].clone());
+                if j < nbits {
+                    weight.sub(&product);
+                } else {
+                    rest.sub(&product);
+                }
+            }
+            ours.push(weight);
+            ours.push(rest);
+        }
+        let (ours_t, ours_u): (Vec<T>, Vec<U>) = ours.into_iter().unzip();
+        let theirs_t = Self::swap_shares(gc_sender, channel, &ours_t);
+        let theirs_u = Self::swap_shares(gc_sender, channel, &ours_u);
+        for (c, &i) in clients.iter().enumerate() {
+            out[i] = (2 * c..2 * c + 2).all(|k| ours_t[k] == theirs_t[k] && ours_u[k] == theirs_u[k]);
+        }
+        let failed = clients.len() - out.iter().filter(|&&ok| ok).count();
+        if failed > 0 {
+            println!("Disabled {:?} clients whose weights are out of range", failed);
+        }
+        out
+    }
+
+    // Whether, on each dimension, each client's intervals hold at most
+    // 2 * `ball_size[i]` + 1 points, given each client's shares of the bits
+    // of its intervals' endpoints, in the order of
+    // `ClientKey::endpoint_shares`.
+    fn check_widths(
+        gc_sender: bool,
+        dim_lens: &[usize],
+        ball_size: &[usize],
+        pairs_per_dim: &[usize],
+        endpoints: &[Vec<u16>],
+        rng: &mut AesRng,
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>
+    ) -> Vec<bool> {
+        // The widths of the intervals, compared in a garbled circuit.
+        let max_widths = ball_size.iter().map(|&size| 2 * size as u64 + 1).collect::<Vec<_>>();
+        let in_bounds_shares = if gc_sender {
+            multiple_gb_width_test(rng, channel, endpoints, pairs_per_dim, dim_lens, &max_widths)
+        } else {
+            multiple_ev_width_test(rng, channel, endpoints, pairs_per_dim, dim_lens, &max_widths)
+        };
+        let in_bounds_bytes = in_bounds_shares.iter().map(|&b| b as u8).collect::<Vec<u8>>();
+        let other_in_bounds = Self::swap_bytes(gc_sender, channel, &in_bounds_bytes);
+        in_bounds_bytes
+            .iter()
+            .zip(&other_in_bounds)
+            .map(|(a, b)| a ^ b == 1)
+            .collect()
+    }
+
+    // Each step of the crawl from `self.frontier` to `next`, its children,
+    // checked with the other server for every live client's keys (see
+    // `ibDCFKey::check_step`), along with the outputs of arithmetic keys
+    // (see `ArithDCFKey::check_values` and `check_point_values`); disables the clients whose keys fail and
+    // returns how many. The children of a node are contiguous in `next`,
+    // in the order of `make_children`. Every node of the frontier takes
+    // part, and a node whose prefix on a dimension is shared with another
+    // node is checked once for each, with different coefficients.
+    //
+    // This is interactive for every kind of key, including the arithmetic
+    // keys whose counts need none: the check multiplies XOR shares of bits
+    // (the bit of `alpha`, and the control bits that say which nodes are on
+    // its path) into coefficients, which takes an OT per bit, and the
+    // servers then compare their sums. It runs on every level, not once
+    // after the crawl, because a key that fails it could otherwise steer
+    // which nodes survive each prune before the servers catch it. With
+    // arithmetic keys on one dimension, a level with the check takes about
+    // five times as long as the evaluation of the keys alone (see
+    // `ibDCFbench`).
+    fn check_level(
+        &mut self,
+        gc_sender: bool,
+        next: &[TreeNode<T, U>],
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>
+    ) -> usize {
+        let live = (0..self.keys.len()).filter(|&i| self.keys[i].0).collect::<Vec<_>>();
+        if self.is_hamming() || self.frontier.is_empty() || live.is_empty() {
+            return 0;
+        }
+
+        // For each active dimension, its level, and the offset among a
+        // node's children of the child that differs from the first only
+        // in its bit on that dimension.
+        let parent = &self.frontier[0];
+        let level = parent.path.iter().map(|p| p.len()).sum::<usize>();
+        let mut steps = vec![];
+        for (d, active) in self.active_dims(parent).into_iter().enumerate() {
+            if active {
+                steps.push((d, parent.path[d].len(), 1 << steps.len()));
+            }
+        }
+        let n_children = 1 << steps.len();
+        debug_assert_eq!(next.len(), n_children * self.frontier.len());
+
+        let checks = live
+            .par_iter()
+            .map(|&i| {
+                let mut rng = self.check_seed.to_rng_at((level as u64) << 32 | i as u64);
+                let mut checks = vec![];
+                let mut values = ValueCheck::default();
+                for &(d, dim_level, offset) in &steps {
+                    let keys = self.keys[i].1.boundary_keys(d);
+                    let mut key_checks = keys.iter().map(|(_, key)| LevelCheck::new(key, dim_level)).collect::<Vec<_>>();
+                    for (parent, children) in self.frontier.iter().zip(next.chunks(n_children)) {
+                        let parent_states = self.boundary_states(parent, i, d);
+                        let first = self.boundary_states(&children[0], i, d);
+                        let second = self.boundary_states(&children[offset], i, d);
+                        for (k, ((side, key), check)) in keys.iter().zip(key_checks.iter_mut()).enumerate() {
+                            key.check_step(*side, parent_states[k], (first[k], second[k]), &mut rng, check);
+                        }
+                    }
+                    checks.extend(key_checks);
+
+                    // Arithmetic keys' outputs must also be those of their
+                    // comparisons or point functions, whatever their value
+                    // words.
+                    if let Some(dims) = self.keys[i].1.arith_dims() {
+                        for (parent, children) in self.frontier.iter().zip(next.chunks(n_children)) {
+                            for (k, (key, (side, _))) in dims[d].iter().zip(&keys).enumerate() {
+                                let states = (&children[0].arith_states[i][d][k], &children[offset].arith_states[i][d][k]);
+                                match self.layout.kind {
+                                    KeyKind::Point => key.check_point_values(states, &mut rng, &mut values),
+                                    _ => key.check_values(*side, &parent.arith_states[i][d][k], states, &mut rng, &mut values),
+                                }
+                            }
+                        }
+                    }
+                }
+                (checks, values)
+            })
+            .collect::<Vec<(Vec<LevelCheck>, ValueCheck<(T, U)>)>>();
+
+        // The servers multiply in the bits of `alpha` together; each
+        // client's checks then add up to a block that is zero, so that the
+        // servers' shares of it are equal, iff they all pass.
+        let alphas = checks.iter().flat_map(|(c, _)| c).map(|c| c.alpha).collect::<Vec<bool>>();
+        let paths = checks.iter().flat_map(|(c, _)| c).map(|c| c.path).collect::<Vec<u128>>();
+        let mut rng = self.step_rng();
+        let mut products = Self::bit_times_block(channel, &mut rng, gc_sender, &alphas, &paths).into_iter();
+        let sums = checks
+            .iter()
+            .map(|(client, _)| Block::from(client.iter().fold(0, |acc, c| acc ^ c.linear ^ products.next().unwrap())))
+            .collect::<Vec<Block>>();
+        let other_sums = Self::swap_blocks(gc_sender, channel, &sums);
+
+        // Likewise for the values, in both groups: the bits times their
+        // coefficients, taken from the linear part, leave shares that are
+        // equal iff the check passes, and are masked by the OTs otherwise.
+        let bits = checks.iter().flat_map(|(_, v)| &v.bits).map(|(bit, _)| *bit).collect::<Vec<bool>>();
+        let values_pass = if bits.is_empty() {
+            vec![true; live.len()]
+        } else {
+            let coeffs_t = checks.iter().flat_map(|(_, v)| &v.bits).map(|(_, c)| c.0.clone()).collect::<Vec<T>>();
+            let coeffs_u = checks.iter().flat_map(|(_, v)| &v.bits).map(|(_, c)| c.1.clone()).collect::<Vec<U>>();
+            let mut products_t = Self::crawl_products(channel, &mut rng, gc_sender, false, &bits, &coeffs_t).into_iter();
+            let mut products_u = Self::crawl_products(channel, &mut rng, gc_sender, false, &bits, &coeffs_u).into_iter();
+            let (sums_t, sums_u): (Vec<T>, Vec<U>) = checks
+                .iter()
+                .map(|(_, v)| {
+                    let (mut sum_t, mut sum_u) = v.linear.clone();
+                    for _ in &v.bits {
+                        sum_t.sub(&products_t.next().unwrap());
+                        sum_u.sub(&products_u.next().unwrap());
+                    }
+                    (sum_t, sum_u)
+                })
+                .unzip();
+            let other_sums_t = Self::swap_shares(gc_sender, channel, &sums_t);
+            let other_sums_u = Self::swap_shares(gc_sender, channel, &sums_u);
+            (0..live.len()).map(|j| sums_t[j] == other_sums_t[j] && sums_u[j] == other_sums_u[j]).collect()
+        };
+
+        let mut disabled = 0;
+        for (j, &i) in live.iter().enumerate() {
+            if sums[j] != other_sums[j] || !values_pass[j] {
+                self.keys[i].0 = false;
+                disabled += 1;
+            }
+        }
+        if disabled > 0 {
+            println!("Disabled {:?} clients whose keys failed the check of level {:?}", disabled, level);
+        }
+        disabled
+    }
+
+    // Client `i`'s states at `node` of its boundary keys on dimension `d`,
+    // in the order of `ClientKey::boundary_keys`.
+    fn boundary_states<'a>(&self, node: &'a TreeNode<T, U>, i: usize, d: usize) -> Vec<&'a EvalState> {
+        match self.layout.kind {
+            KeyKind::Box | KeyKind::BoxUnion => node.key_states[i][d].iter().flat_map(|(left, right)| vec![left, right]).collect(),
+            KeyKind::Arith | KeyKind::Beaver | KeyKind::Point => node.arith_states[i][d].iter().map(|s| &s.0).collect(),
+            KeyKind::Hamming => vec![],
+        }
+    }
+
+    // Sends `ours` to the other server and returns what it sent, which is
+    // the same length. The server with `gc_sender` sends first, so that
+    // neither blocks on a full buffer.
+    fn swap_bytes(
+        gc_sender: bool,
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        ours: &[u8]
+    ) -> Vec<u8> {
+        let mut theirs = vec![0u8; ours.len()];
+        if gc_sender {
+            channel.write_bytes(ours).unwrap();
+            channel.flush().unwrap();
+        }
+        channel.read_bytes(&mut theirs).unwrap();
+        if !gc_sender {
+            channel.write_bytes(ours).unwrap();
+            channel.flush().unwrap();
+        }
+        theirs
+    }
+
+    // Like `swap_bytes`, for blocks.
+    fn swap_blocks(
+        gc_sender: bool,
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        ours: &[Block]
+    ) -> Vec<Block> {
+        if gc_sender {
+            ours.iter().for_each(|b| channel.write_block(b).unwrap());
+            channel.flush().unwrap();
+        }
+        let theirs = (0..ours.len()).map(|_| channel.read_block().unwrap()).collect();
+        if !gc_sender {
+            ours.iter().for_each(|b| channel.write_block(b).unwrap());
+            channel.flush().unwrap();
+        }
+        theirs
+    }
+
+    // Like `swap_blocks`, for shares.
+    fn swap_shares<S: OtBlocks + Clone>(
+        gc_sender: bool,
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        ours: &[S]
+    ) -> Vec<S> {
+        let blocks = ours.iter().flat_map(|x| x.clone().to_blocks()).collect::<Vec<Block>>();
+        Self::swap_blocks(gc_sender, channel, &blocks)
+            .chunks(S::BLOCKS)
+            .map(S::from_blocks)
+            .collect()
+    }
+
+    // Aborts the crawl if the step to `next` would need more of each
+    // client's Beaver triples than `beaver_triples`, after `used` of them.
+    // Every client needs as many, so without them no client could be
+    // counted; the leader checks this before it asks for the step.
+    fn check_triples(&self, used: usize, next: &[TreeNode<T, U>]) {
+        let needed = used + self.triples_needed(next);
+        assert!(
+            self.layout.kind != KeyKind::Beaver || needed <= self.beaver_triples,
+            "the crawl needs {} Beaver triples from each client, which has only {}; raise beaver_triples",
+            needed,
+            self.beaver_triples
+        );
     }
 
     pub fn tree_init(&mut self) {
@@ -70,42 +1036,177 @@ where
             // value: T::zero(),
             key_states: vec![],
             // key_values: vec![],
+            arith_states: vec![],
         };
 
-        for k in &self.keys {
-            let mut root_states = vec![];
-            for interval_key in k.1.clone(){
-                root_states.push((interval_key.0.eval_init(), interval_key.1.eval_init()));
-            }
-            root.key_states.push(root_states);
+        // Disabled clients keep their place with empty states.
+        for (live, key) in &self.keys {
+            let arith_states = match key.arith_dims() {
+                Some(dims) if *live => dims.iter().map(|keys| keys.iter().map(|k| k.eval_init()).collect()).collect(),
+                _ => vec![],
+            };
+            root.arith_states.push(arith_states);
+
+            // Hamming keys need no evaluation state; the path is enough.
+            let key_states = match key.intervals() {
+                Some(key) if *live => key
+                    .iter()
+                    .map(|dim_keys| {
+                        dim_keys
+                            .iter()
+                            .map(|interval_key| (interval_key.0.eval_init(), interval_key.1.eval_init()))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            root.key_states.push(key_states);
         }
 
         assert!(self.keys.len() > 0);
-        for i in 0..self.keys[0].1.len(){
+        for _ in 0..self.dim_lens().len() {
             root.path.push(vec![]);
         }
 
         self.frontier.clear();
         self.frontier_last.clear();
+        self.frontier_last_bits.clear();
         self.frontier.push(root);
 
     }
 
-    fn make_tree_node(&self, parent: &TreeNode, search_string: &Vec<bool>) -> TreeNode {
-        let key_states = self
-            .keys
+    /// Like `tree_init`, but starts the frontier with every node one level
+    /// above the leaves, so that a single `tree_crawl_last` gives shares of
+    /// the whole histogram in one GC/OT batch. The servers expand every
+    /// node and check each level of the clients' keys with each other on
+    /// the way down, as the crawl does. The frontier holds every client's
+    /// states at every node, so this is only for small domains: it refuses
+    /// more than `MAX_FULL_DOMAIN_BITS` bits, or more than
+    /// `MAX_FULL_DOMAIN_STATES` client states, before allocating any.
+    pub fn tree_init_all(
+        &mut self,
+        gc_sender: bool,
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>
+    ) -> std::result::Result<(), String> {
+        // The prefix lengths after `depth - 1` crawls.
+        let total = self.dim_lens().iter().map(|&len| len.min(self.depth - 1)).sum::<usize>();
+        if total > MAX_FULL_DOMAIN_BITS {
+            return Err(format!(
+                "a domain of 2^{} nodes is too large to evaluate in full; the most is 2^{}",
+                total, MAX_FULL_DOMAIN_BITS
+            ));
+        }
+        let states = self.keys.len() << total;
+        if states > MAX_FULL_DOMAIN_STATES {
+            return Err(format!(
+                "{} clients on each of 2^{} nodes are too many to evaluate in full; the most is {} in all",
+                self.keys.len(), total, MAX_FULL_DOMAIN_STATES
+            ));
+        }
+
+        self.tree_init();
+        for _ in 1..self.depth {
+            self.tree_expand_checked(gc_sender, channel);
+        }
+        Ok(())
+    }
+
+    // Steps every client's states once in both directions on every active
+    // dimension; all children of `parent` are built from these.
+    fn expand_node(&self, parent: &TreeNode<T, U>) -> NodeSteps<T, U> {
+        let mut steps = NodeSteps { intervals: vec![], arith: vec![] };
+        if self.is_arith() {
+            steps.arith = self.step_live(&parent.arith_states, |keys, states| {
+                let keys = keys.iter().map(|k| k.arith_dims().unwrap()).collect::<Vec<_>>();
+                arith_both_batch(&keys, states)
+            });
+        } else if !self.is_hamming() {
+            steps.intervals = self.step_live(&parent.key_states, |keys, states| {
+                let keys = keys.iter().map(|k| k.intervals().unwrap()).collect::<Vec<_>>();
+                eval_both_batch(&keys, states)
+            });
+        }
+        steps
+    }
+
+    // Runs `step` on the keys and `states` of the live clients, in batches
+    // of `EVAL_BATCH_SIZE` clients in parallel. Disabled clients take no
+    // steps.
+    fn step_live<S, R, F>(&self, states: &[S], step: F) -> Vec<Vec<R>>
+    where
+        S: Sync,
+        R: Send,
+        F: Fn(&[&ClientKey<T, U>], &[&S]) -> Vec<Vec<R>> + Sync + Send,
+    {
+        self.keys
+            .par_chunks(EVAL_BATCH_SIZE)
+            .zip(states.par_chunks(EVAL_BATCH_SIZE))
+            .flat_map(|(keys, states)| {
+                let (live_keys, live_states): (Vec<_>, Vec<_>) = keys
+                    .iter()
+                    .zip(states)
+                    .filter(|((live, _), _)| *live)
+                    .map(|((_, k), s)| (k, s))
+                    .unzip();
+                let mut steps = step(&live_keys, &live_states).into_iter();
+                keys.iter()
+                    .map(|(live, _)| match live {
+                        true => steps.next().unwrap(),
+                        false => vec![],
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // The children of every node of the frontier, in order.
+    fn next_frontier(&self) -> Vec<TreeNode<T, U>> {
+        self.frontier
+            .par_iter()
+            .map(|node| self.make_children(node))
+            .flatten()
+            .collect()
+    }
+
+    fn make_children(&self, parent: &TreeNode<T, U>) -> Vec<TreeNode<T, U>> {
+        let steps = self.expand_node(parent);
+        let n_active = self.active_dims(parent).iter().filter(|&&a| a).count();
+        all_bit_vectors(n_active)
+            .iter()
+            .map(|s| self.make_tree_node(parent, &steps, s))
+            .collect()
+    }
+
+    fn make_tree_node(&self, parent: &TreeNode<T, U>, steps: &NodeSteps<T, U>, search_string: &Vec<bool>) -> TreeNode<T, U> {
+        let key_states = parent
+            .key_states
             .par_iter()
-            .enumerate()
-            .map(|(i, key)| {
-                let ev = eval_str(&key.1, &parent.key_states[i], search_string);
-                ev
+            .zip(steps.intervals.par_iter())
+            .zip(self.keys.par_iter())
+            .map(|((states, client_steps), (live, _))| match live {
+                true => child_states(states, client_steps, search_string),
+                false => vec![],
             })
             .collect();
+        let arith_states = parent
+            .arith_states
+            .par_iter()
+            .zip(steps.arith.par_iter())
+            .zip(self.keys.par_iter())
+            .map(|((states, client_steps), (live, _))| match live {
+                true => child_states(states, client_steps, search_string),
+                false => vec![],
+            })
+            .collect();
+        let active = self.active_dims(parent);
 
         let mut new_path = vec![];
-        for (i, dim_path) in parent.path.iter().enumerate(){
+        let mut bits = search_string.iter();
+        for (dim_path, active) in parent.path.iter().zip(active) {
             let mut new_dim_path = dim_path.clone();
-            new_dim_path.push(search_string[i]);
+            if active {
+                new_dim_path.push(*bits.next().unwrap());
+            }
             new_path.push(new_dim_path)
         }
 
@@ -114,6 +1215,7 @@ where
             // value: child_val,
             key_states,
             // key_values : vec![],
+            arith_states,
         };
         child
     }
@@ -376,38 +1478,30 @@ where
         let start = Instant::now();
 
         // 1. Prepare next frontier (parallel tree expansion)
-        let next_frontier = self
-            .frontier
-            .par_iter()
-            .map(|node| {
-                let mut children = vec![];
-                let search_strings = all_bit_vectors(node.key_states[0].len());
-                for s in search_strings {
-                    children.push(self.make_tree_node(node, &s));
-                }
-                children
-            })
-            .flatten()
-            .collect::<Vec<TreeNode>>();
+        let next_frontier = self.next_frontier();
+        let used = self.triples_used.0;
+        self.check_triples(used, &next_frontier);
+        self.check_level(gc_sender, &next_frontier, &mut *channels[0]);
+
+        if self.is_arith() {
+            let channel = &mut *channels[0];
+            let results = self.arith_counts(&next_frontier, gc_sender, used, |v| v.0.clone(), |k| &k.triples, |ours: &[T]| {
+                Self::swap_shares(gc_sender, channel, ours)
+            });
+            println!("Arithmetic shares - {:?}", start.elapsed());
+            self.triples_used.0 += self.triples_needed(&next_frontier);
+            self.frontier = next_frontier;
+            return results;
+        }
 
         let node_client_string: Vec<Vec<Vec<bool>>> = next_frontier
             .par_iter()
-            .map(|node| {
-                node.key_states
-                    .par_iter()
-                    .map(|state| {
-                        let mut left_bits: Vec<bool> = state.iter()
-                            .map(|(left, _)| left.y_bit ^ left.bit)
-                            .collect();
-                        let mut right_bits: Vec<bool> = state.iter()
-                            .map(|(_, right)| right.y_bit ^ right.bit)
-                            .collect();
-                        left_bits.append(&mut right_bits);
-                        left_bits
-                    })
-                    .collect()
-            })
+            .map(|node| self.node_client_strings(node))
             .collect();
+        let mut rngs = (0..channels.len()).map(|_| self.step_rng()).collect::<Vec<_>>().into_iter();
+        let test = self.crawl_test(&next_frontier);
+        let nclients = self.keys.len();
+        let weighted = self.is_weighted();
 
         let non_mpc = start.elapsed();
         println!("Tree searching and FSS - {:?}", non_mpc);
@@ -426,51 +1520,17 @@ where
                 let start_idx = i * chunk_size;
                 let end_idx = std::cmp::min(start_idx + chunk_size, all_client_strings.len());
                 let chunk = all_client_strings[start_idx..end_idx].to_vec();
+                let chunk_weights = (start_idx..end_idx)
+                    .map(|j| self.weight_share(j % nclients, gc_sender))
+                    .collect::<Vec<_>>();
+                let test = &test;
 
+                let mut rng = rngs.next().unwrap();
                 handles.push(s.spawn(move |_| {
-                    let mut rng = AesRng::new();
                     let mut channel = (*channel).clone();
-                    let bin_shares = if gc_sender {
-                        multiple_gb_equality_test(&mut rng, &mut channel, &chunk)
-                    } else {
-                        multiple_ev_equality_test(&mut rng, &mut channel, &chunk)
-                    };
-                    let mut node_vals = vec![];
-                    if gc_sender{
-                        let mut all_shares = Vec::with_capacity(bin_shares.len());
-                        for i in 0..bin_shares.len() {
-                            let r0 = T::random();
-                            let mut r1 = r0.clone();
-                            r1.add(&T::one());
-                            node_vals.push(r1.clone());
-                            let r0_block: Block = r0.try_into().expect("Conversion failed");
-                            let r1_block: Block = r1.try_into().expect("Conversion failed");
-                            if bin_shares[i] {
-                                all_shares.push((r0_block, r1_block));
-                            } else {
-                                all_shares.push((r1_block, r0_block));
-                            }
-                        }
-                        let mut ot = OtSender::init(&mut channel, &mut rng).unwrap();
-                        ot.send(&mut channel, all_shares.as_slice(), &mut rng).map_err(|e| {
-                            println!("Error in tree_crawl ot send")
-                        }).unwrap();
-                    }
-                    else{
-                        let mut ot = OtReceiver::init(&mut channel, &mut rng).unwrap();
-                        let out_blocks = ot.receive(&mut channel, bin_shares.as_slice(), &mut rng).unwrap();
-                        node_vals = out_blocks.into_iter()
-                            .map(|b| {
-                                T::try_from(b)
-                                    .map_err(|e| {
-                                        // eprintln!("Conversion error: {:?}", e);  // Changed to {:?}
-                                        // e
-                                    })
-                                    .unwrap()
-                            })
-                            .collect();
-                    }
-                    node_vals
+                    let bin_shares = test.run(gc_sender, &mut rng, &mut channel, &chunk);
+                    let weights = chunk_weights.iter().map(|w| w.0.clone()).collect::<Vec<T>>();
+                    Self::crawl_products(&mut channel, &mut rng, gc_sender, weighted, &bin_shares, &weights)
                 }));
             }
 
@@ -781,38 +1841,35 @@ where
         let start = Instant::now();
 
         // 1. Prepare next frontier (parallel tree expansion)
-        let next_frontier = self
-            .frontier
-            .par_iter()
-            .map(|node| {
-                let mut children = vec![];
-                let search_strings = all_bit_vectors(node.key_states[0].len());
-                for s in search_strings {
-                    children.push(self.make_tree_node(node, &s));
-                }
-                children
-            })
-            .flatten()
-            .collect::<Vec<TreeNode>>();
+        let next_frontier = self.next_frontier();
+        let used = self.triples_used.1;
+        self.check_triples(used, &next_frontier);
+        self.check_level(gc_sender, &next_frontier, &mut *channels[0]);
+
+        if self.is_arith() {
+            let channel = &mut *channels[0];
+            let results = self.arith_counts(&next_frontier, gc_sender, used, |v| v.1.clone(), |k| &k.triples_last, |ours: &[U]| {
+                Self::swap_shares(gc_sender, channel, ours)
+            });
+            println!("Arithmetic shares - {:?}", start.elapsed());
+            self.triples_used.1 += self.triples_needed(&next_frontier);
+            self.frontier_last = next_frontier
+                .iter()
+                .zip(&results)
+                .map(|(node, value)| Result { path: node.path.clone(), value: value.clone() })
+                .collect();
+            self.frontier_last_bits = vec![vec![]; results.len()];
+            return results;
+        }
 
         let node_client_string: Vec<Vec<Vec<bool>>> = next_frontier
             .par_iter()
-            .map(|node| {
-                node.key_states
-                    .par_iter()
-                    .map(|state| {
-                        let mut left_bits: Vec<bool> = state.iter()
-                            .map(|(left, _)| left.y_bit ^ left.bit)
-                            .collect();
-                        let mut right_bits: Vec<bool> = state.iter()
-                            .map(|(_, right)| right.y_bit ^ right.bit)
-                            .collect();
-                        left_bits.append(&mut right_bits);
-                        left_bits
-                    })
-                    .collect()
-            })
+            .map(|node| self.node_client_strings(node))
             .collect();
+        let mut rngs = (0..channels.len()).map(|_| self.step_rng()).collect::<Vec<_>>().into_iter();
+        let test = self.crawl_test(&next_frontier);
+        let nclients = self.keys.len();
+        let weighted = self.is_weighted();
 
         let non_mpc = start.elapsed();
         println!("Tree searching and FSS - {:?}", non_mpc);
@@ -821,6 +1878,7 @@ where
             .iter()
             .flat_map(|node| node.iter().map(|client| client.iter().map(|&b| b as u16).collect::<Vec<u16>>()))
             .collect();
+        let mut all_bits = Vec::with_capacity(all_client_strings.len());
         let all_node_vals = crossbeam::scope(|s| {
             let mut results = vec![];
             let mut handles = vec![];
@@ -831,55 +1889,25 @@ where
                 let start_idx = i * chunk_size;
                 let end_idx = std::cmp::min(start_idx + chunk_size, all_client_strings.len());
                 let chunk = all_client_strings[start_idx..end_idx].to_vec();
+                let chunk_weights = (start_idx..end_idx)
+                    .map(|j| self.weight_share(j % nclients, gc_sender))
+                    .collect::<Vec<_>>();
+                let test = &test;
 
+                let mut rng = rngs.next().unwrap();
                 handles.push(s.spawn(move |_| {
-                    let mut rng = AesRng::new();
                     let mut channel = (*channel).clone();
-                    let bin_shares = if gc_sender {
-                        multiple_gb_equality_test(&mut rng, &mut channel, &chunk)
-                    } else {
-                        multiple_ev_equality_test(&mut rng, &mut channel, &chunk)
-                    };
-                    let mut node_vals = vec![];
-                    if gc_sender{
-                        let mut all_shares = Vec::with_capacity(bin_shares.len());
-                        for i in 0..bin_shares.len() {
-                            let r0 = U::random();
-                            let mut r1 = r0.clone();
-                            r1.add(&U::one());
-                            node_vals.push(r1.clone());
-                            let r0_block: BlockPair = r0.try_into().expect("Conversion failed");
-                            let r1_block: BlockPair = r1.try_into().expect("Conversion failed");
-                            if bin_shares[i] {
-                                all_shares.push((r0_block.0[0], r1_block.0[0]));
-                                all_shares.push((r0_block.0[1], r1_block.0[1]));
-                            } else {
-                                all_shares.push((r1_block.0[0], r0_block.0[0]));
-                                all_shares.push((r1_block.0[1], r0_block.0[1]));
-                            }
-                        }
-                        let mut ot = OtSender::init(&mut channel, &mut rng).unwrap();
-                        ot.send(&mut channel, all_shares.as_slice(), &mut rng).map_err(|e| {
-                            println!("Error in tree_crawl ot send")
-                        }).unwrap();
-                    }
-                    else{
-                        let mut ot = OtReceiver::init(&mut channel, &mut rng).unwrap();
-                        let doubled_binary_shares = bin_shares.iter().flat_map(|&b| [b, b]).collect::<Vec<bool>>();
-                        let out_blocks = ot.receive(&mut channel, doubled_binary_shares.as_slice(), &mut rng).unwrap();
-                        let mut i = 0;
-                        while i < out_blocks.len() - 1 {
-                            let val = U::try_from(BlockPair([out_blocks[i], out_blocks[i+1]])).map_err(|e| {}).unwrap();
-                            node_vals.push(val);
-                            i += 2;
-                        }
-                    }
-                    node_vals
+                    let bin_shares = test.run(gc_sender, &mut rng, &mut channel, &chunk);
+                    let weights = chunk_weights.iter().map(|w| w.1.clone()).collect::<Vec<U>>();
+                    let node_vals = Self::crawl_products(&mut channel, &mut rng, gc_sender, weighted, &bin_shares, &weights);
+                    (node_vals, bin_shares)
                 }));
             }
 
             for handle in handles {
-                results.extend(handle.join().unwrap());
+                let (node_vals, bin_shares) = handle.join().unwrap();
+                results.extend(node_vals);
+                all_bits.extend(bin_shares);
             }
 
             results
@@ -912,9 +1940,34 @@ where
                     value: results_by_node[i].clone(),
                 }
             }).collect::<Vec<Result<U>>>();
+        self.frontier_last_bits = all_bits.chunks(nclients).map(|c| c.to_vec()).collect();
         results_by_node
     }
 
+    /// Replaces the frontier with its children, evaluating every client's
+    /// keys as `tree_crawl` does, but without checking the keys or counting
+    /// the clients with the other server. Returns the size of the new
+    /// frontier. This is the part of a crawl that each server does on its
+    /// own, for benchmarks; a real crawl uses `tree_crawl`.
+    pub fn tree_expand(&mut self) -> usize {
+        self.frontier = self.next_frontier();
+        self.frontier.len()
+    }
+
+    /// Like `tree_expand`, but checks every client's keys on the new
+    /// frontier with the other server, as `tree_crawl` does, and disables
+    /// those that fail. Returns the number disabled.
+    pub fn tree_expand_checked(
+        &mut self,
+        gc_sender: bool,
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>
+    ) -> usize {
+        let next = self.next_frontier();
+        let disabled = self.check_level(gc_sender, &next, channel);
+        self.frontier = next;
+        disabled
+    }
+
     pub fn tree_prune(&mut self, alive_vals: &[bool]) {
         assert_eq!(alive_vals.len(), self.frontier.len());
 
@@ -935,6 +1988,7 @@ where
         for i in (0..alive_vals.len()).rev() {
             if !alive_vals[i] {
                 self.frontier_last.remove(i);
+                self.frontier_last_bits.remove(i);
             }
         }
 
@@ -942,10 +1996,14 @@ where
     }
 
 
-    pub fn keep_values(nclients: usize, threshold: &T, vals0: &[T], vals1: &[T]) -> Vec<bool> {
+    // `max_total` bounds any node's value: the number of clients, times the
+    // maximum weight in a weighted collection. It must fit in 32 bits.
+    pub fn keep_values(max_total: usize, threshold: &T, vals0: &[T], vals1: &[T]) -> std::result::Result<Vec<bool>, String> {
         assert_eq!(vals0.len(), vals1.len());
 
-        let nclients = T::from(nclients as u32);
+        let max_total = u32::try_from(max_total)
+            .map(T::from)
+            .map_err(|_| format!("a total weight of {} does not fit in 32 bits", max_total))?;
         let mut keep = vec![];
         for i in 0..vals0.len() {
             let mut v = T::zero();
@@ -953,20 +2011,24 @@ where
             v.sub(&vals1[i]);
             // println!("-> {:?} {:?} {:?}", v, *threshold, nclients);
 
-            debug_assert!(v <= nclients);
+            debug_assert!(v <= max_total);
 
             // Keep nodes that are above threshold
             // println!("{:?}",v);
             keep.push(v >= *threshold);
         }
 
-        keep
+        Ok(keep)
     }
 
-    pub fn keep_values_last(nclients: usize, threshold: &U, vals0: &[U], vals1: &[U]) -> Vec<bool> {
+    // `max_total` bounds any node's value: the number of clients, times the
+    // maximum weight in a weighted collection. It must fit in 32 bits.
+    pub fn keep_values_last(max_total: usize, threshold: &U, vals0: &[U], vals1: &[U]) -> std::result::Result<Vec<bool>, String> {
         assert_eq!(vals0.len(), vals1.len());
 
-        let nclients = U::from(nclients as u32);
+        let max_total = u32::try_from(max_total)
+            .map(U::from)
+            .map_err(|_| format!("a total weight of {} does not fit in 32 bits", max_total))?;
         let mut keep = vec![];
         for i in 0..vals0.len() {
             let mut v = U::zero();
@@ -978,17 +2040,244 @@ where
             v.sub(&v1);
             // println!("-> {:?} {:?} {:?}", v, *threshold, nclients);
 
-            debug_assert!(v <= nclients);
+            debug_assert!(v <= max_total);
 
             // Keep nodes that are above threshold
             // println!("{:?}",v);
             keep.push(v >= *threshold);
         }
 
-        keep
+        Ok(keep)
+    }
+
+
+
+    // The sender's shares of bits[i] * values[i], where bits[i] is the XOR
+    // of the two servers' bits: one OT per block of each entry. The
+    // receiver gets the other share from `receive_products`, and the
+    // product is the sender's share minus the receiver's.
+    fn send_products<S: Share + OtBlocks>(
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        rng: &mut AesRng,
+        bits: &[bool],
+        values: &[S],
+    ) -> Vec<S> {
+        let mut out = Vec::with_capacity(values.len());
+        let mut all_shares = Vec::with_capacity(S::BLOCKS * values.len());
+        let mut masks = mask_stream(rng);
+        for (&bit, value) in bits.iter().zip(values) {
+            let mut r0 = S::zero();
+            r0.from_rng(&mut masks);
+            let mut r1 = r0.clone();
+            r1.add(value);
+            out.push(r1.clone());
+            for (b0, b1) in r0.to_blocks().into_iter().zip(r1.to_blocks()) {
+                if bit {
+                    all_shares.push((b0, b1));
+                } else {
+                    all_shares.push((b1, b0));
+                }
+            }
+        }
+        let mut ot = OtSender::init(channel, rng).unwrap();
+        ot.send(channel, all_shares.as_slice(), rng).unwrap();
+        out
+    }
+
+    fn receive_products<S: OtBlocks>(
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        rng: &mut AesRng,
+        bits: &[bool],
+    ) -> Vec<S> {
+        let mut ot = OtReceiver::init(channel, rng).unwrap();
+        let block_bits = bits.iter().flat_map(|&b| vec![b; S::BLOCKS]).collect::<Vec<bool>>();
+        let out_blocks = ot.receive(channel, block_bits.as_slice(), rng).unwrap();
+        out_blocks.chunks(S::BLOCKS).map(S::from_blocks).collect()
+    }
+
+    // Shares (garbler's minus evaluator's) of bits[i] * x_i, where x_i is
+    // the sum of the two servers' `shares[i]`. Each server multiplies its
+    // own share in with one OT in each direction.
+    fn bit_times_share<S: Share + OtBlocks>(
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        rng: &mut AesRng,
+        gc_sender: bool,
+        bits: &[bool],
+        shares: &[S],
+    ) -> Vec<S> {
+        let (mut out, other) = if gc_sender {
+            let sent = Self::send_products(channel, rng, bits, shares);
+            (sent, Self::receive_products(channel, rng, bits))
+        } else {
+            // The evaluator's share enters the result with the opposite
+            // sign of the sender's, so it sends the negation.
+            let received = Self::receive_products(channel, rng, bits);
+            let negated = shares
+                .iter()
+                .map(|x| {
+                    let mut x = x.clone();
+                    x.negate();
+                    x
+                })
+                .collect::<Vec<S>>();
+            (received, Self::send_products(channel, rng, bits, &negated))
+        };
+        for (v, o) in out.iter_mut().zip(&other) {
+            v.add(o);
+        }
+        out
+    }
+
+    // Like `send_products`, for XOR shares of 128-bit values.
+    fn send_xor_products(
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        rng: &mut AesRng,
+        bits: &[bool],
+        values: &[u128],
+    ) -> Vec<u128> {
+        let mut out = Vec::with_capacity(values.len());
+        let mut all_shares = Vec::with_capacity(values.len());
+        let mut masks = mask_stream(rng);
+        for (&bit, &value) in bits.iter().zip(values) {
+            let r0 = masks.gen::<u128>();
+            let r1 = r0 ^ value;
+            out.push(r0);
+            if bit {
+                all_shares.push((Block::from(r1), Block::from(r0)));
+            } else {
+                all_shares.push((Block::from(r0), Block::from(r1)));
+            }
+        }
+        let mut ot = OtSender::init(channel, rng).unwrap();
+        ot.send(channel, all_shares.as_slice(), rng).unwrap();
+        out
+    }
+
+    fn receive_xor_products(
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        rng: &mut AesRng,
+        bits: &[bool],
+    ) -> Vec<u128> {
+        let mut ot = OtReceiver::init(channel, rng).unwrap();
+        let out_blocks = ot.receive(channel, bits, rng).unwrap();
+        out_blocks.into_iter().map(u128::from).collect()
+    }
+
+    // XOR shares of bits[i] * x_i, where both the bit and x_i are the XOR
+    // of the two servers' `bits[i]` and `values[i]`: one OT in each
+    // direction for the other server's value, as in `bit_times_share`.
+    fn bit_times_block(
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        rng: &mut AesRng,
+        gc_sender: bool,
+        bits: &[bool],
+        values: &[u128],
+    ) -> Vec<u128> {
+        if bits.is_empty() {
+            return vec![];
+        }
+        let (sent, received) = if gc_sender {
+            let sent = Self::send_xor_products(channel, rng, bits, values);
+            (sent, Self::receive_xor_products(channel, rng, bits))
+        } else {
+            let received = Self::receive_xor_products(channel, rng, bits);
+            (Self::send_xor_products(channel, rng, bits, values), received)
+        };
+        sent.iter().zip(&received).map(|(s, r)| s ^ r).collect()
+    }
+
+    // The crawl's shares of each client's membership bit times its weight.
+    // Without weights, the garbler's weight share is one and the
+    // evaluator's zero, so the OT from the garbler alone suffices.
+    fn crawl_products<S: Share + OtBlocks>(
+        channel: &mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>,
+        rng: &mut AesRng,
+        gc_sender: bool,
+        weighted: bool,
+        bits: &[bool],
+        weights: &[S],
+    ) -> Vec<S> {
+        if weighted {
+            Self::bit_times_share(channel, rng, gc_sender, bits, weights)
+        } else if gc_sender {
+            Self::send_products(channel, rng, bits, weights)
+        } else {
+            Self::receive_products(channel, rng, bits)
+        }
     }
 
+    /// For every leaf left in the frontier after `tree_prune_last`, this
+    /// server's shares of the sum of the attribute vectors of the clients
+    /// in that leaf. Run after the last prune and before `final_shares`.
+    pub fn tree_attributes(
+        &mut self,
+        gc_sender: bool,
+        channels: &mut [&mut SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>]
+    ) -> Vec<Vec<U>> {
+        let n_attributes = self.attributes.first().map_or(0, |a| a.len());
+        if n_attributes == 0 {
+            return vec![vec![]; self.frontier_last.len()];
+        }
+        let start = Instant::now();
+
+        // One entry per (leaf, client, attribute).
+        let mut bits = vec![];
+        let mut shares = vec![];
+        for leaf_bits in &self.frontier_last_bits {
+            for (client, &bit) in leaf_bits.iter().enumerate() {
+                for a in &self.attributes[client] {
+                    bits.push(bit);
+                    shares.push(a.clone());
+                }
+            }
+        }
+
+        let mut rngs = (0..channels.len()).map(|_| self.step_rng()).collect::<Vec<_>>().into_iter();
+        let products = crossbeam::scope(|s| {
+            let mut handles = vec![];
+            let chunk_size = (bits.len() + channels.len() - 1) / channels.len();
 
+            for (i, channel) in channels.iter().enumerate() {
+                let start_idx = std::cmp::min(i * chunk_size, bits.len());
+                let end_idx = std::cmp::min(start_idx + chunk_size, bits.len());
+                let chunk_bits = &bits[start_idx..end_idx];
+                let chunk_shares = &shares[start_idx..end_idx];
+
+                let mut rng = rngs.next().unwrap();
+                handles.push(s.spawn(move |_| {
+                    if chunk_bits.is_empty() {
+                        return vec![];
+                    }
+                    let mut channel = (*channel).clone();
+                    Self::bit_times_share(&mut channel, &mut rng, gc_sender, chunk_bits, chunk_shares)
+                }));
+            }
+
+            let mut results = vec![];
+            for handle in handles {
+                results.extend(handle.join().unwrap());
+            }
+            results
+        }).unwrap();
+
+        let nclients = self.keys.len();
+        let mut out = vec![];
+        for leaf in products.chunks(nclients * n_attributes) {
+            let mut sums = vec![U::zero(); n_attributes];
+            for (client, client_products) in leaf.chunks(n_attributes).enumerate() {
+                // Add in only live values
+                if self.keys[client].0 {
+                    for (sum, v) in sums.iter_mut().zip(client_products) {
+                        sum.add_lazy(v);
+                    }
+                }
+            }
+            out.push(sums);
+        }
+
+        println!("Attributes - {:?}", start.elapsed());
+        out
+    }
 
     pub fn final_shares(&self) -> Vec<Result<U>> {
         let mut alive = vec![];
@@ -1027,5 +2316,29 @@ where
 
         out
     }
+
+    pub fn final_attributes(attrs0: &[Vec<U>], attrs1: &[Vec<U>]) -> Vec<Vec<U>> {
+        assert_eq!(attrs0.len(), attrs1.len());
+
+        attrs0
+            .iter()
+            .zip(attrs1)
+            .map(|(a0, a1)| {
+                a0.iter()
+                    .zip(a1)
+                    .map(|(v0, v1)| {
+                        let mut v = U::zero();
+                        let mut v0 = v0.clone();
+                        let mut v1 = v1.clone();
+                        v0.reduce();
+                        v1.reduce();
+                        v.add(&v0);
+                        v.sub(&v1);
+                        v
+                    })
+                    .collect()
+            })
+            .collect()
+    }
 }
 
@@ -1,11 +1,67 @@
+use crate::collect::{KeyKind, KeyLayout, MAX_FULL_DOMAIN_BITS};
+use crate::hamming::HammingBall;
+use crate::prg::PrgSeed;
 use clap::{App, Arg};
 use serde_json::Value;
-use std::{fs, net::SocketAddr};
+use std::{convert::TryFrom, fs, net::SocketAddr};
+
+// What a client's point matches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    // Points in a box around it, within `ball_size` on each dimension.
+    LInf,
+    // Points in two dimensions within distance `ball_size` of it, up to the
+    // approximation of the disc by `n_boxes` boxes.
+    L2,
+    // Bit strings that differ from it in at most `ball_size` symbols of
+    // `symbol_bits` bits on each dimension.
+    Hamming,
+    // Only itself, for heavy hitters over one-dimensional strings.
+    Exact,
+}
+
+// How the servers combine a client's per-dimension membership in a node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    // A garbled circuit over the boundary keys' output bits.
+    GarbledCircuit,
+    // Beaver multiplication of arithmetic indicators, with `beaver_triples`
+    // triples from each client, plus as many for checking them.
+    Beaver,
+}
+
+// The groups that the servers share counts in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShareType {
+    // `FE` for the crawl and `FieldElm` for the last level.
+    Field,
+    // `RingElm`, the integers modulo 2^64, throughout.
+    Ring,
+}
 
 pub struct Config {
-    pub data_len: usize,
+    // Bit length and ball radius of each dimension. A single number in the
+    // JSON config applies to every dimension.
+    pub data_len: Vec<usize>,
     pub n_dims: usize,
-    pub ball_size: usize,
+    pub ball_size: Vec<usize>,
+    pub toroidal: Vec<bool>,
+    pub mode: Mode,
+    pub symbol_bits: usize,
+    pub n_boxes: usize,
+    pub backend: Backend,
+    pub beaver_triples: usize,
+    pub share_type: ShareType,
+    // Whether to evaluate every client's keys on the whole domain up front
+    // and crawl only the last level, and whether to then release every
+    // leaf's count instead of only those above the threshold.
+    pub full_domain: bool,
+    pub release_histogram: bool,
+    // Clients' weights are capped at this value. With the default of one,
+    // every client counts once and the collection is unweighted.
+    pub max_weight: u32,
+    // Length of each client's vector of auxiliary attributes (zero if none).
+    pub n_attributes: usize,
     pub addkey_batch_size: usize,
     pub num_sites: usize,
     pub threshold: f64,
@@ -15,17 +71,168 @@ pub struct Config {
     pub distribution: String
 }
 
+impl Config {
+    /// Number of levels in the crawl, i.e., the bit length of the longest
+    /// dimension.
+    pub fn depth(&self) -> usize {
+        *self.data_len.iter().max().unwrap()
+    }
+
+    /// The Beaver triples that each client's keys need for the crawl's step
+    /// from `n_nodes` nodes at `level` to their children: one per child
+    /// for each dimension after the first, as `collect::KeyCollection`
+    /// uses them.
+    pub fn triples_needed(&self, n_nodes: usize, level: usize) -> usize {
+        let active = self.data_len.iter().filter(|&&len| len > level).count();
+        (n_nodes << active) * (self.n_dims - 1)
+    }
+
+    /// The servers' ball around each client's point in Hamming mode.
+    pub fn hamming_ball(&self) -> Option<HammingBall> {
+        match self.mode {
+            Mode::LInf | Mode::L2 | Mode::Exact => None,
+            Mode::Hamming => Some(HammingBall {
+                radius: self.ball_size.clone(),
+                symbol_bits: self.symbol_bits,
+            }),
+        }
+    }
+
+    /// Whether one-dimensional, unweighted balls get arithmetic keys, which
+    /// need no garbled circuits: the servers add up arithmetic shares of
+    /// each client's membership instead.
+    pub fn arith_keys(&self) -> bool {
+        self.n_dims == 1
+            && self.mode == Mode::LInf
+            && !self.toroidal[0]
+            && self.max_weight == 1
+            && self.n_attributes == 0
+    }
+
+    /// What every client's submission looks like, which the servers hold
+    /// the clients to.
+    pub fn key_layout(&self) -> KeyLayout {
+        let kind = match self.mode {
+            Mode::Hamming => KeyKind::Hamming,
+            Mode::Exact => KeyKind::Point,
+            _ if self.arith_keys() => KeyKind::Arith,
+            _ if self.backend == Backend::Beaver => KeyKind::Beaver,
+            Mode::L2 => KeyKind::BoxUnion,
+            Mode::LInf => KeyKind::Box,
+        };
+        let pairs_per_dim = match kind {
+            KeyKind::Box => self.toroidal.iter().map(|&t| if t { 2 } else { 1 }).collect(),
+            KeyKind::BoxUnion => vec![self.n_boxes; self.n_dims],
+            _ => vec![],
+        };
+        KeyLayout {
+            kind,
+            dim_lens: self.data_len.clone(),
+            pairs_per_dim,
+            max_weight: self.max_weight,
+            n_attributes: self.n_attributes,
+        }
+    }
+}
+
 fn parse_ip(v: &Value, error_msg: &str) -> SocketAddr {
     v.as_str().expect(error_msg).parse().expect(error_msg)
 }
 
+// Either one number per dimension, or one number for all of them.
+fn parse_per_dim(v: &Value, n_dims: usize, error_msg: &str) -> Vec<usize> {
+    let out: Vec<usize> = match v.as_array() {
+        Some(dims) => dims
+            .iter()
+            .map(|d| d.as_u64().expect(error_msg) as usize)
+            .collect(),
+        None => vec![v.as_u64().expect(error_msg) as usize; n_dims],
+    };
+    assert_eq!(out.len(), n_dims, "{}: need one entry per dimension", error_msg);
+    out
+}
+
 pub fn get_config(filename: &str) -> Config {
     let json_data = &fs::read_to_string(filename).expect("Cannot open JSON file");
     let v: Value = serde_json::from_str(json_data).expect("Cannot parse JSON config");
 
-    let data_len: usize = v["data_len"].as_u64().expect("Can't parse data_len") as usize;
-    let n_dims: usize = v["n_dims"].as_u64().expect("Can't parse data_len") as usize;
-    let ball_size: usize = v["ball_size"].as_u64().expect("Can't parse data_len") as usize;
+    let n_dims: usize = v["n_dims"].as_u64().expect("Can't parse n_dims") as usize;
+    let data_len = parse_per_dim(&v["data_len"], n_dims, "Can't parse data_len");
+    let ball_size = parse_per_dim(&v["ball_size"], n_dims, "Can't parse ball_size");
+    let toroidal: Vec<bool> = match v["toroidal"].as_array() {
+        Some(dims) => dims
+            .iter()
+            .map(|d| d.as_bool().expect("Can't parse toroidal"))
+            .collect(),
+        None => vec![false; n_dims],
+    };
+    assert_eq!(toroidal.len(), n_dims, "toroidal needs one entry per dimension");
+    let mode = match v["mode"].as_str() {
+        Some("l_inf") | None => Mode::LInf,
+        Some("l2") => Mode::L2,
+        Some("hamming") => Mode::Hamming,
+        Some("exact") => Mode::Exact,
+        Some(m) => panic!("Unknown mode {}", m),
+    };
+    let symbol_bits: usize = match v["symbol_bits"].as_u64() {
+        Some(b) => b as usize,
+        None => 1,
+    };
+    assert!(symbol_bits >= 1, "symbol_bits must be at least one");
+    let n_boxes: usize = match v["n_boxes"].as_u64() {
+        Some(n) => n as usize,
+        None => 4,
+    };
+    assert!(n_boxes >= 1, "n_boxes must be at least one");
+    if mode == Mode::L2 {
+        assert_eq!(n_dims, 2, "the l2 mode needs two dimensions");
+        assert_eq!(ball_size[0], ball_size[1], "the l2 mode needs one radius");
+    }
+    let max_weight: u32 = match v["max_weight"].as_u64() {
+        Some(w) => u32::try_from(w).expect("max_weight must fit in 32 bits"),
+        None => 1,
+    };
+    assert!(max_weight >= 1, "max_weight must be at least one");
+    let n_attributes: usize = match v["n_attributes"].as_u64() {
+        Some(n) => n as usize,
+        None => 0,
+    };
+    let backend = match v["backend"].as_str() {
+        Some("gc") | None => Backend::GarbledCircuit,
+        Some("beaver") => Backend::Beaver,
+        Some(b) => panic!("Unknown backend {}", b),
+    };
+    let beaver_triples: usize = match v["beaver_triples"].as_u64() {
+        Some(n) => n as usize,
+        None => 1024,
+    };
+    let share_type = match v["share_type"].as_str() {
+        Some("field") | None => ShareType::Field,
+        Some("ring") => ShareType::Ring,
+        Some(t) => panic!("Unknown share_type {}", t),
+    };
+    let full_domain = v["full_domain"].as_bool().unwrap_or(false);
+    let release_histogram = v["release_histogram"].as_bool().unwrap_or(false);
+    assert!(full_domain || !release_histogram, "releasing the histogram needs full_domain");
+    if full_domain {
+        let depth = *data_len.iter().max().unwrap();
+        let bits = data_len.iter().map(|&len| len.min(depth - 1)).sum::<usize>();
+        assert!(
+            bits <= MAX_FULL_DOMAIN_BITS,
+            "full_domain evaluates at most {} bits, not {}",
+            MAX_FULL_DOMAIN_BITS,
+            bits
+        );
+    }
+    if mode == Mode::Exact {
+        assert_eq!(n_dims, 1, "the exact mode needs one dimension");
+        assert!(max_weight == 1 && n_attributes == 0, "the exact mode counts each client once");
+    }
+    if backend == Backend::Beaver {
+        assert_eq!(mode, Mode::LInf, "the beaver backend needs the l_inf mode");
+        assert!(!toroidal.iter().any(|&t| t), "the beaver backend needs non-toroidal dimensions");
+        assert!(max_weight == 1 && n_attributes == 0, "the beaver backend counts each client once");
+    }
     let addkey_batch_size: usize = v["addkey_batch_size"]
         .as_u64()
         .expect("Can't parse addkey_batch_size") as usize;
@@ -38,10 +245,21 @@ pub fn get_config(filename: &str) -> Config {
     let server1 = parse_ip(&v["server1"], "Can't parse server1 addr");
     let distribution: String = v["distribution"].as_str().expect("Can't parse distribution").to_string();
 
-    Config {
+    let cfg = Config {
         data_len,
         n_dims,
         ball_size,
+        toroidal,
+        mode,
+        symbol_bits,
+        n_boxes,
+        backend,
+        beaver_triples,
+        share_type,
+        full_domain,
+        release_histogram,
+        max_weight,
+        n_attributes,
         addkey_batch_size,
         num_sites,
         threshold,
@@ -49,10 +267,18 @@ pub fn get_config(filename: &str) -> Config {
         server0,
         server1,
         distribution
-    }
+    };
+    // Checking arithmetic keys' outputs takes a field: in the ring, a bad
+    // output can pass the check half of the time.
+    let kind = cfg.key_layout().kind;
+    assert!(
+        share_type == ShareType::Field || !matches!(kind, KeyKind::Arith | KeyKind::Beaver | KeyKind::Point),
+        "the ring share type cannot check arithmetic keys, which the exact mode, the beaver backend and unweighted one-dimensional l_inf balls use"
+    );
+    cfg
 }
 
-pub fn get_args(name: &str, get_server_id: bool, get_n_reqs: bool) -> (Config, i8, usize) {
+pub fn get_args(name: &str, get_server_id: bool, get_n_reqs: bool) -> (Config, i8, usize, Option<PrgSeed>) {
     let mut flags = App::new(name)
         .version("0.1")
         .author("Henry Corrigan-Gibbs <henrycg@csail.mit.edu>")
@@ -76,6 +302,13 @@ pub fn get_args(name: &str, get_server_id: bool, get_n_reqs: bool) -> (Config, i
                 .help("Zero-indexed ID of server")
                 .required(true)
                 .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("HEX")
+                .help("Secret seed of the server's randomness, as 32 hex digits, for repeating a run")
+                .takes_value(true),
         );
     }
 
@@ -103,9 +336,16 @@ pub fn get_args(name: &str, get_server_id: bool, get_n_reqs: bool) -> (Config, i
         n_reqs = flags.value_of("num_requests").unwrap().parse().unwrap();
     }
 
+    let seed = flags.value_of("seed").map(|hex| {
+        assert_eq!(hex.len(), 32, "--seed takes 32 hex digits");
+        let key = u128::from_str_radix(hex, 16).expect("--seed takes 32 hex digits");
+        PrgSeed { key: key.to_be_bytes() }
+    });
+
     (
         get_config(flags.value_of("config").unwrap()),
         server_id,
         n_reqs,
+        seed,
     )
 }
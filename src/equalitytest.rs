@@ -22,11 +22,122 @@ struct EQInputs<F> {
     pub evaluator_wires: BinaryBundle<F>,
 }
 
+/// Which function of the two parties' strings the circuit computes.
+#[derive(Clone, Copy)]
+enum Test<'a> {
+    /// The two strings are equal.
+    Equality,
+    /// The strings are a client's (left, right) boundary bits, grouped by
+    /// dimension with `pairs_per_dim[i]` pairs on dimension `i`. The output
+    /// is whether, on every dimension, some pair has both bits equal.
+    Membership(&'a [usize]),
+    /// Like `Membership`, but every one of the `n_dims` dimensions has
+    /// `n_boxes` pairs and pair `j` of each dimension belongs to box `j`.
+    /// The output is whether, for some box, the pairs on every dimension
+    /// have both bits equal.
+    BoxUnion { n_dims: usize, n_boxes: usize },
+    /// The strings are XOR shares of the endpoints of a client's boundary
+    /// pairs; see `multi_bin_width_shared`. The output is whether, on every
+    /// dimension, the intervals cover at most `max_widths[i]` points.
+    Width {
+        pairs_per_dim: &'a [usize],
+        dim_lens: &'a [usize],
+        max_widths: &'a [u64],
+    },
+    /// The strings are XOR shares of where a node's path differs from a
+    /// client's point, `prefix_lens[i]` bits on dimension `i`. The output is
+    /// whether, on every dimension, at most `radius[i]` symbols of
+    /// `symbol_bits` bits differ.
+    Hamming {
+        prefix_lens: &'a [usize],
+        radius: &'a [usize],
+        symbol_bits: usize,
+    },
+}
+
 pub fn multiple_gb_equality_test<C>(
     rng: &mut AesRng,
     channel: &mut C,
     inputs: &[Vec<u16>]
 ) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    gb_test(rng, channel, inputs, Test::Equality)
+}
+
+/// Garbler side of the interval-union membership test. Each input holds,
+/// for every dimension in turn, the (left, right) bits of each of that
+/// dimension's `pairs_per_dim[i]` boundary pairs.
+pub fn multiple_gb_membership_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    pairs_per_dim: &[usize],
+) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    gb_test(rng, channel, inputs, Test::Membership(pairs_per_dim))
+}
+
+/// Garbler side of the union-of-boxes membership test. Each input holds,
+/// for every dimension in turn, the (left, right) bits of the boundary pair
+/// of each of the `n_boxes` boxes.
+pub fn multiple_gb_box_union_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    n_dims: usize,
+    n_boxes: usize,
+) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    gb_test(rng, channel, inputs, Test::BoxUnion { n_dims, n_boxes })
+}
+
+/// Garbler side of the interval-width test. Each input holds, for every
+/// dimension `i` in turn and each of its `pairs_per_dim[i]` boundary pairs,
+/// this server's XOR shares of the `dim_lens[i]` bits of the left endpoint
+/// and then of the right one.
+pub fn multiple_gb_width_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    pairs_per_dim: &[usize],
+    dim_lens: &[usize],
+    max_widths: &[u64],
+) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    gb_test(rng, channel, inputs, Test::Width { pairs_per_dim, dim_lens, max_widths })
+}
+
+/// Garbler side of the Hamming-ball test. Each input holds this server's
+/// XOR shares of the mismatch bits of every dimension in turn, as given by
+/// `HammingKey::mismatch_share`.
+pub fn multiple_gb_hamming_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    prefix_lens: &[usize],
+    radius: &[usize],
+    symbol_bits: usize,
+) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    gb_test(rng, channel, inputs, Test::Hamming { prefix_lens, radius, symbol_bits })
+}
+
+fn gb_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    test: Test,
+) -> Vec<bool>
 where
     C: AbstractChannel + Clone,
 {
@@ -53,7 +164,7 @@ where
     // println!("time: {:?}", step2_time);
     // println!("Step 3");
 
-    let eq = fancy_equality(&mut gb, wires, num_tests).unwrap();
+    let eq = fancy_test(&mut gb, wires, num_tests, test).unwrap();
     gb.outputs(eq.wires()).unwrap();
     // let step3_time = start.elapsed() - step1_time - step2_time;
     // println!("time: {:?}", step3_time);
@@ -87,6 +198,77 @@ pub fn multiple_ev_equality_test<C>(
     channel: &mut C,
     inputs: &[Vec<u16>]
 ) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    ev_test(rng, channel, inputs, Test::Equality)
+}
+
+/// Evaluator side of the interval-union membership test; see
+/// `multiple_gb_membership_test`.
+pub fn multiple_ev_membership_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    pairs_per_dim: &[usize],
+) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    ev_test(rng, channel, inputs, Test::Membership(pairs_per_dim))
+}
+
+/// Evaluator side of the union-of-boxes membership test; see
+/// `multiple_gb_box_union_test`.
+pub fn multiple_ev_box_union_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    n_dims: usize,
+    n_boxes: usize,
+) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    ev_test(rng, channel, inputs, Test::BoxUnion { n_dims, n_boxes })
+}
+
+/// Evaluator side of the interval-width test; see
+/// `multiple_gb_width_test`.
+pub fn multiple_ev_width_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    pairs_per_dim: &[usize],
+    dim_lens: &[usize],
+    max_widths: &[u64],
+) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    ev_test(rng, channel, inputs, Test::Width { pairs_per_dim, dim_lens, max_widths })
+}
+
+pub fn multiple_ev_hamming_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    prefix_lens: &[usize],
+    radius: &[usize],
+    symbol_bits: usize,
+) -> Vec<bool>
+where
+    C: AbstractChannel + Clone,
+{
+    ev_test(rng, channel, inputs, Test::Hamming { prefix_lens, radius, symbol_bits })
+}
+
+fn ev_test<C>(
+    rng: &mut AesRng,
+    channel: &mut C,
+    inputs: &[Vec<u16>],
+    test: Test,
+) -> Vec<bool>
 where
     C: AbstractChannel + Clone,
 {
@@ -95,7 +277,7 @@ where
     let input_vec = inputs.to_vec().into_iter().flatten().collect::<Vec<u16>>();
     let ev_in = input_vec.as_slice();
     let wires = ev_set_fancy_inputs(&mut ev, &ev_in, num_tests);
-    let eq = fancy_equality(&mut ev, wires, num_tests).unwrap();
+    let eq = fancy_test(&mut ev, wires, num_tests, test).unwrap();
     let output = ev.outputs(eq.wires()).unwrap().unwrap();
     let results = output.iter().map(|r| *r == 1).collect();
 
@@ -189,6 +371,303 @@ pub trait BinaryGadgets: FancyBinary + BundleGadgets {
         }
         Ok(BinaryBundle::new(results))
     }
+
+    /// Like `multi_bin_eq_bundles_shared`, but each string is a list of
+    /// (left, right) bit pairs grouped by dimension, and the result is
+    /// AND over dimensions of OR over that dimension's pairs of whether
+    /// both bits of the pair are equal.
+    fn multi_bin_membership_shared(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+        num_tests: usize,
+        pairs_per_dim: &[usize],
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        assert_eq!(
+            x.wires().len(),
+            y.wires().len() + num_tests,
+            "each string in x must have one extra mask bit"
+        );
+        let string_len = 2 * pairs_per_dim.iter().sum::<usize>();
+        assert_eq!(y.wires().len(), string_len * num_tests);
+
+        let mut results = Vec::with_capacity(num_tests);
+
+        for i in 0..num_tests {
+            let x_start = i * (string_len + 1);
+            let y_start = i * string_len;
+
+            let mut pos = 0;
+            let mut dims = Vec::with_capacity(pairs_per_dim.len());
+            for &n_pairs in pairs_per_dim {
+                // OR(a, b, ...) = NOT(AND(NOT a, NOT b, ...))
+                let mut not_in_pair = Vec::with_capacity(n_pairs);
+                for _ in 0..n_pairs {
+                    let pair = self.bin_eq_bundles(
+                        &BinaryBundle::new(x.wires()[x_start + pos..x_start + pos + 2].to_vec()),
+                        &BinaryBundle::new(y.wires()[y_start + pos..y_start + pos + 2].to_vec()))?;
+                    not_in_pair.push(self.negate(&pair)?);
+                    pos += 2;
+                }
+                let not_in_dim = self.and_many(&not_in_pair)?;
+                dims.push(self.negate(&not_in_dim)?);
+            }
+            let in_all = self.and_many(&dims)?;
+
+            let masked_result = self.xor(&in_all, &x.wires()[x_start + string_len])?;
+            results.push(masked_result);
+        }
+        Ok(BinaryBundle::new(results))
+    }
+
+    /// Like `multi_bin_membership_shared`, but the pairs form `n_boxes`
+    /// boxes, with pair `j` of every dimension belonging to box `j`, and the
+    /// result is OR over boxes of AND over dimensions of whether both bits
+    /// of the box's pair are equal. A string in a box counts once however
+    /// many boxes hold it.
+    fn multi_bin_box_union_shared(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+        num_tests: usize,
+        n_dims: usize,
+        n_boxes: usize,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        assert_eq!(
+            x.wires().len(),
+            y.wires().len() + num_tests,
+            "each string in x must have one extra mask bit"
+        );
+        let string_len = 2 * n_dims * n_boxes;
+        assert_eq!(y.wires().len(), string_len * num_tests);
+
+        let mut results = Vec::with_capacity(num_tests);
+
+        for i in 0..num_tests {
+            let x_start = i * (string_len + 1);
+            let y_start = i * string_len;
+
+            // OR(a, b, ...) = NOT(AND(NOT a, NOT b, ...))
+            let mut not_in_box = Vec::with_capacity(n_boxes);
+            for j in 0..n_boxes {
+                let mut dims = Vec::with_capacity(n_dims);
+                for d in 0..n_dims {
+                    let pos = 2 * (d * n_boxes + j);
+                    dims.push(self.bin_eq_bundles(
+                        &BinaryBundle::new(x.wires()[x_start + pos..x_start + pos + 2].to_vec()),
+                        &BinaryBundle::new(y.wires()[y_start + pos..y_start + pos + 2].to_vec()))?);
+                }
+                let in_box = self.and_many(&dims)?;
+                not_in_box.push(self.negate(&in_box)?);
+            }
+            let in_none = self.and_many(&not_in_box)?;
+            let in_any = self.negate(&in_none)?;
+
+            let masked_result = self.xor(&in_any, &x.wires()[x_start + string_len])?;
+            results.push(masked_result);
+        }
+        Ok(BinaryBundle::new(results))
+    }
+
+    /// `a + b + carry_in` modulo 2^len, on little-endian bits.
+    fn bin_add_lsb(
+        &mut self,
+        a: &[Self::Item],
+        b: &[Self::Item],
+        carry_in: &Self::Item,
+    ) -> Result<Vec<Self::Item>, Self::Error> {
+        assert_eq!(a.len(), b.len());
+
+        let mut carry = carry_in.clone();
+        let mut sum = Vec::with_capacity(a.len());
+        for (x, y) in a.iter().zip(b) {
+            let xy = self.xor(x, y)?;
+            sum.push(self.xor(&xy, &carry)?);
+            let both = self.and(x, y)?;
+            let carried = self.and(&carry, &xy)?;
+            carry = self.xor(&both, &carried)?;
+        }
+        Ok(sum)
+    }
+
+    /// Whether the little-endian `x` is at most the public constant `c`.
+    /// `one` is a wire that carries a one.
+    fn bin_le_const(
+        &mut self,
+        x: &[Self::Item],
+        c: u64,
+        one: &Self::Item,
+    ) -> Result<Self::Item, Self::Error> {
+        if x.len() < 64 && c >> x.len() != 0 {
+            return Ok(one.clone());
+        }
+
+        // From the least significant bit up: x <= c on the bits so far iff
+        // x's top bit is below c's, or they tie and x <= c on the rest.
+        let mut le = one.clone();
+        for (i, bit) in x.iter().enumerate() {
+            le = if i < 64 && (c >> i) & 1 == 1 {
+                // NOT bit OR le
+                let not_le = self.negate(&le)?;
+                let neither = self.and(bit, &not_le)?;
+                self.negate(&neither)?
+            } else {
+                // NOT bit AND le
+                let below = self.negate(bit)?;
+                self.and(&below, &le)?
+            };
+        }
+        Ok(le)
+    }
+
+    /// Like `multi_bin_membership_shared`, but each string holds XOR shares
+    /// of the endpoints of a client's boundary pairs: for every dimension
+    /// `i` in turn and each of its `pairs_per_dim[i]` pairs, the
+    /// `dim_lens[i]` bits of `l` and then of `r`, MSB first. The result is
+    /// whether, on every dimension, the intervals `[l, r]` hold at most
+    /// `max_widths[i]` points in total. Intervals with `r < l` are empty.
+    fn multi_bin_width_shared(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+        num_tests: usize,
+        pairs_per_dim: &[usize],
+        dim_lens: &[usize],
+        max_widths: &[u64],
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        assert_eq!(pairs_per_dim.len(), dim_lens.len());
+        assert_eq!(pairs_per_dim.len(), max_widths.len());
+        assert_eq!(
+            x.wires().len(),
+            y.wires().len() + num_tests,
+            "each string in x must have one extra mask bit"
+        );
+        let string_len = pairs_per_dim.iter().zip(dim_lens).map(|(p, n)| 2 * p * n).sum::<usize>();
+        assert_eq!(y.wires().len(), string_len * num_tests);
+        if num_tests == 0 {
+            return Ok(BinaryBundle::new(vec![]));
+        }
+
+        // Free-XOR gives us constant wires without any extra inputs.
+        let zero = self.xor(&x.wires()[0], &x.wires()[0])?;
+        let one = self.negate(&zero)?;
+
+        let mut results = Vec::with_capacity(num_tests);
+
+        for i in 0..num_tests {
+            let x_start = i * (string_len + 1);
+            let y_start = i * string_len;
+
+            let mut pos = 0;
+            let mut dims = Vec::with_capacity(pairs_per_dim.len());
+            for ((&n_pairs, &len), &max_width) in pairs_per_dim.iter().zip(dim_lens).zip(max_widths) {
+                // Room for the sign of r - l and for the sum of the widths.
+                let m = len + 2 + (usize::BITS - n_pairs.leading_zeros()) as usize;
+
+                // Reconstructs the next `len` bits, little-endian and
+                // zero-extended to `m` bits.
+                let endpoint = |f: &mut Self, pos: &mut usize| -> Result<Vec<Self::Item>, Self::Error> {
+                    let mut bits = Vec::with_capacity(m);
+                    for j in (0..len).rev() {
+                        bits.push(f.xor(&x.wires()[x_start + *pos + j], &y.wires()[y_start + *pos + j])?);
+                    }
+                    bits.resize(m, zero.clone());
+                    *pos += len;
+                    Ok(bits)
+                };
+
+                let zeros = vec![zero.clone(); m];
+                let mut total = zeros.clone();
+                for _ in 0..n_pairs {
+                    let l = endpoint(self, &mut pos)?;
+                    let r = endpoint(self, &mut pos)?;
+
+                    // r - l = r + NOT l + 1, and the width is that plus one,
+                    // or zero if r < l.
+                    let not_l = l.iter().map(|w| self.negate(w)).collect::<Result<Vec<_>, _>>()?;
+                    let diff = self.bin_add_lsb(&r, &not_l, &one)?;
+                    let nonneg = self.negate(&diff[m - 1])?;
+                    let width = self.bin_add_lsb(&diff, &zeros, &one)?;
+                    let width = width.iter().map(|w| self.and(w, &nonneg)).collect::<Result<Vec<_>, _>>()?;
+                    total = self.bin_add_lsb(&total, &width, &zero)?;
+                }
+                dims.push(self.bin_le_const(&total, max_width, &one)?);
+            }
+            let in_bounds = self.and_many(&dims)?;
+
+            let masked_result = self.xor(&in_bounds, &x.wires()[x_start + string_len])?;
+            results.push(masked_result);
+        }
+        Ok(BinaryBundle::new(results))
+    }
+
+    /// Like `multi_bin_membership_shared`, but each string holds XOR shares
+    /// of the bits where a path differs from a client's point, with
+    /// `prefix_lens[i]` bits on dimension `i`. The bits of a dimension form
+    /// symbols of `symbol_bits` bits, the last of which may be partial, and
+    /// the result is whether at most `radius[i]` symbols differ on every
+    /// dimension.
+    fn multi_bin_hamming_shared(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+        num_tests: usize,
+        prefix_lens: &[usize],
+        radius: &[usize],
+        symbol_bits: usize,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        assert_eq!(prefix_lens.len(), radius.len());
+        assert!(symbol_bits > 0);
+        assert_eq!(
+            x.wires().len(),
+            y.wires().len() + num_tests,
+            "each string in x must have one extra mask bit"
+        );
+        let string_len = prefix_lens.iter().sum::<usize>();
+        assert_eq!(y.wires().len(), string_len * num_tests);
+        if num_tests == 0 {
+            return Ok(BinaryBundle::new(vec![]));
+        }
+
+        let zero = self.xor(&x.wires()[0], &x.wires()[0])?;
+        let one = self.negate(&zero)?;
+
+        let mut results = Vec::with_capacity(num_tests);
+
+        for i in 0..num_tests {
+            let x_start = i * (string_len + 1);
+            let y_start = i * string_len;
+
+            let mut pos = 0;
+            let mut dims = Vec::with_capacity(prefix_lens.len());
+            for (&len, &r) in prefix_lens.iter().zip(radius) {
+                let n_symbols = (len + symbol_bits - 1) / symbol_bits;
+                // Room for the count of differing symbols, little-endian.
+                let m = (usize::BITS - n_symbols.leading_zeros()) as usize;
+                let zeros = vec![zero.clone(); m];
+
+                let mut total = zeros.clone();
+                for start in (0..len).step_by(symbol_bits) {
+                    // OR(a, b, ...) = NOT(AND(NOT a, NOT b, ...))
+                    let mut same = Vec::with_capacity(symbol_bits);
+                    for j in start..len.min(start + symbol_bits) {
+                        let bit = self.xor(&x.wires()[x_start + pos + j], &y.wires()[y_start + pos + j])?;
+                        same.push(self.negate(&bit)?);
+                    }
+                    let all_same = self.and_many(&same)?;
+                    let differs = self.negate(&all_same)?;
+                    total = self.bin_add_lsb(&total, &zeros, &differs)?;
+                }
+                pos += len;
+                dims.push(self.bin_le_const(&total, r as u64, &one)?);
+            }
+            let in_ball = self.and_many(&dims)?;
+
+            let masked_result = self.xor(&in_ball, &x.wires()[x_start + string_len])?;
+            results.push(masked_result);
+        }
+        Ok(BinaryBundle::new(results))
+    }
 }
 
 /// Implement BinaryGadgets for `Garbler`
@@ -205,17 +684,24 @@ where
 {
 }
 
-/// Fancy equality test using garbled circuits
-fn fancy_equality<F>(
+/// Fancy equality (or membership) test using garbled circuits
+fn fancy_test<F>(
     f: &mut F,
     wire_inputs: EQInputs<F::Item>,
     num_tests: usize,
+    test: Test,
 ) -> Result<BinaryBundle<F::Item>, F::Error>
 where
     F: FancyReveal + Fancy + BinaryGadgets + FancyBinary + FancyArithmetic,
 {
-    let equality_bits = f.multi_bin_eq_bundles_shared(&wire_inputs.garbler_wires, &wire_inputs.evaluator_wires,num_tests)?;
-    Ok(equality_bits)
+    let result_bits = match test {
+        Test::Equality => f.multi_bin_eq_bundles_shared(&wire_inputs.garbler_wires, &wire_inputs.evaluator_wires, num_tests)?,
+        Test::Membership(pairs_per_dim) => f.multi_bin_membership_shared(&wire_inputs.garbler_wires, &wire_inputs.evaluator_wires, num_tests, pairs_per_dim)?,
+        Test::BoxUnion { n_dims, n_boxes } => f.multi_bin_box_union_shared(&wire_inputs.garbler_wires, &wire_inputs.evaluator_wires, num_tests, n_dims, n_boxes)?,
+        Test::Width { pairs_per_dim, dim_lens, max_widths } => f.multi_bin_width_shared(&wire_inputs.garbler_wires, &wire_inputs.evaluator_wires, num_tests, pairs_per_dim, dim_lens, max_widths)?,
+        Test::Hamming { prefix_lens, radius, symbol_bits } => f.multi_bin_hamming_shared(&wire_inputs.garbler_wires, &wire_inputs.evaluator_wires, num_tests, prefix_lens, radius, symbol_bits)?,
+    };
+    Ok(result_bits)
 }
 
 
@@ -265,3 +751,219 @@ fn eq_gc() {
     }
 }
 
+
+#[test]
+fn membership_gc() {
+    // Two dimensions: one boundary pair on the first, two on the second.
+    let pairs_per_dim = vec![1, 2];
+    let gb_value = vec![
+        vec![0, 1, 1, 1, 0, 0],
+        vec![0, 1, 1, 1, 0, 0],
+        vec![0, 1, 1, 1, 0, 0],
+        vec![0, 1, 1, 1, 0, 0],
+    ];
+    let ev_value = vec![
+        vec![0, 1, 1, 1, 0, 0], // in via both pairs of dim 1
+        vec![0, 1, 0, 1, 0, 0], // in via the second pair of dim 1
+        vec![0, 1, 0, 1, 0, 1], // no pair of dim 1 matches
+        vec![1, 1, 1, 1, 0, 0], // dim 0 does not match
+    ];
+    let expected = vec![true, true, false, false];
+
+    let (sender, receiver) = UnixStream::pair().unwrap();
+    let (result_sender, result_receiver) = std::sync::mpsc::channel();
+
+    let layout = pairs_per_dim.clone();
+    let x = std::thread::spawn(move || {
+        let rng_gb = AesRng::new();
+        let reader = BufReader::new(sender.try_clone().unwrap());
+        let writer = BufWriter::new(sender);
+        let mut channel = Channel::new(reader, writer);
+        let masks = multiple_gb_membership_test(&mut rng_gb.clone(), &mut channel, gb_value.as_slice(), &layout);
+        result_sender.send(masks).unwrap();
+    });
+
+    let rng_ev = AesRng::new();
+    let reader = BufReader::new(receiver.try_clone().unwrap());
+    let writer = BufWriter::new(receiver);
+    let mut channel = Channel::new(reader, writer);
+
+    let results = multiple_ev_membership_test(&mut rng_ev.clone(), &mut channel, ev_value.as_slice(), &pairs_per_dim);
+
+    let masks = result_receiver.recv().unwrap();
+    x.join().unwrap();
+
+    for i in 0..results.len() {
+        assert_eq!(masks[i] ^ results[i], expected[i], "wrong membership for test {}", i);
+    }
+}
+
+
+#[test]
+fn box_union_gc() {
+    // Two dimensions and two boxes; each dimension lists box 0's pair and
+    // then box 1's.
+    let gb_value = vec![
+        vec![0, 1, 1, 0, 1, 1, 0, 0],
+        vec![0, 1, 1, 0, 1, 1, 0, 0],
+        vec![0, 1, 1, 0, 1, 1, 0, 0],
+        vec![0, 1, 1, 0, 1, 1, 0, 0],
+    ];
+    let ev_value = vec![
+        vec![0, 1, 1, 0, 1, 1, 0, 0], // in both boxes
+        vec![0, 1, 0, 0, 1, 1, 1, 0], // in box 0 only
+        vec![1, 1, 1, 0, 1, 0, 0, 0], // in box 1 only
+        vec![0, 1, 0, 0, 1, 0, 0, 0], // dim 0 in box 0 and dim 1 in box 1
+    ];
+    let expected = vec![true, true, true, false];
+
+    let (sender, receiver) = UnixStream::pair().unwrap();
+    let (result_sender, result_receiver) = std::sync::mpsc::channel();
+
+    let x = std::thread::spawn(move || {
+        let rng_gb = AesRng::new();
+        let reader = BufReader::new(sender.try_clone().unwrap());
+        let writer = BufWriter::new(sender);
+        let mut channel = Channel::new(reader, writer);
+        let masks = multiple_gb_box_union_test(&mut rng_gb.clone(), &mut channel, gb_value.as_slice(), 2, 2);
+        result_sender.send(masks).unwrap();
+    });
+
+    let rng_ev = AesRng::new();
+    let reader = BufReader::new(receiver.try_clone().unwrap());
+    let writer = BufWriter::new(receiver);
+    let mut channel = Channel::new(reader, writer);
+
+    let results = multiple_ev_box_union_test(&mut rng_ev.clone(), &mut channel, ev_value.as_slice(), 2, 2);
+
+    let masks = result_receiver.recv().unwrap();
+    x.join().unwrap();
+
+    for i in 0..results.len() {
+        assert_eq!(masks[i] ^ results[i], expected[i], "wrong membership for test {}", i);
+    }
+}
+
+#[test]
+fn width_gc() {
+    // One dimension of four bits with two boundary pairs, at most 5 points.
+    let pairs_per_dim = vec![2];
+    let dim_lens = vec![4];
+    let max_widths = vec![5];
+    let bits = |v: u16| (0..4).rev().map(|i| (v >> i) & 1).collect::<Vec<u16>>();
+    let client = |pairs: &[(u16, u16)]| pairs.iter().flat_map(|&(l, r)| [bits(l), bits(r)].concat()).collect::<Vec<u16>>();
+    let clients = vec![
+        client(&[(3, 7), (15, 0)]),  // width 5 plus an empty pair
+        client(&[(3, 8), (15, 0)]),  // width 6
+        client(&[(0, 1), (13, 15)]), // 2 + 3 points across the seam
+        client(&[(0, 2), (13, 15)]), // 3 + 3 points
+        client(&[(0, 15), (15, 0)]), // the whole dimension
+    ];
+    let expected = vec![true, false, true, false, false];
+
+    // Random XOR shares of each client's string.
+    let mut rng = AesRng::new();
+    let gb_value = clients
+        .iter()
+        .map(|c| c.iter().map(|_| rng.gen_bool() as u16).collect::<Vec<u16>>())
+        .collect::<Vec<_>>();
+    let ev_value = clients
+        .iter()
+        .zip(&gb_value)
+        .map(|(c, g)| c.iter().zip(g).map(|(a, b)| a ^ b).collect::<Vec<u16>>())
+        .collect::<Vec<_>>();
+
+    let (sender, receiver) = UnixStream::pair().unwrap();
+    let (result_sender, result_receiver) = std::sync::mpsc::channel();
+
+    let (layout, lens, widths) = (pairs_per_dim.clone(), dim_lens.clone(), max_widths.clone());
+    let x = std::thread::spawn(move || {
+        let rng_gb = AesRng::new();
+        let reader = BufReader::new(sender.try_clone().unwrap());
+        let writer = BufWriter::new(sender);
+        let mut channel = Channel::new(reader, writer);
+        let masks = multiple_gb_width_test(&mut rng_gb.clone(), &mut channel, gb_value.as_slice(), &layout, &lens, &widths);
+        result_sender.send(masks).unwrap();
+    });
+
+    let rng_ev = AesRng::new();
+    let reader = BufReader::new(receiver.try_clone().unwrap());
+    let writer = BufWriter::new(receiver);
+    let mut channel = Channel::new(reader, writer);
+
+    let results = multiple_ev_width_test(&mut rng_ev.clone(), &mut channel, ev_value.as_slice(), &pairs_per_dim, &dim_lens, &max_widths);
+
+    let masks = result_receiver.recv().unwrap();
+    x.join().unwrap();
+
+    for i in 0..results.len() {
+        assert_eq!(masks[i] ^ results[i], expected[i], "wrong width bound for test {}", i);
+    }
+}
+
+#[test]
+fn hamming_gc() {
+    use crate::hamming::HammingBall;
+
+    // A five-bit dimension over a four-letter alphabet, so its last symbol
+    // is partial, and a two-bit one that must match exactly.
+    let ball = HammingBall { radius: vec![1, 0], symbol_bits: 2 };
+    let center = vec![crate::MSB_u32_to_bits(5, 0b01_10_1), crate::MSB_u32_to_bits(2, 0b10)];
+    let prefix_lens = vec![5, 2];
+
+    let mut paths = vec![];
+    for x in 0..(1u32 << 5) {
+        for y in 0..(1u32 << 2) {
+            paths.push(vec![crate::MSB_u32_to_bits(5, x), crate::MSB_u32_to_bits(2, y)]);
+        }
+    }
+    let expected = paths.iter().map(|p| ball.contains(&center, p)).collect::<Vec<_>>();
+    let mismatches = paths
+        .iter()
+        .map(|p| {
+            p.iter()
+                .zip(&center)
+                .flat_map(|(a, b)| a.iter().zip(b).map(|(x, y)| (x ^ y) as u16).collect::<Vec<_>>())
+                .collect::<Vec<u16>>()
+        })
+        .collect::<Vec<_>>();
+
+    // Random XOR shares of each mismatch string.
+    let mut rng = AesRng::new();
+    let gb_value = mismatches
+        .iter()
+        .map(|c| c.iter().map(|_| rng.gen_bool() as u16).collect::<Vec<u16>>())
+        .collect::<Vec<_>>();
+    let ev_value = mismatches
+        .iter()
+        .zip(&gb_value)
+        .map(|(c, g)| c.iter().zip(g).map(|(a, b)| a ^ b).collect::<Vec<u16>>())
+        .collect::<Vec<_>>();
+
+    let (sender, receiver) = UnixStream::pair().unwrap();
+    let (result_sender, result_receiver) = std::sync::mpsc::channel();
+
+    let (lens, radius) = (prefix_lens.clone(), ball.radius.clone());
+    let x = std::thread::spawn(move || {
+        let rng_gb = AesRng::new();
+        let reader = BufReader::new(sender.try_clone().unwrap());
+        let writer = BufWriter::new(sender);
+        let mut channel = Channel::new(reader, writer);
+        let masks = multiple_gb_hamming_test(&mut rng_gb.clone(), &mut channel, gb_value.as_slice(), &lens, &radius, 2);
+        result_sender.send(masks).unwrap();
+    });
+
+    let rng_ev = AesRng::new();
+    let reader = BufReader::new(receiver.try_clone().unwrap());
+    let writer = BufWriter::new(receiver);
+    let mut channel = Channel::new(reader, writer);
+
+    let results = multiple_ev_hamming_test(&mut rng_ev.clone(), &mut channel, ev_value.as_slice(), &prefix_lens, &ball.radius, 2);
+
+    let masks = result_receiver.recv().unwrap();
+    x.join().unwrap();
+
+    for i in 0..results.len() {
+        assert_eq!(masks[i] ^ results[i], expected[i], "wrong Hamming ball for path {}", i);
+    }
+}
@@ -10,35 +10,152 @@ use std::convert::{TryFrom, TryInto};
 use std::u32;
 use scuttlebutt::Block;
 
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+/// An element of the field of integers modulo p = 2^255 - 19, in four
+/// 64-bit limbs, least significant first. The limbs hold any value below
+/// 2^256; the arithmetic always reduces its results below p, and
+/// comparisons and serialization go by the value modulo p.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "FieldElmDigits", from = "FieldElmDigits")]
 pub struct FieldElm {
-    value: BigUint,
+    limbs: [u64; 4],
+}
+
+// The serialized form of a `FieldElm`, which is that of the `BigUint` it
+// used to wrap: the value's base-2^32 digits, least significant first and
+// without leading zeros.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "FieldElm")]
+struct FieldElmDigits {
+    value: Vec<u32>,
 }
 
-// 255-bit modulus:   p = 2^255 - 10
+// 255-bit modulus:   p = 2^255 - 19
 const MODULUS_STR: &[u8] = b"7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffed";
+const MODULUS_LIMBS: [u64; 4] = [0xffff_ffff_ffff_ffed, u64::MAX, u64::MAX, 0x7fff_ffff_ffff_ffff];
 
 // 127-bit modulus:   p = 2^127 - 1
 //const MODULUS_STR: &[u8] = b"7fffffffffffffffffffffffffffffff";
 
-//  63-bit modulus:   p = 2^63 - 25;
-const MODULUS_64: u64 = 9223372036854775783u64;
-const MODULUS_64_BIG: u128 = 9223372036854775783u128;
 
 lazy_static! {
-    static ref MODULUS: FieldElm =
-        FieldElm::from_hex(MODULUS_STR).expect("Could not parse modulus");
+    static ref MODULUS_BIG: BigUint =
+        BigUint::parse_bytes(MODULUS_STR, 16).expect("Could not parse modulus");
     static ref MODULUS_DUMMY: Dummy = Dummy::from(7);
 }
 
+// x + y, and the carry out.
+#[inline]
+fn add_limbs(x: &[u64; 4], y: &[u64; 4]) -> ([u64; 4], bool) {
+    let mut out = [0u64; 4];
+    let mut carry = false;
+    for i in 0..4 {
+        let (s, c1) = x[i].overflowing_add(y[i]);
+        let (s, c2) = s.overflowing_add(carry as u64);
+        out[i] = s;
+        carry = c1 | c2;
+    }
+    (out, carry)
+}
+
+// x - y, and the borrow out, which is set if x < y.
+#[inline]
+fn sub_limbs(x: &[u64; 4], y: &[u64; 4]) -> ([u64; 4], bool) {
+    let mut out = [0u64; 4];
+    let mut borrow = false;
+    for i in 0..4 {
+        let (d, b1) = x[i].overflowing_sub(y[i]);
+        let (d, b2) = d.overflowing_sub(borrow as u64);
+        out[i] = d;
+        borrow = b1 | b2;
+    }
+    (out, borrow)
+}
+
 impl FieldElm {
     pub fn from_hex(inp: &[u8]) -> Option<FieldElm> {
-        BigUint::parse_bytes(inp, 16).map(|value| FieldElm { value })
+        let digits = std::str::from_utf8(inp).ok()?;
+        if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digits = digits.trim_start_matches('0').as_bytes();
+        if digits.len() > 64 {
+            return None;
+        }
+
+        let mut limbs = [0u64; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(digits.rchunks(16)) {
+            *limb = u64::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?;
+        }
+        Some(FieldElm { limbs })
     }
 
     pub fn to_vec(&self, len: usize) -> Vec<FieldElm> {
         std::iter::repeat(self.clone()).take(len).collect()
     }
+
+    // The value modulo p. The limbs are below 2^256 < 3p, so this
+    // subtracts p at most twice.
+    #[inline]
+    fn canonical(&self) -> [u64; 4] {
+        let mut x = self.limbs;
+        for _ in 0..2 {
+            let (d, borrow) = sub_limbs(&x, &MODULUS_LIMBS);
+            if !borrow {
+                x = d;
+            }
+        }
+        x
+    }
+
+    // x + carry * 2^256 modulo p, using 2^256 = 38 (mod p).
+    #[inline]
+    fn fold(mut x: [u64; 4], mut carry: u64) -> FieldElm {
+        // 38 * carry fits in a limb for every carry the callers pass, and
+        // once the sum wraps around it is small, so it cannot wrap again.
+        while carry != 0 {
+            let (sum, c) = add_limbs(&x, &[38 * carry, 0, 0, 0]);
+            x = sum;
+            carry = c as u64;
+        }
+        FieldElm { limbs: FieldElm { limbs: x }.canonical() }
+    }
+
+    // The value times 2^32, plus `digit`, modulo p.
+    fn shift_in(&self, digit: u32) -> FieldElm {
+        let x = self.canonical();
+        let limbs = [
+            (x[0] << 32) | digit as u64,
+            (x[1] << 32) | (x[0] >> 32),
+            (x[2] << 32) | (x[1] >> 32),
+            (x[3] << 32) | (x[2] >> 32),
+        ];
+        FieldElm::fold(limbs, x[3] >> 32)
+    }
+}
+
+impl From<FieldElm> for FieldElmDigits {
+    fn from(fe: FieldElm) -> FieldElmDigits {
+        let mut value = fe
+            .canonical()
+            .iter()
+            .flat_map(|&limb| vec![limb as u32, (limb >> 32) as u32])
+            .collect::<Vec<_>>();
+        while value.last() == Some(&0) {
+            value.pop();
+        }
+        FieldElmDigits { value }
+    }
+}
+
+// Digits of any length, as `BigUint` accepted, reduced modulo p.
+impl From<FieldElmDigits> for FieldElm {
+    fn from(digits: FieldElmDigits) -> FieldElm {
+        digits
+            .value
+            .iter()
+            .rev()
+            .fold(FieldElm { limbs: [0; 4] }, |acc, &digit| acc.shift_in(digit))
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -83,12 +200,12 @@ impl crate::Group for Dummy {
     fn add(&mut self, other: &Self) {
         //*self = FieldElm::from((&self.value + &other.value) % &MODULUS.value);
         self.value += &other.value;
-        self.value %= &MODULUS.value;
+        self.value %= &*MODULUS_BIG;
     }
 
     fn mul(&mut self, other: &Self) {
         self.value *= &other.value;
-        self.value %= &MODULUS.value;
+        self.value %= &*MODULUS_BIG;
     }
 
     fn add_lazy(&mut self, other: &Self) {
@@ -100,7 +217,7 @@ impl crate::Group for Dummy {
     }
 
     fn reduce(&mut self) {
-        self.value %= &MODULUS.value;
+        self.value %= &*MODULUS_BIG;
     }
 
     fn sub(&mut self, other: &Self) {
@@ -119,84 +236,12 @@ impl crate::Group for Dummy {
 
 impl crate::prg::FromRng for Dummy {
     fn from_rng(&mut self, rng: &mut impl rand::Rng) {
-        RandBigInt::gen_biguint_below(rng, &MODULUS.value);
+        RandBigInt::gen_biguint_below(rng, &MODULUS_BIG);
     }
 }
 
 impl crate::Share for Dummy {}
 
-impl crate::Group for u64 {
-    #[inline]
-    fn zero() -> Self {
-        0u64
-    }
-
-    #[inline]
-    fn one() -> Self {
-        1u64
-    }
-
-    #[inline]
-    fn add(&mut self, other: &Self) {
-        debug_assert!(*self < MODULUS_64);
-        debug_assert!(*other < MODULUS_64);
-        *self += other;
-        *self %= MODULUS_64;
-    }
-
-    #[inline]
-    fn mul(&mut self, other: &Self) {
-        debug_assert!(*self < MODULUS_64);
-        debug_assert!(*other < MODULUS_64);
-        let s64: u64 = *self;
-        let o64: u64 = *other;
-        let a: u128 = s64.into();
-        let b: u128 = o64.into();
-
-        let res = (a * b) % MODULUS_64_BIG;
-        *self = res.try_into().unwrap();
-    }
-
-    #[inline]
-    fn add_lazy(&mut self, other: &Self) {
-        self.add(other);
-    }
-
-    #[inline]
-    fn mul_lazy(&mut self, other: &Self) {
-        self.mul(other);
-    }
-
-    #[inline]
-    fn reduce(&mut self) {}
-
-    #[inline]
-    fn sub(&mut self, other: &Self) {
-        debug_assert!(*self < MODULUS_64);
-        debug_assert!(*other < MODULUS_64);
-        let mut neg = *other;
-        neg.negate();
-        self.add(&neg);
-    }
-
-    #[inline]
-    fn negate(&mut self) {
-        debug_assert!(*self < MODULUS_64);
-        *self = MODULUS_64 - *self;
-        *self %= MODULUS_64;
-    }
-}
-
-impl crate::prg::FromRng for u64 {
-    fn from_rng(&mut self, rng: &mut impl rand::Rng) {
-        *self = u64::MAX;
-        while *self >= MODULUS_64 {
-            *self = rng.next_u64();
-            *self &= 0x7fffffffffffffffu64;
-        }
-    }
-}
-
 impl crate::Share for u64 {}
 
 impl crate::Group for FE {
@@ -283,7 +328,7 @@ impl From<u32> for FieldElm {
     #[inline]
     fn from(inp: u32) -> Self {
         FieldElm {
-            value: BigUint::from(inp),
+            limbs: [inp as u64, 0, 0, 0],
         }
     }
 }
@@ -291,21 +336,32 @@ impl From<u32> for FieldElm {
 impl From<BigUint> for FieldElm {
     #[inline]
     fn from(inp: BigUint) -> Self {
-        FieldElm { value: inp }
+        FieldElm::from(FieldElmDigits {
+            value: inp.to_u32_digits(),
+        })
+    }
+}
+
+impl PartialEq for FieldElm {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical() == other.canonical()
     }
 }
 
+impl Eq for FieldElm {}
+
 impl Ord for FieldElm {
     #[inline]
     fn cmp(&self, other: &Self) -> Ordering {
-        self.value.cmp(&other.value)
+        self.canonical().iter().rev().cmp(other.canonical().iter().rev())
     }
 }
 
 impl PartialOrd for FieldElm {
     #[inline]
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.value.cmp(&other.value))
+        Some(self.cmp(other))
     }
 }
 
@@ -322,172 +378,281 @@ impl crate::Group for FieldElm {
 
     #[inline]
     fn add(&mut self, other: &Self) {
-        //*self = FieldElm::from((&self.value + &other.value) % &MODULUS.value);
-        self.value += &other.value;
-        self.value %= &MODULUS.value;
+        let (sum, carry) = add_limbs(&self.limbs, &other.limbs);
+        *self = FieldElm::fold(sum, carry as u64);
     }
 
     #[inline]
     fn mul(&mut self, other: &Self) {
-        self.value *= &other.value;
-        self.value %= &MODULUS.value;
+        let (a, b) = (&self.limbs, &other.limbs);
+        let mut wide = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let t = (a[i] as u128) * (b[j] as u128) + wide[i + j] as u128 + carry;
+                wide[i + j] = t as u64;
+                carry = t >> 64;
+            }
+            wide[i + 4] = carry as u64;
+        }
+
+        // The product is low + high * 2^256 = low + 38 * high (mod p).
+        let mut low = [0u64; 4];
+        let mut carry = 0u128;
+        for i in 0..4 {
+            let t = wide[i] as u128 + 38 * wide[i + 4] as u128 + carry;
+            low[i] = t as u64;
+            carry = t >> 64;
+        }
+        *self = FieldElm::fold(low, carry as u64);
     }
 
+    // With fixed-width limbs there is no room to defer the reduction.
     #[inline]
     fn add_lazy(&mut self, other: &Self) {
-        self.value += &other.value;
+        self.add(other);
     }
 
     #[inline]
     fn mul_lazy(&mut self, other: &Self) {
-        self.value *= &other.value;
+        self.mul(other);
     }
 
     #[inline]
     fn reduce(&mut self) {
-        self.value %= &MODULUS.value;
+        self.limbs = self.canonical();
     }
 
     #[inline]
     fn sub(&mut self, other: &Self) {
         // XXX not constant time
-        if self.value < other.value {
-            self.value += &MODULUS.value;
-        }
-
-        *self = FieldElm::from(&self.value - &other.value);
+        let (diff, borrow) = sub_limbs(&self.canonical(), &other.canonical());
+        self.limbs = if borrow {
+            add_limbs(&diff, &MODULUS_LIMBS).0
+        } else {
+            diff
+        };
     }
 
     #[inline]
     fn negate(&mut self) {
-        self.value = &MODULUS.value - &self.value;
+        let x = self.canonical();
+        self.limbs = if x == [0; 4] {
+            x
+        } else {
+            sub_limbs(&MODULUS_LIMBS, &x).0
+        };
     }
 }
 
 impl crate::prg::FromRng for FieldElm {
     #[inline]
     fn from_rng(&mut self, rng: &mut impl rand::Rng) {
-        self.value = rng.gen_biguint_below(&MODULUS.value);
+        // Rejection sampling from [0, 2^255), which almost never rejects.
+        loop {
+            let mut limbs = [0u64; 4];
+            for limb in limbs.iter_mut() {
+                *limb = rng.next_u64();
+            }
+            limbs[3] >>= 1;
+            if sub_limbs(&limbs, &MODULUS_LIMBS).1 {
+                self.limbs = limbs;
+                break;
+            }
+        }
     }
 }
 
 impl crate::Share for FieldElm {}
 
-impl<T> crate::Group for (T, T)
-where
-    T: crate::Group + Clone,
-{
+impl TryFrom<Block> for FieldElm {
+    type Error = &'static str;
+
+    // A block is below 2^128, so it is always in the field.
+    fn try_from(block: Block) -> Result<Self, Self::Error> {
+        let bytes = block.as_ref();
+        let mut limbs = [0u64; 4];
+        limbs[1] = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        limbs[0] = u64::from_be_bytes(bytes[8..].try_into().unwrap());
+        Ok(FieldElm { limbs })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockPair(pub [Block; 2]);
+impl TryFrom<BlockPair> for FieldElm {
+    type Error = &'static str;
+
+    // The two blocks are the value's 32 bytes, big-endian, reduced modulo p.
+    fn try_from(blocks: BlockPair) -> Result<Self, Self::Error> {
+        let mut bytes = [0u8; 32];
+        bytes[..16].copy_from_slice(blocks.0[0].as_ref());
+        bytes[16..].copy_from_slice(blocks.0[1].as_ref());
+
+        let mut limbs = [0u64; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.rchunks(8)) {
+            *limb = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        Ok(FieldElm { limbs: FieldElm { limbs }.canonical() })
+    }
+}
+
+impl From<FieldElm> for BlockPair {
+    fn from(fe: FieldElm) -> BlockPair {
+        let mut bytes = [0u8; 32];
+        for (chunk, limb) in bytes.rchunks_mut(8).zip(&fe.canonical()) {
+            chunk.copy_from_slice(&limb.to_be_bytes());
+        }
+
+        let mut blocks = [Block::default(); 2];
+        blocks[0].as_mut().copy_from_slice(&bytes[..16]);
+        blocks[1].as_mut().copy_from_slice(&bytes[16..]);
+        BlockPair(blocks)
+    }
+}
+
+/// A share that the servers exchange through OT, one block per OT. The
+/// elements that fit in 128 bits take one block, and `FieldElm` two.
+pub trait OtBlocks: Sized {
+    const BLOCKS: usize;
+
+    fn to_blocks(self) -> Vec<Block>;
+
+    /// The inverse of `to_blocks`, from `Self::BLOCKS` blocks.
+    fn from_blocks(blocks: &[Block]) -> Self;
+}
+
+impl OtBlocks for FE {
+    const BLOCKS: usize = 1;
+
+    fn to_blocks(self) -> Vec<Block> {
+        vec![self.into()]
+    }
+
+    fn from_blocks(blocks: &[Block]) -> Self {
+        FE::try_from(blocks[0]).unwrap()
+    }
+}
+
+impl OtBlocks for FieldElm {
+    const BLOCKS: usize = 2;
+
+    fn to_blocks(self) -> Vec<Block> {
+        BlockPair::from(self).0.to_vec()
+    }
+
+    fn from_blocks(blocks: &[Block]) -> Self {
+        FieldElm::try_from(BlockPair([blocks[0], blocks[1]])).unwrap()
+    }
+}
+
+/*******/
+
+/// An element of the ring of integers modulo 2^64. Counts and sums of
+/// weights stay far below 2^64, so shares of them need neither a prime
+/// modulus nor any reduction, and fit in one block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RingElm(u64);
+
+impl RingElm {
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u32> for RingElm {
+    #[inline]
+    fn from(inp: u32) -> Self {
+        RingElm(inp.into())
+    }
+}
+
+impl From<u64> for RingElm {
+    #[inline]
+    fn from(inp: u64) -> Self {
+        RingElm(inp)
+    }
+}
+
+impl crate::Group for RingElm {
     #[inline]
     fn zero() -> Self {
-        (T::zero(), T::zero())
+        RingElm(0)
     }
 
     #[inline]
     fn one() -> Self {
-        (T::one(), T::one())
+        RingElm(1)
     }
 
     #[inline]
     fn add(&mut self, other: &Self) {
-        self.0.add(&other.0);
-        self.1.add(&other.1);
+        self.0 = self.0.wrapping_add(other.0);
     }
 
     #[inline]
     fn mul(&mut self, other: &Self) {
-        self.0.mul(&other.0);
-        self.1.mul(&other.1);
+        self.0 = self.0.wrapping_mul(other.0);
     }
 
     #[inline]
     fn add_lazy(&mut self, other: &Self) {
-        self.0.add_lazy(&other.0);
-        self.1.add_lazy(&other.1);
+        self.add(other);
     }
 
     #[inline]
     fn mul_lazy(&mut self, other: &Self) {
-        self.0.mul_lazy(&other.0);
-        self.1.mul_lazy(&other.1);
+        self.mul(other);
     }
 
     #[inline]
-    fn reduce(&mut self) {
-        self.0.reduce();
-        self.1.reduce();
-    }
+    fn reduce(&mut self) {}
 
     #[inline]
-    fn negate(&mut self) {
-        self.0.negate();
-        self.1.negate();
+    fn sub(&mut self, other: &Self) {
+        self.0 = self.0.wrapping_sub(other.0);
     }
 
     #[inline]
-    fn sub(&mut self, other: &Self) {
-        let mut inv0 = other.0.clone();
-        let mut inv1 = other.1.clone();
-        inv0.negate();
-        inv1.negate();
-        self.0.add(&inv0);
-        self.1.add(&inv1);
+    fn negate(&mut self) {
+        self.0 = self.0.wrapping_neg();
     }
 }
 
-impl<T> crate::prg::FromRng for (T, T)
-where
-    T: crate::prg::FromRng + crate::Group,
-{
-    fn from_rng(&mut self, mut rng: &mut impl rand::Rng) {
-        self.0 = T::zero();
-        self.1 = T::zero();
-        self.0.from_rng(&mut rng);
-        self.1.from_rng(&mut rng);
+impl crate::prg::FromRng for RingElm {
+    fn from_rng(&mut self, rng: &mut impl rand::Rng) {
+        self.0 = rng.next_u64();
     }
 }
 
+impl crate::Share for RingElm {}
 
-impl TryFrom<Block> for FieldElm {
+impl TryFrom<Block> for RingElm {
     type Error = &'static str;
 
+    // The low 8 bytes, little-endian, as for `FE`.
     fn try_from(block: Block) -> Result<Self, Self::Error> {
-        let bytes = block.as_ref();
-        let value = BigUint::from_bytes_be(bytes);
-        if FieldElm::from(value.clone()) >= *MODULUS {
-            Err("Value too large for FieldElm")
-        } else {
-            Ok(FieldElm { value })
-        }
+        Ok(RingElm(u64::from_le_bytes(block.as_ref()[..8].try_into().unwrap())))
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct BlockPair(pub [Block; 2]);
-impl TryFrom<BlockPair> for FieldElm {
-    type Error = &'static str;
-
-    fn try_from(blocks: BlockPair) -> Result<Self, Self::Error> {
-        let mut bytes = [0u8; 32];
-        bytes[..16].copy_from_slice(blocks.0[0].as_ref());
-        bytes[16..].copy_from_slice(blocks.0[1].as_ref());
-        let value = BigUint::from_bytes_be(&bytes);
-        Ok(FieldElm { value })
+impl From<RingElm> for Block {
+    fn from(x: RingElm) -> Block {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&x.0.to_le_bytes());
+        Block::from(bytes)
     }
 }
 
-impl From<FieldElm> for BlockPair {
-    fn from(fe: FieldElm) -> BlockPair {
-        let bytes = fe.value.to_bytes_be();
-        assert!(bytes.len() <= 32, "FieldElm value too large for BlockPair");
+impl OtBlocks for RingElm {
+    const BLOCKS: usize = 1;
 
-        let mut padded = [0u8; 32];
-        // Pad with leading zeros (MSB side for big-endian)
-        padded[32 - bytes.len()..].copy_from_slice(&bytes);
+    fn to_blocks(self) -> Vec<Block> {
+        vec![self.into()]
+    }
 
-        let mut blocks = [Block::default(); 2];
-        blocks[0].as_mut().copy_from_slice(&padded[..16]);
-        blocks[1].as_mut().copy_from_slice(&padded[16..]);
-        BlockPair(blocks)
+    fn from_blocks(blocks: &[Block]) -> Self {
+        RingElm::try_from(blocks[0]).unwrap()
     }
 }
 
@@ -498,6 +663,122 @@ mod tests {
 
     use crate::Group;
 
+    // p itself, unreduced.
+    const MODULUS: FieldElm = FieldElm { limbs: MODULUS_LIMBS };
+
+    fn to_big(x: &FieldElm) -> BigUint {
+        BigUint::new(FieldElmDigits::from(x.clone()).value)
+    }
+
+    #[test]
+    fn modulus() {
+        assert_eq!(FieldElm::from_hex(MODULUS_STR).unwrap().limbs, MODULUS_LIMBS);
+        assert_eq!(MODULUS, FieldElm::zero());
+        // The largest value the limbs hold is 2^256 - 1 = 37 (mod p).
+        let max = FieldElm { limbs: [u64::MAX; 4] };
+        assert_eq!(max, FieldElm::from(37));
+        assert_eq!(FieldElm::from_hex(&[b'f'; 64]), Some(max));
+        assert_eq!(FieldElm::from_hex(&[b'f'; 65]), None);
+        assert_eq!(FieldElm::from_hex(b"x1"), None);
+    }
+
+    #[test]
+    fn matches_biguint() {
+        let mut rng = rand::thread_rng();
+        let p = &*MODULUS_BIG;
+        let mut values = vec![FieldElm::zero(), FieldElm::one(), MODULUS, FieldElm { limbs: [u64::MAX; 4] }];
+        values.extend((0..50).map(|_| FieldElm::random()));
+        values.extend((0..50).map(|_| FieldElm::from(rng.gen_biguint(256))));
+        for x in &values {
+            for y in &values {
+                let (bx, by) = (to_big(x), to_big(y));
+                let mut sum = x.clone();
+                sum.add(y);
+                assert_eq!(to_big(&sum), (&bx + &by) % p);
+                let mut prod = x.clone();
+                prod.mul(y);
+                assert_eq!(to_big(&prod), (&bx * &by) % p);
+                let mut diff = x.clone();
+                diff.sub(y);
+                assert_eq!(to_big(&diff), (&bx + p - &by) % p);
+                assert_eq!(x.cmp(y), bx.cmp(&by));
+            }
+            let mut neg = x.clone();
+            neg.negate();
+            assert_eq!(to_big(&neg), (p - to_big(x)) % p);
+        }
+    }
+
+    #[test]
+    fn bincode_compatible() {
+        // The encoding of the BigUint-based FieldElm.
+        #[derive(Serialize, Deserialize)]
+        struct Old {
+            value: BigUint,
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut values = vec![BigUint::from(0u32), BigUint::from(1u32), BigUint::from(1u64 << 40)];
+        values.extend((0..20).map(|_| rng.gen_biguint_below(&MODULUS_BIG)));
+        for value in values {
+            let old = bincode::serialize(&Old { value: value.clone() }).unwrap();
+            let new = bincode::serialize(&FieldElm::from(value.clone())).unwrap();
+            assert_eq!(old, new);
+            let decoded: FieldElm = bincode::deserialize(&old).unwrap();
+            assert_eq!(to_big(&decoded), value);
+        }
+
+        // Old senders could send values at or above p.
+        let big = &*MODULUS_BIG * 5u32 + 3u32;
+        let decoded: FieldElm = bincode::deserialize(&bincode::serialize(&Old { value: big }).unwrap()).unwrap();
+        assert_eq!(decoded, FieldElm::from(3));
+    }
+
+    #[test]
+    fn block_pair_reduces() {
+        let x = FieldElm::random();
+        assert_eq!(FieldElm::try_from(BlockPair::from(x.clone())).unwrap(), x);
+
+        let all_ones = BlockPair::from(FieldElm::zero());
+        let mut all_ones = all_ones.0;
+        for b in all_ones.iter_mut() {
+            b.as_mut().copy_from_slice(&[0xff; 16]);
+        }
+        let reduced = FieldElm::try_from(BlockPair(all_ones)).unwrap();
+        assert_eq!(reduced.limbs, [37, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ring_wraps() {
+        let mut x = RingElm::from(u64::MAX);
+        x.add(&RingElm::from(3u32));
+        assert_eq!(x, RingElm::from(2u32));
+        x.sub(&RingElm::from(5u32));
+        assert_eq!(x, RingElm::from(u64::MAX - 2));
+        x.negate();
+        assert_eq!(x, RingElm::from(3u32));
+        x.mul(&RingElm::from(1u64 << 63));
+        assert_eq!(x, RingElm::from(1u64 << 63));
+
+        let (s0, s1) = RingElm::from(42u32).share();
+        let mut out = s0;
+        out.add(&s1);
+        assert_eq!(out, RingElm::from(42u32));
+    }
+
+    #[test]
+    fn ot_blocks_round_trip() {
+        fn round_trip<S: OtBlocks + Share + PartialEq + std::fmt::Debug>() {
+            let x = S::random();
+            let blocks = x.clone().to_blocks();
+            assert_eq!(blocks.len(), S::BLOCKS);
+            assert_eq!(S::from_blocks(&blocks), x);
+        }
+        round_trip::<FE>();
+        round_trip::<FieldElm>();
+        round_trip::<RingElm>();
+    }
+
     #[test]
     fn add() {
         let mut res = FieldElm::zero();
@@ -571,53 +852,11 @@ mod tests {
     }
 
     #[test]
-    fn add128() {
-        let mut res = u64::zero();
-        let one = 1u64;
-        let two = 2u64;
-        res.add(&one);
-        res.add(&one);
-        assert_eq!(two, res);
-    }
-
-    #[test]
-    fn add_big128() {
-        let mut res = 1u64;
-        let two = 2u64;
-        res.add(&two);
-        res.add(&(MODULUS_64 - 1));
-        assert_eq!(two, res);
-    }
-
-    #[test]
-    fn mul_big128() {
-        let mut res = 0u64;
-        let four = 4u64;
-        res.add(&four);
-        res.mul(&(MODULUS_64 - 1));
-        assert_eq!(res, MODULUS_64 - 4);
-    }
-
-    #[test]
-    fn mul_big2128() {
-        let mut res = u64::zero();
-        let two = 2u64;
-        let eight = 8u64;
-        res.add(&two);
-        res.mul(&eight);
-        assert_eq!(res, 16u64);
+    fn convert_is_apart_from_expand() {
+        let seed = crate::prg::PrgSeed::random();
+        let out = seed.expand();
+        let converted = seed.convert::<FieldElm>().seed;
+        assert_ne!(converted.key, out.seeds.0.key);
+        assert_ne!(converted.key, out.seeds.1.key);
     }
-
-    #[test]
-    fn negate128() {
-        let zero = u64::zero();
-        let x = 1123123u64;
-        let mut negx = 1123123u64;
-        let mut res = 0u64;
-
-        negx.negate();
-        res.add(&x);
-        res.add(&negx);
-        assert_eq!(zero, res);
-    }
-}
\ No newline at end of file
+}
@@ -1,36 +1,46 @@
 // extern crate cpuprofiler;
 
+#[cfg(feature = "server")]
 pub mod collect;
+#[cfg(feature = "server")]
 pub mod config;
+#[cfg(feature = "server")]
 pub mod fastfield;
+#[cfg(feature = "server")]
 pub mod field;
+#[cfg(feature = "server")]
 pub mod mpc;
-pub mod prg;
+#[cfg(feature = "server")]
 pub mod rpc;
-pub mod ibDCF;
+#[cfg(feature = "server")]
 pub mod equalitytest;
+#[cfg(feature = "server")]
 pub mod sample_covid_data;
+#[cfg(feature = "server")]
 pub mod sample_driving_data;
 
+#[cfg(feature = "server")]
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(feature = "server")]
 pub use crate::field::Dummy;
+#[cfg(feature = "server")]
 pub use crate::field::FieldElm;
+#[cfg(feature = "server")]
+pub use crate::field::RingElm;
+#[cfg(feature = "server")]
 pub use crate::rpc::CollectorClient;
 
-// Additive group, such as (Z_n, +)
-pub trait Group {
-    fn zero() -> Self;
-    fn one() -> Self;
-    fn negate(&mut self);
-    fn reduce(&mut self);
-    fn add(&mut self, other: &Self);
-    fn add_lazy(&mut self, other: &Self);
-    fn mul(&mut self, other: &Self);
-    fn mul_lazy(&mut self, other: &Self);
-    fn sub(&mut self, other: &Self);
-}
+// The client's side lives in the `counttree-client` crate, which builds
+// without the servers' dependencies; its modules and helpers keep their
+// paths here.
+pub use counttree_client as client;
+pub use counttree_client::{encoding, hamming, ibDCF, prg, reference};
+pub use counttree_client::{
+    add_bitstrings, all_bit_vectors, bits_to_string, bits_to_u32, bits_to_u8, string_to_bits, subtract_bitstrings,
+    u32_to_bits, Group, MSB_u32_to_bits,
+};
 
 pub trait Share: Group + prg::FromRng + Clone {
     fn random() -> Self {
@@ -53,187 +63,99 @@ pub trait Share: Group + prg::FromRng + Clone {
     }
 }
 
-pub fn u32_to_bits(nbits: u8, input: u32) -> Vec<bool> {
-    assert!(nbits <= 32);
-
-    let mut out: Vec<bool> = Vec::new();
-    for i in 0..nbits {
-        let bit = (input & (1 << i)) != 0;
-        out.push(bit);
-    }
-    out
-}
-
-pub fn  MSB_u32_to_bits(nbits: u8, input: u32) -> Vec<bool> {
-    assert!(nbits <= 32);
-
-    let mut out: Vec<bool> = Vec::new();
-    for i in (0..nbits).rev() {
-        let bit = (input & (1 << i)) != 0;
-        out.push(bit);
-    }
-    out
+/// Additive shares of a client's weight for a weighted collection, with
+/// the weight capped at `max_weight`. Server 0 gets the first share.
+pub fn share_weight<T: Share + From<u32>>(weight: u32, max_weight: u32) -> (T, T) {
+    T::from(std::cmp::min(weight, max_weight)).share()
 }
 
-pub fn bits_to_u32(bits: &[bool]) -> u32 {
-    assert!(bits.len() <= 32);
-
-    let mut result: u32 = 0;
-    for (i, &bit) in bits.iter().enumerate() {
-        if bit {
-            result |= 1 << (bits.len() - 1 - i);
-        }
-    }
-    result
-}
-
-pub fn string_to_bits(s: &str) -> Vec<bool> {
-    let mut bits = vec![];
-    let byte_vec = s.to_string().into_bytes();
-    for byte in &byte_vec {
-        let mut b = crate::u32_to_bits(8, (*byte).into());
-        bits.append(&mut b);
-    }
-    bits
+/// The number of bits of each of the two numbers in `share_weight_bits`.
+pub fn weight_bits(max_weight: u32) -> usize {
+    (32 - max_weight.leading_zeros()) as usize
 }
 
-pub fn bits_to_u8(bits: &[bool]) -> u8 {
-    assert_eq!(bits.len(), 8);
-    let mut out = 0u8;
-    for i in 0..8 {
-        let b8: u8 = bits[i].into();
-        out |= b8 << i;
-    }
-
-    out
-}
-
-pub fn bits_to_string(bits: &[bool]) -> String {
-    assert!(bits.len() % 8 == 0);
-
-    let mut out: String = "".to_string();
-    let byte_len = bits.len() / 8;
-    for b in 0..byte_len {
-        let byte = &bits[8 * b..8 * (b + 1)];
-        let ubyte = bits_to_u8(&byte);
-        out.push_str(std::str::from_utf8(&[ubyte]).unwrap());
-    }
-
-    out
-}
-
-fn all_bit_vectors(dim: usize) -> Vec<Vec<bool>> {
-    (0..1 << dim).map(|i| {
-        (0..dim).map(|j| (i >> j) & 1 == 1).collect()
-    }).collect()
-}
-
-pub fn add_bitstrings(alpha: &[bool], beta: &[bool]) -> Vec<bool> {
-    let max_len = alpha.len().max(beta.len());
-    let mut alpha_padded = vec![false; max_len - alpha.len()];
-    alpha_padded.extend(alpha);
-    let mut beta_padded = vec![false; max_len - beta.len()];
-    beta_padded.extend(beta);
-    let mut sum = Vec::new();
-    let mut carry = false;
-
-    // Iterate from LSB to MSB
-    for (a, b) in alpha_padded.iter().rev().zip(beta_padded.iter().rev()) {
-        let (s, c) = full_adder(*a, *b, carry);
-        sum.push(s);
-        carry = c;
-    }
-    if carry {
-        sum.push(true);
-    }
-    // Reverse to get MSB first ordering
-    sum.into_iter().rev().collect()
+/// XOR shares of the bits, least significant first, of a client's weight
+/// (capped as in `share_weight`) and then of `max_weight` minus it. The
+/// servers check the additive shares of the weight against them: both
+/// numbers fit in `weight_bits(max_weight)` bits only if the weight is
+/// between zero and `max_weight`. Server 0 gets the first share.
+pub fn share_weight_bits(weight: u32, max_weight: u32) -> (Vec<bool>, Vec<bool>) {
+    use rand::Rng;
+
+    let weight = std::cmp::min(weight, max_weight);
+    let nbits = weight_bits(max_weight) as u8;
+    let mut bits = u32_to_bits(nbits, weight);
+    bits.extend(u32_to_bits(nbits, max_weight - weight));
+
+    let mut rng = prg::thread_rng();
+    bits.into_iter()
+        .map(|bit| {
+            let mask = rng.gen::<bool>();
+            (mask, mask ^ bit)
+        })
+        .unzip()
 }
 
-pub fn subtract_bitstrings(alpha: &[bool], beta: &[bool]) -> Vec<bool> {
-    let max_len = alpha.len().max(beta.len());
-    let mut alpha_padded = vec![false; max_len - alpha.len()];
-    alpha_padded.extend(alpha);
-    let mut beta_padded = vec![false; max_len - beta.len()];
-    beta_padded.extend(beta);
-
-    let mut beta_twos_complement: Vec<bool> = beta_padded.iter().map(|b| !b).collect();
-
-    let mut carry = true;
-    for bit in beta_twos_complement.iter_mut().rev() {
-        let sum = *bit ^ carry;
-        carry = *bit && carry;
-        *bit = sum;
-        if !carry { break; }
-    }
-
-    let mut result = Vec::new();
-    let mut carry = false;
-
-    for (a, b) in alpha_padded.iter().rev().zip(beta_twos_complement.iter().rev()) {
-        let (s, c) = full_adder(*a, *b, carry);
-        result.push(s);
-        carry = c;
-    }
-
-    // If there’s a carry-out, ignore it (overflow)
-
-    // Reverse to get MSB-first ordering
-    result.into_iter().rev().collect()
+/// Additive shares of each entry of a client's vector of auxiliary
+/// attributes (e.g., a one-hot hour of day). Server 0 gets the first
+/// vector.
+pub fn share_attributes<T: Share + From<u32>>(attributes: &[u32]) -> (Vec<T>, Vec<T>) {
+    attributes.iter().map(|&a| T::from(a).share()).unzip()
 }
 
-// Helper function for single-bit addition with carry
-fn full_adder(a: bool, b: bool, carry_in: bool) -> (bool, bool) {
-    let sum = a ^ b ^ carry_in;
-    let carry_out = (a & b) | (b & carry_in) | (a & carry_in);
-    (sum, carry_out)
-}
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn share() {
-        let val = FieldElm::random();
-        let (s0, s1) = val.share();
-        let mut out = FieldElm::zero();
-        out.add(&s0);
-        out.add(&s1);
-        assert_eq!(out, val);
+    #[cfg(feature = "server")]
+    fn share_weight_capped() {
+        for &(weight, expected) in &[(0u32, 0u32), (7, 7), (250, 100)] {
+            let (s0, s1) = share_weight::<FieldElm>(weight, 100);
+            let mut out = s0.clone();
+            out.add(&s1);
+            assert_eq!(out, FieldElm::from(expected));
+
+            let (s0, s1) = share_weight::<fastfield::FE>(weight, 100);
+            let mut out = s0.clone();
+            out.add(&s1);
+            assert_eq!(out, fastfield::FE::from(expected));
+        }
     }
 
     #[test]
-    fn to_bits() {
-        let empty: Vec<bool> = vec![];
-        assert_eq!(u32_to_bits(0, 7), empty);
-        assert_eq!(u32_to_bits(1, 0), vec![false]);
-        assert_eq!(u32_to_bits(2, 0), vec![false, false]);
-        assert_eq!(u32_to_bits(2, 3), vec![true, true]);
-        assert_eq!(u32_to_bits(2, 1), vec![true, false]);
-        assert_eq!(u32_to_bits(12, 65535), vec![true; 12]);
+    fn share_weight_bits_decompose() {
+        for &(weight, expected) in &[(0u32, 0u32), (7, 7), (250, 100)] {
+            let (s0, s1) = share_weight_bits(weight, 100);
+            assert_eq!(s0.len(), 2 * weight_bits(100));
+            let bits = s0.iter().zip(&s1).map(|(a, b)| a ^ b).collect::<Vec<bool>>();
+            let (low, high) = bits.split_at(weight_bits(100));
+            assert_eq!(low, u32_to_bits(7, expected).as_slice());
+            assert_eq!(high, u32_to_bits(7, 100 - expected).as_slice());
+        }
     }
 
     #[test]
-    fn to_string() {
-        let empty: Vec<bool> = vec![];
-        assert_eq!(string_to_bits(""), empty);
-        let avec = vec![true, false, false, false, false, true, true, false];
-        assert_eq!(string_to_bits("a"), avec);
-
-        let mut aaavec = vec![];
-        for _i in 0..3 {
-            aaavec.append(&mut avec.clone());
+    #[cfg(feature = "server")]
+    fn share_attribute_vector() {
+        let attributes = [0u32, 1, 0, 42];
+        let (s0, s1) = share_attributes::<FieldElm>(&attributes);
+        assert_eq!(s0.len(), attributes.len());
+        for i in 0..attributes.len() {
+            let mut out = s0[i].clone();
+            out.add(&s1[i]);
+            assert_eq!(out, FieldElm::from(attributes[i]));
         }
-        assert_eq!(string_to_bits("aaa"), aaavec);
     }
 
     #[test]
-    fn to_from_string() {
-        let s = "basfsdfwefwf";
-        let bitvec = string_to_bits(s);
-        let s2 = bits_to_string(&bitvec);
-
-        assert_eq!(bitvec.len(), s.len() * 8);
-        assert_eq!(s, s2);
+    #[cfg(feature = "server")]
+    fn share() {
+        let val = FieldElm::random();
+        let (s0, s1) = val.share();
+        let mut out = FieldElm::zero();
+        out.add(&s0);
+        out.add(&s1);
+        assert_eq!(out, val);
     }
 }
@@ -1,50 +1,367 @@
-// use crate::FieldElm;
-// use serde::Deserialize;
-// use serde::Serialize;
-// use crate::sketch_dcf;
-//
-// #[derive(Clone, Debug, Serialize, Deserialize)]
-// pub struct TripleShare<T> {
-//     pub a: T,
-//     pub b: T,
-//     pub c: T,
-// }
-//
-// // XXX: Optimization: compress Beaver triples.
-// impl<T> TripleShare<T>
-// where
-//     T: crate::Share + std::fmt::Debug,
-// {
-//     pub fn new() -> [TripleShare<T>; 2] {
-//         let (a_s0, a_s1) = T::share_random();
-//         let (b_s0, b_s1) = T::share_random();
-//
-//         // c = a*b
-//         let mut c = a_s0.clone();
-//         c.add(&a_s1);
-//
-//         let mut b = b_s0.clone();
-//         b.add(&b_s1);
-//
-//         c.mul(&b);
-//
-//         let (c_s0, c_s1) = c.share();
-//
-//         [
-//             TripleShare {
-//                 a: a_s0,
-//                 b: b_s0,
-//                 c: c_s0,
-//             },
-//             TripleShare {
-//                 a: a_s1,
-//                 b: b_s1,
-//                 c: c_s1,
-//             },
-//         ]
-//     }
-// }
-//
+// Beaver multiplication of additively shared values. Clients supply the
+// triples along with their keys, so the servers need neither oblivious
+// transfers nor garbled circuits to multiply: one round of openings per
+// layer of products.
+
+use crate::ibDCF::{ArithDCFKey, ArithIntervalKey};
+use crate::prg::PrgSeed;
+use crate::Share;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One server's share of a Beaver triple (a, b, c = a * b).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TripleShare<T> {
+    pub a: T,
+    pub b: T,
+    pub c: T,
+}
+
+/// One server's share of `n` triples. Both servers expand their shares of
+/// each `a` and `b` from their seed, and so does the first server for `c`.
+/// The second server's shares of `c` come explicitly, so a triple costs
+/// one group element on the wire instead of six.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TripleShares<T> {
+    pub key_idx: bool,
+    pub seed: PrgSeed,
+    pub n: usize,
+    pub c: Vec<T>,
+}
+
+impl<T> TripleShare<T>
+where
+    T: Share,
+{
+    // Triple `i` from `seed`, with a zero `c` unless `with_c`.
+    fn expand(seed: &PrgSeed, i: usize, with_c: bool) -> TripleShare<T> {
+        let mut rng = seed.to_rng_at(i as u64);
+        let mut out = TripleShare {
+            a: T::zero(),
+            b: T::zero(),
+            c: T::zero(),
+        };
+        out.a.from_rng(&mut rng);
+        out.b.from_rng(&mut rng);
+        if with_c {
+            out.c.from_rng(&mut rng);
+        }
+        out
+    }
+
+    /// This server's shares of x - a and y - b, which the servers open to
+    /// multiply x by y.
+    pub fn mask(&self, x: &T, y: &T) -> (T, T) {
+        let mut d = x.clone();
+        d.sub(&self.a);
+        let mut e = y.clone();
+        e.sub(&self.b);
+        (d, e)
+    }
+
+    /// This server's share of x * y, given the opened d = x - a and
+    /// e = y - b. As x * y = c + d * b + e * a + d * e, the first server
+    /// adds in the d * e.
+    pub fn product(&self, first: bool, d: &T, e: &T) -> T {
+        let mut out = self.c.clone();
+
+        let mut tmp = d.clone();
+        tmp.mul(&self.b);
+        out.add(&tmp);
+
+        tmp = e.clone();
+        tmp.mul(&self.a);
+        out.add(&tmp);
+
+        if first {
+            tmp = d.clone();
+            tmp.mul(e);
+            out.add(&tmp);
+        }
+        out
+    }
+
+    /// This server's shares of rho = r * a - x and sigma = b - y, where
+    /// `other` is (x, y, z), which the servers open to check this triple
+    /// against `other`; see `sacrifice`.
+    pub fn sacrifice_mask(&self, other: &TripleShare<T>, r: &T) -> (T, T) {
+        let mut rho = self.a.clone();
+        rho.mul(r);
+        rho.sub(&other.a);
+        let mut sigma = self.b.clone();
+        sigma.sub(&other.b);
+        (rho, sigma)
+    }
+
+    /// This server's share of r * c - z - sigma * x - rho * y - sigma * rho,
+    /// given the opened rho and sigma. If c = a * b + e and z = x * y + f,
+    /// this is r * e - f, so unless both triples are good it is zero only
+    /// for one `r`. As in `product`, the first server adds in the
+    /// sigma * rho.
+    pub fn sacrifice(&self, first: bool, other: &TripleShare<T>, r: &T, rho: &T, sigma: &T) -> T {
+        let mut out = self.c.clone();
+        out.mul(r);
+        out.sub(&other.c);
+
+        let mut tmp = sigma.clone();
+        tmp.mul(&other.a);
+        out.sub(&tmp);
+
+        tmp = rho.clone();
+        tmp.mul(&other.b);
+        out.sub(&tmp);
+
+        if first {
+            tmp = sigma.clone();
+            tmp.mul(rho);
+            out.sub(&tmp);
+        }
+        out
+    }
+}
+
+impl<T> TripleShares<T>
+where
+    T: Share,
+{
+    pub fn gen(n: usize) -> (TripleShares<T>, TripleShares<T>) {
+        let seed0 = PrgSeed::random();
+        let seed1 = PrgSeed::random();
+
+        // c = a*b
+        let c = (0..n)
+            .map(|i| {
+                let t0 = TripleShare::<T>::expand(&seed0, i, true);
+                let t1 = TripleShare::<T>::expand(&seed1, i, false);
+
+                let mut c = t0.a.clone();
+                c.add(&t1.a);
+
+                let mut b = t0.b.clone();
+                b.add(&t1.b);
+
+                c.mul(&b);
+                c.sub(&t0.c);
+                c
+            })
+            .collect();
+
+        (
+            TripleShares {
+                key_idx: false,
+                seed: seed0,
+                n,
+                c: vec![],
+            },
+            TripleShares {
+                key_idx: true,
+                seed: seed1,
+                n,
+                c,
+            },
+        )
+    }
+
+    /// This server's share of triple `i`.
+    pub fn get(&self, i: usize) -> TripleShare<T> {
+        assert!(i < self.n, "the client has only {} triples", self.n);
+        let mut out = TripleShare::expand(&self.seed, i, !self.key_idx);
+        if self.key_idx {
+            out.c = self.c[i].clone();
+        }
+        out
+    }
+}
+
+impl<T> TripleShares<T> {
+    /// Whether these are `n` triples for the server with `key_idx`. That
+    /// c = a * b is for `check_triples`.
+    pub fn well_formed(&self, key_idx: bool, n: usize) -> bool {
+        self.key_idx == key_idx && self.n == n && self.c.len() == if key_idx { n } else { 0 }
+    }
+}
+
+/// Multiplies together the factors of each job, which all have the same
+/// number of them, pairwise over rounds: `k` factors take ceil(log2(k))
+/// rounds and k - 1 triples. `triple(j, i)` is this server's share of the
+/// triple for the `i`-th multiplication of job `j`, and `swap` sends this
+/// server's masked shares to the other server and returns the other
+/// server's. `first` is whether this is the first server. Returns this
+/// server's share of each job's product.
+pub fn multiply_all<T, F, S>(first: bool, mut factors: Vec<Vec<T>>, triple: F, mut swap: S) -> Vec<T>
+where
+    T: Share + Send + Sync,
+    F: Fn(usize, usize) -> TripleShare<T> + Sync,
+    S: FnMut(&[T]) -> Vec<T>,
+{
+    let mut done = 0;
+    while factors.first().map_or(false, |f| f.len() > 1) {
+        let pairs = factors[0].len() / 2;
+        let triples = (0..factors.len())
+            .into_par_iter()
+            .map(|j| (0..pairs).map(|i| triple(j, done + i)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let masked = factors
+            .par_iter()
+            .zip(triples.par_iter())
+            .flat_map(|(f, ts)| {
+                ts.iter()
+                    .enumerate()
+                    .flat_map(|(i, t)| {
+                        let (d, e) = t.mask(&f[2 * i], &f[2 * i + 1]);
+                        vec![d, e]
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<T>>();
+        let theirs = swap(&masked);
+        assert_eq!(theirs.len(), masked.len());
+
+        factors = factors
+            .par_iter()
+            .zip(triples.par_iter())
+            .enumerate()
+            .map(|(j, (f, ts))| {
+                let mut next = ts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, t)| {
+                        let at = 2 * (j * pairs + i);
+                        let mut d = masked[at].clone();
+                        d.add(&theirs[at]);
+                        let mut e = masked[at + 1].clone();
+                        e.add(&theirs[at + 1]);
+                        t.product(first, &d, &e)
+                    })
+                    .collect::<Vec<_>>();
+                if f.len() % 2 == 1 {
+                    next.push(f[f.len() - 1].clone());
+                }
+                next
+            })
+            .collect();
+        done += pairs;
+    }
+
+    factors.into_iter().map(|mut f| f.pop().expect("a job needs a factor")).collect()
+}
+
+/// This server's share of a triple and of the triple it is checked
+/// against, with the servers' joint random `r` for
+/// `TripleShare::sacrifice` and a random weight.
+pub type TripleCheck<T> = (TripleShare<T>, TripleShare<T>, T, T);
+
+/// Checks each job's triples against as many others, which are then used
+/// up: `jobs[j]` holds a `TripleCheck` for each triple of job `j`. The
+/// servers open the masks and then the weighted sum of each job's checks,
+/// which is zero when its triples are good and, otherwise, except with
+/// probability about two over the size of the group. `swap` and `first`
+/// are as in `multiply_all`. Returns whether each job passes.
+pub fn check_triples<T, S>(first: bool, jobs: &[Vec<TripleCheck<T>>], mut swap: S) -> Vec<bool>
+where
+    T: Share + Send + Sync + PartialEq,
+    S: FnMut(&[T]) -> Vec<T>,
+{
+    let masked = jobs
+        .par_iter()
+        .flat_map(|job| {
+            job.iter()
+                .flat_map(|(t, other, r, _)| {
+                    let (rho, sigma) = t.sacrifice_mask(other, r);
+                    vec![rho, sigma]
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<T>>();
+    let theirs = swap(&masked);
+    assert_eq!(theirs.len(), masked.len());
+
+    let mut at = 0;
+    let starts = jobs
+        .iter()
+        .map(|job| {
+            let start = at;
+            at += 2 * job.len();
+            start
+        })
+        .collect::<Vec<_>>();
+    let sums = jobs
+        .par_iter()
+        .zip(starts.par_iter())
+        .map(|(job, &start)| {
+            let mut sum = T::zero();
+            for (i, (t, other, r, weight)) in job.iter().enumerate() {
+                let at = start + 2 * i;
+                let mut rho = masked[at].clone();
+                rho.add(&theirs[at]);
+                let mut sigma = masked[at + 1].clone();
+                sigma.add(&theirs[at + 1]);
+                let mut check = t.sacrifice(first, other, r, &rho, &sigma);
+                check.mul(weight);
+                sum.add(&check);
+            }
+            sum
+        })
+        .collect::<Vec<T>>();
+    let other_sums = swap(&sums);
+    assert_eq!(other_sums.len(), sums.len());
+
+    sums.into_iter()
+        .zip(other_sums)
+        .map(|(mut sum, other)| {
+            sum.add(&other);
+            sum == T::zero()
+        })
+        .collect()
+}
+
+/// One server's share of a client's submission for the Beaver backend: an
+/// interval key with arithmetic outputs on every dimension, and triples in
+/// both of the collection's groups for multiplying the dimensions'
+/// indicators together.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BeaverKey<T, U> {
+    pub dims: Vec<ArithIntervalKey<(T, U)>>,
+    pub triples: TripleShares<T>,
+    pub triples_last: TripleShares<U>,
+}
+
+impl<T, U> BeaverKey<T, U>
+where
+    T: Share,
+    U: Share,
+{
+    /// Keys for the L-infinity ball around `alpha` with radius `sizes[i]` on
+    /// dimension `i`, clipped to the domain, with `n_triples` triples in
+    /// each group for the crawl, followed by as many for the servers to
+    /// check them against.
+    pub fn gen_l_inf_box(alpha: &[Vec<bool>], sizes: &[u32], n_triples: usize) -> (BeaverKey<T, U>, BeaverKey<T, U>) {
+        assert_eq!(alpha.len(), sizes.len());
+
+        let (dims0, dims1) = alpha
+            .iter()
+            .zip(sizes)
+            .map(|(a, &size)| ArithDCFKey::gen_l_inf_ball(a, size))
+            .unzip();
+        let triples = TripleShares::gen(2 * n_triples);
+        let triples_last = TripleShares::gen(2 * n_triples);
+
+        (
+            BeaverKey {
+                dims: dims0,
+                triples: triples.0,
+                triples_last: triples_last.0,
+            },
+            BeaverKey {
+                dims: dims1,
+                triples: triples.1,
+                triples_last: triples_last.1,
+            },
+        )
+    }
+}
+
 // // We will compute in MPC:
 // //    \sum_i [ (x_i * y_i) + z_i ]
 // #[derive(Clone)]
@@ -321,32 +638,110 @@
 //     }
 // }
 //
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     use crate::FieldElm;
-//     use crate::Group;
-//
-//     #[test]
-//     fn triple() {
-//         let [t0, t1] = TripleShare::<FieldElm>::new();
-//
-//         debug_assert!(t0.a != FieldElm::zero());
-//         debug_assert!(t0.b != FieldElm::zero());
-//         debug_assert!(t0.c != FieldElm::zero());
-//
-//         let mut a = t0.a.clone();
-//         a.add(&t1.a);
-//
-//         let mut b = t0.b.clone();
-//         b.add(&t1.b);
-//
-//         let mut c = t0.c.clone();
-//         c.add(&t1.c);
-//
-//         let mut ab = a.clone();
-//         ab.mul(&b);
-//
-//         assert_eq!(ab, c);
-//     }
-// }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fastfield::FE;
+    use crate::Group;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn triple() {
+        let (s0, s1) = TripleShares::<FE>::gen(20);
+        for i in 0..20 {
+            let t0 = s0.get(i);
+            let t1 = s1.get(i);
+
+            let mut a = t0.a.clone();
+            a.add(&t1.a);
+
+            let mut b = t0.b.clone();
+            b.add(&t1.b);
+
+            let mut c = t0.c.clone();
+            c.add(&t1.c);
+
+            let mut ab = a.clone();
+            ab.mul(&b);
+
+            assert_eq!(ab, c);
+        }
+        assert!(s0.well_formed(false, 20) && s1.well_formed(true, 20));
+        assert!(!s1.well_formed(false, 20) && !s1.well_formed(true, 19));
+    }
+
+    #[test]
+    fn sacrifice_triples() {
+        // Three jobs of four triples, each checked against one of the last
+        // twelve. The second job has a bad triple, and the third a bad
+        // triple to check against.
+        let (triples0, mut triples1) = TripleShares::<FE>::gen(24);
+        triples1.c[6].add(&FE::one());
+        triples1.c[12 + 9].add(&FE::one());
+        let coeffs = (0..12).map(|_| (FE::random(), FE::random())).collect::<Vec<_>>();
+        let jobs = |triples: &TripleShares<FE>| {
+            (0..3)
+                .map(|j| {
+                    (4 * j..4 * j + 4)
+                        .map(|i| (triples.get(i), triples.get(12 + i), coeffs[i].0.clone(), coeffs[i].1.clone()))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let (jobs0, jobs1) = (jobs(&triples0), jobs(&triples1));
+
+        let (to1, from0) = channel();
+        let (to0, from1) = channel();
+        let server1 = std::thread::spawn(move || {
+            check_triples(false, &jobs1, |ours| {
+                to0.send(ours.to_vec()).unwrap();
+                from0.recv().unwrap()
+            })
+        });
+        let out0 = check_triples(true, &jobs0, |ours| {
+            to1.send(ours.to_vec()).unwrap();
+            from1.recv().unwrap()
+        });
+        let out1 = server1.join().unwrap();
+
+        assert_eq!(out0, vec![true, false, false]);
+        assert_eq!(out0, out1);
+    }
+
+    #[test]
+    fn multiply_jobs() {
+        // Jobs with five factors each, so that a factor sits out a round.
+        let jobs = (0..30u32)
+            .map(|j| (0..5).map(|k| FE::from((j + k) % 3)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let (shares0, shares1): (Vec<Vec<FE>>, Vec<Vec<FE>>) = jobs
+            .iter()
+            .map(|factors| factors.iter().map(|f| f.share()).unzip())
+            .unzip();
+        // Each job's multiplications use their own triples.
+        let (triples0, triples1) = TripleShares::<FE>::gen(30 * 4);
+
+        let (to1, from0) = channel();
+        let (to0, from1) = channel();
+        let server1 = std::thread::spawn(move || {
+            multiply_all(false, shares1, |j, i| triples1.get(4 * j + i), |ours| {
+                to0.send(ours.to_vec()).unwrap();
+                from0.recv().unwrap()
+            })
+        });
+        let out0 = multiply_all(true, shares0, |j, i| triples0.get(4 * j + i), |ours| {
+            to1.send(ours.to_vec()).unwrap();
+            from1.recv().unwrap()
+        });
+        let out1 = server1.join().unwrap();
+
+        for ((factors, s0), s1) in jobs.iter().zip(out0).zip(out1) {
+            let mut want = FE::one();
+            factors.iter().for_each(|f| want.mul(f));
+            let mut got = s0;
+            got.add(&s1);
+            assert_eq!(got, want);
+        }
+    }
+}
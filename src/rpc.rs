@@ -1,21 +1,144 @@
 use crate::collect;
-use crate::FieldElm;
+use crate::{FieldElm, RingElm, Share};
 use crate::fastfield::FE;
+use crate::field::OtBlocks;
 
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
-use crate::ibDCF::ibDCFKey;
+use crate::collect::{ClientKey, WeightShare};
+use std::fmt::Debug;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ResetRequest {}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct AddKeysRequest {
-    pub keys: Vec<Vec<(ibDCFKey, ibDCFKey)>>,
+pub struct AddKeysRequest<T, U> {
+    // The ibDCF keys of interval keys go over the wire in the compact
+    // format of `ibDCFKey::to_bytes`.
+    pub keys: Vec<ClientKey<T, U>>,
+    // This server's share of each client's weight, for weighted collections.
+    pub weights: Option<Vec<WeightShare<T, U>>>,
+    // This server's shares of each client's auxiliary attributes.
+    pub attributes: Option<Vec<Vec<U>>>,
 }
 
+/// Requests and responses that hold shares, in the groups that the
+/// collection counts in; see `config::ShareType`.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct TreeInitRequest {}
+pub enum Shares<F, R> {
+    // In `FE` for the crawl and `FieldElm` for the last level.
+    Field(F),
+    // In `RingElm` throughout.
+    Ring(R),
+}
+
+pub type AddKeys = Shares<AddKeysRequest<FE, FieldElm>, AddKeysRequest<RingElm, RingElm>>;
+pub type CrawlShares = Shares<Vec<FE>, Vec<RingElm>>;
+pub type CrawlLastShares = Shares<Vec<FieldElm>, Vec<RingElm>>;
+pub type AttributeShares = Shares<Vec<Vec<FieldElm>>, Vec<Vec<RingElm>>>;
+pub type FinalShares = Shares<Vec<collect::Result<FieldElm>>, Vec<collect::Result<RingElm>>>;
+
+impl<F, R> Shares<F, R> {
+    /// The shares in the field; panics if they are in the ring.
+    pub fn field(self) -> F {
+        match self {
+            Shares::Field(f) => f,
+            Shares::Ring(_) => panic!("the server counts in the ring, not the field"),
+        }
+    }
+
+    /// The shares in the ring; panics if they are in the field.
+    pub fn ring(self) -> R {
+        match self {
+            Shares::Ring(r) => r,
+            Shares::Field(_) => panic!("the server counts in the field, not the ring"),
+        }
+    }
+}
+
+/// A collection's groups, `T` for the crawl and `U` for the last level,
+/// and the variant of `Shares` that their shares go over the wire in.
+pub trait ShareGroups {
+    type T: Share + Clone + Debug + PartialOrd + From<u32> + Send + Sync + OtBlocks + Serialize + DeserializeOwned;
+    type U: Share + Clone + Debug + PartialOrd + From<u32> + Send + Sync + OtBlocks + Serialize + DeserializeOwned;
+
+    fn add_keys(add: AddKeysRequest<Self::T, Self::U>) -> AddKeys;
+    fn crawl(shares: CrawlShares) -> Vec<Self::T>;
+    fn crawl_last(shares: CrawlLastShares) -> Vec<Self::U>;
+    fn attributes(shares: AttributeShares) -> Vec<Vec<Self::U>>;
+    fn final_shares(shares: FinalShares) -> Vec<collect::Result<Self::U>>;
+}
+
+/// `ShareType::Field`.
+pub struct FieldGroups;
+
+/// `ShareType::Ring`.
+pub struct RingGroups;
+
+impl ShareGroups for FieldGroups {
+    type T = FE;
+    type U = FieldElm;
+
+    fn add_keys(add: AddKeysRequest<FE, FieldElm>) -> AddKeys {
+        Shares::Field(add)
+    }
+
+    fn crawl(shares: CrawlShares) -> Vec<FE> {
+        shares.field()
+    }
+
+    fn crawl_last(shares: CrawlLastShares) -> Vec<FieldElm> {
+        shares.field()
+    }
+
+    fn attributes(shares: AttributeShares) -> Vec<Vec<FieldElm>> {
+        shares.field()
+    }
+
+    fn final_shares(shares: FinalShares) -> Vec<collect::Result<FieldElm>> {
+        shares.field()
+    }
+}
+
+impl ShareGroups for RingGroups {
+    type T = RingElm;
+    type U = RingElm;
+
+    fn add_keys(add: AddKeysRequest<RingElm, RingElm>) -> AddKeys {
+        Shares::Ring(add)
+    }
+
+    fn crawl(shares: CrawlShares) -> Vec<RingElm> {
+        shares.ring()
+    }
+
+    fn crawl_last(shares: CrawlLastShares) -> Vec<RingElm> {
+        shares.ring()
+    }
+
+    fn attributes(shares: AttributeShares) -> Vec<Vec<RingElm>> {
+        shares.ring()
+    }
+
+    fn final_shares(shares: FinalShares) -> Vec<collect::Result<RingElm>> {
+        shares.ring()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VerifyKeysRequest {
+    pub gc_sender: bool
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TreeInitRequest {
+    // Start from every node one level above the leaves instead of the root.
+    pub full_domain: bool,
+    // Which server sends first when the servers check the clients' keys on
+    // the way down, as in `VerifyKeysRequest`.
+    pub gc_sender: bool,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TreeCrawlRequest {
@@ -27,6 +150,11 @@ pub struct TreeCrawlLastRequest {
     pub gc_sender: bool
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TreeAttributesRequest {
+    pub gc_sender: bool
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TreePruneRequest {
     pub keep: Vec<bool>,
@@ -56,11 +184,13 @@ pub struct FinalSharesRequest {}
 #[tarpc::service]
 pub trait Collector {
     async fn reset(rst: ResetRequest) -> String;
-    async fn add_keys(add: AddKeysRequest) -> String;
+    async fn add_keys(add: AddKeys) -> String;
+    async fn verify_keys(req: VerifyKeysRequest) -> usize;
     async fn tree_init(req: TreeInitRequest) -> String;
-    async fn tree_crawl(req: TreeCrawlRequest) -> Vec<FE>;
-    async fn tree_crawl_last(req: TreeCrawlLastRequest) -> Vec<FieldElm>;
+    async fn tree_crawl(req: TreeCrawlRequest) -> CrawlShares;
+    async fn tree_crawl_last(req: TreeCrawlLastRequest) -> CrawlLastShares;
     async fn tree_prune(req: TreePruneRequest) -> String;
     async fn tree_prune_last(req: TreePruneLastRequest) -> String;
-    async fn final_shares(req: FinalSharesRequest) -> Vec<collect::Result<FieldElm>>;
+    async fn tree_attributes(req: TreeAttributesRequest) -> AttributeShares;
+    async fn final_shares(req: FinalSharesRequest) -> FinalShares;
 }
@@ -6,6 +6,7 @@ use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 use rand::distributions::Uniform;
 use rayon::prelude::*;
 use serde::Deserialize;
+use crate::encoding::{Encoder, OrderedF64};
 
 #[derive(Debug, Deserialize)]
 struct CountyCentroid {
@@ -29,11 +30,6 @@ fn load_centroids(path: &str) -> Result<(HashMap<String, (f64, f64)>, HashSet<St
     Ok((map, fips_set))
 }
 
-fn f64_to_bool_vec(value: f64) -> Vec<bool> {
-    let bits = value.to_bits();
-    (0..64).map(|i| ((bits >> (63 - i)) & 1) == 1).collect()
-}
-
 fn fuzzy_coords((lat, lon): (f64, f64), decimal_places: usize, rng: &mut StdRng) -> (f64, f64) {
     let noise_magnitude = 0.5 / 10f64.powi(decimal_places as i32);
     (
@@ -148,10 +144,10 @@ pub fn sample_covid_locations(
         let sample = match fuzz_factor {
             Some(places) => {
                 let (lat, lon) = uniform_in_square(coords.0, coords.1, places, &mut rng);
-                vec![f64_to_bool_vec(lat), f64_to_bool_vec(lon)]
+                vec![OrderedF64.encode(lat), OrderedF64.encode(lon)]
             },
             None => {
-                vec![f64_to_bool_vec(coords.0), f64_to_bool_vec(coords.1)]
+                vec![OrderedF64.encode(coords.0), OrderedF64.encode(coords.1)]
             }
         };
 
@@ -4,38 +4,16 @@ use std::io::{BufReader, BufWriter};
 use csv::{Reader, Writer, StringRecord};
 use rand::{seq::IteratorRandom, rngs::StdRng, SeedableRng};
 use std::path::Path;
-
-const CENTIDEGREES_SCALE: f64 = 100.0; // 2 decimal places (~1.1 km precision)
+use crate::encoding::{from_centidegrees, to_centidegrees, Encoder, OffsetBinary};
 
 // Convert lat/lng floats to centidegrees (i16)
 fn geo_to_int(lat: f64, lng: f64) -> (i16, i16) {
-    let lat_int = (lat * CENTIDEGREES_SCALE).round() as i16;
-    let lng_int = (lng * CENTIDEGREES_SCALE).round() as i16;
-    (lat_int, lng_int)
+    (to_centidegrees(lat), to_centidegrees(lng))
 }
 
 // Convert centidegrees back to floats
 fn int_to_geo(lat_int: i16, lng_int: i16) -> (f64, f64) {
-    let lat = f64::from(lat_int) / CENTIDEGREES_SCALE;
-    let lng = f64::from(lng_int) / CENTIDEGREES_SCALE;
-    (lat, lng)
-}
-
-/// Convert i16 to 16-bit vector (MSB first)
-pub fn i16_to_bitvec(value: i16) -> Vec<bool> {
-    let bits = value as u16; // Safe for bit ops
-    (0..16).map(|i| (bits >> (15 - i)) & 1 == 1).collect()
-}
-
-/// Convert 16-bit vector back to i16
-fn bitvec_to_i16(bits: &[bool]) -> i16 {
-    let mut value: u16 = 0;
-    for (i, &bit) in bits.iter().enumerate() {
-        if bit {
-            value |= 1 << (15 - i);
-        }
-    }
-    value as i16
+    (from_centidegrees(lat_int), from_centidegrees(lng_int))
 }
 
 /// Sample start locations as 16-bit centidegrees
@@ -62,8 +40,8 @@ fn bitvec_to_i16(bits: &[bool]) -> i16 {
 //                 record[13].parse::<f64>()?, // start_lon
 //             );
 //             Ok(vec![
-//                 i16_to_bitvec(lat_int),
-//                 i16_to_bitvec(lon_int),
+//                 OffsetBinary.encode(lat_int),
+//                 OffsetBinary.encode(lon_int),
 //             ])
 //         })
 //         .collect()
@@ -132,8 +110,8 @@ pub fn save_heavy_hitters(
     }
 
     for (i, chunk) in heavy_hitters.chunks_exact(2).enumerate() {
-        let lat = bitvec_to_i16(&chunk[0]);
-        let lon = bitvec_to_i16(&chunk[1]);
+        let lat = OffsetBinary.decode(&chunk[0]);
+        let lon = OffsetBinary.decode(&chunk[1]);
         let (lat_float, lon_float) = int_to_geo(lat, lon);
 
         wtr.write_record(&[
@@ -150,11 +128,11 @@ pub fn save_heavy_hitters(
 fn test_austin_coords() {
     let (lat, lon) = (30.26, -97.74); // Austin, 2 decimal places
     let (lat_int, lon_int) = geo_to_int(lat, lon);
-    let bits_lat = i16_to_bitvec(lat_int);
-    let bits_lon = i16_to_bitvec(lon_int);
+    let bits_lat = OffsetBinary.encode(lat_int);
+    let bits_lon = OffsetBinary.encode(lon_int);
 
-    let reconstructed_lat = bitvec_to_i16(&bits_lat);
-    let reconstructed_lon = bitvec_to_i16(&bits_lon);
+    let reconstructed_lat = OffsetBinary.decode(&bits_lat);
+    let reconstructed_lon = OffsetBinary.decode(&bits_lon);
     let (lat_back, lon_back) = int_to_geo(reconstructed_lat, reconstructed_lon);
 
     assert_eq!(lat, lat_back); // Exact match (no floating-point errors)
@@ -1,3 +1,5 @@
+#![cfg(feature = "server")]
+
 // use counttree::collect::*;
 // use counttree::prg;
 // use counttree::sketch::*;
@@ -11,8 +13,7 @@
 //
 //     let strlen = crate::string_to_bits(&client_strings[0]).len();
 //
-//     let seed = prg::PrgSeed::random();
-//     let mut col0 = KeyCollection::new(&seed, strlen);
+// //     let mut col0 = KeyCollection::new(&seed, strlen);
 //     let mut col1 = KeyCollection::new(&seed, strlen);
 //
 //     for cstr in &client_strings {
@@ -177,8 +178,7 @@
 //     let nclients = 10;
 //     let strlen = crate::string_to_bits(&client_strings[0]).len();
 //
-//     let seed = prg::PrgSeed::random();
-//     let mut col0 = KeyCollection::new(&seed, strlen);
+// //     let mut col0 = KeyCollection::new(&seed, strlen);
 //     let mut col1 = KeyCollection::new(&seed, strlen);
 //     // use cpuprofiler::PROFILER;
 //
@@ -249,3 +249,557 @@
 //     }
 // }
 
+
+use counttree::collect::{ClientKey, KeyCollection, KeyKind, KeyLayout, WeightShare};
+use counttree::fastfield::FE;
+use counttree::ibDCF::{ibDCFKey, to_union, ArithDCFKey, CorWord};
+use counttree::mpc::BeaverKey;
+use counttree::field::OtBlocks;
+use counttree::{prg, share_weight, share_weight_bits, FieldElm, Group, RingElm, Share, MSB_u32_to_bits};
+use scuttlebutt::SyncChannel;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+type Channel = SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>;
+
+// The two ends of a local TCP connection.
+fn stream_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream0 = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream1, _) = listener.accept().unwrap();
+    (stream0, stream1)
+}
+
+fn channel(s: TcpStream) -> Channel {
+    SyncChannel::new(BufReader::new(s.try_clone().unwrap()), BufWriter::new(s))
+}
+
+// The two ends of a connection between the servers.
+fn channel_pair() -> (Channel, Channel) {
+    let (stream0, stream1) = stream_pair();
+    (channel(stream0), channel(stream1))
+}
+
+// The two ends of a connection between the servers that passes through a
+// relay, and the relay's threads, which return all that each server sent
+// once both ends are dropped.
+fn recorded_channel_pair() -> (Channel, Channel, [JoinHandle<Vec<u8>>; 2]) {
+    let (end0, relay0) = stream_pair();
+    let (relay1, end1) = stream_pair();
+    let forward = |mut from: TcpStream, mut to: TcpStream| {
+        thread::spawn(move || {
+            let mut sent = vec![];
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = from.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                sent.extend_from_slice(&buf[..n]);
+                to.write_all(&buf[..n]).unwrap();
+            }
+            // The other server may be gone already.
+            let _ = to.shutdown(Shutdown::Write);
+            sent
+        })
+    };
+    let sent0 = forward(relay0.try_clone().unwrap(), relay1.try_clone().unwrap());
+    let sent1 = forward(relay1, relay0);
+    (channel(end0), channel(end1), [sent0, sent1])
+}
+
+// Runs `f` on both servers at once, the first one as the garbler.
+fn on_both<T, U, R, F>(cols: &mut [KeyCollection<T, U>; 2], channels: &mut [Channel; 2], f: F) -> (R, R)
+where
+    T: Send,
+    U: Send,
+    R: Send,
+    F: Fn(&mut KeyCollection<T, U>, bool, &mut Channel) -> R + Sync,
+{
+    let f = &f;
+    let (cols0, cols1) = cols.split_at_mut(1);
+    let (channels0, channels1) = channels.split_at_mut(1);
+    crossbeam::scope(|s| {
+        let first = s.spawn(move |_| f(&mut cols0[0], true, &mut channels0[0]));
+        let second = f(&mut cols1[0], false, &mut channels1[0]);
+        (first.join().unwrap(), second)
+    })
+    .unwrap()
+}
+
+// The values of the leaves that the servers find with at least
+// `threshold` clients, after a crawl of every level.
+fn crawl<T, U>(cols: &mut [KeyCollection<T, U>; 2], channels: &mut [Channel; 2], depth: usize, threshold: u32) -> Vec<(Vec<Vec<bool>>, U)>
+where
+    T: Share + Clone + std::fmt::Debug + PartialOrd + From<u32> + Send + Sync + OtBlocks,
+    U: Share + Clone + std::fmt::Debug + PartialOrd + From<u32> + Send + Sync + OtBlocks,
+{
+    let nclients = cols[0].keys.len();
+    on_both(cols, channels, |col, _, _| col.tree_init());
+    for _ in 0..depth - 1 {
+        let (vals0, vals1) = on_both(cols, channels, |col, gc_sender, channel| col.tree_crawl(gc_sender, &mut [channel]));
+        let keep = KeyCollection::<T, U>::keep_values(nclients, &T::from(threshold), &vals0, &vals1).unwrap();
+        on_both(cols, channels, |col, _, _| col.tree_prune(&keep));
+    }
+    let (vals0, vals1) = on_both(cols, channels, |col, gc_sender, channel| col.tree_crawl_last(gc_sender, &mut [channel]));
+    let keep = KeyCollection::<T, U>::keep_values_last(nclients, &U::from(threshold), &vals0, &vals1).unwrap();
+    on_both(cols, channels, |col, _, _| col.tree_prune_last(&keep));
+    let (res0, res1) = on_both(cols, channels, |col, _, _| col.final_shares());
+    KeyCollection::<T, U>::final_values(&res0, &res1)
+        .into_iter()
+        .map(|res| (res.path, res.value))
+        .collect()
+}
+
+#[test]
+fn crawl_disables_malformed_keys() {
+    let nbits = 4;
+    let layout = KeyLayout {
+        kind: KeyKind::Box,
+        dim_lens: vec![nbits, nbits],
+        pairs_per_dim: vec![1, 1],
+        max_weight: 1,
+        n_attributes: 0,
+    };
+    let mut cols = [
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout.clone()),
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout),
+    ];
+    let (channel0, channel1) = channel_pair();
+    let mut channels = [channel0, channel1];
+
+    let points = [(3, 5), (3, 6), (9, 9), (15, 0)];
+    for &(x, y) in &points {
+        let point = vec![MSB_u32_to_bits(nbits as u8, x as u32), MSB_u32_to_bits(nbits as u8, y as u32)];
+        let (k0, k1) = ibDCFKey::gen_l_inf_ball(point, 1);
+        cols[0].add_key(ClientKey::Box(to_union(k0)), None, vec![]);
+        cols[1].add_key(ClientKey::Box(to_union(k1)), None, vec![]);
+    }
+
+    // A client whose keys have a shorter dimension than the others'.
+    let short = vec![MSB_u32_to_bits(nbits as u8, 7), MSB_u32_to_bits(nbits as u8 - 1, 2)];
+    let (k0, k1) = ibDCFKey::gen_l_inf_ball(short, 1);
+    cols[0].add_key(ClientKey::Box(to_union(k0)), None, vec![]);
+    cols[1].add_key(ClientKey::Box(to_union(k1)), None, vec![]);
+
+    // A client whose keys flip the output below one child of the root, so
+    // that it would count in half of the domain.
+    let point = vec![MSB_u32_to_bits(nbits as u8, 12), MSB_u32_to_bits(nbits as u8, 12)];
+    let (mut k0, mut k1) = ibDCFKey::gen_l_inf_ball(point, 1);
+    for k in [&mut k0[0].1, &mut k1[0].1] {
+        let cw = &k.cor_words[0];
+        let (y0, y1) = cw.y_bits();
+        k.cor_words[0] = CorWord::new(cw.seed.clone(), cw.bits(), (!y0, y1));
+    }
+    cols[0].add_key(ClientKey::Box(to_union(k0)), None, vec![]);
+    cols[1].add_key(ClientKey::Box(to_union(k1)), None, vec![]);
+
+    let (disabled0, disabled1) = on_both(&mut cols, &mut channels, |col, gc_sender, channel| col.verify_keys(gc_sender, &[1, 1], channel));
+    assert_eq!((disabled0, disabled1), (1, 1));
+
+    let mut found = crawl(&mut cols, &mut channels, nbits, 1);
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    assert!(cols.iter().all(|col| !col.keys[points.len() + 1].0));
+
+    // Every point with a client within distance one on both dimensions.
+    let mut expected = vec![];
+    for x in 0..16i32 {
+        for y in 0..16i32 {
+            let count = points.iter().filter(|&&(px, py)| (px - x).abs() <= 1 && (py - y).abs() <= 1).count();
+            if count > 0 {
+                let path = vec![MSB_u32_to_bits(nbits as u8, x as u32), MSB_u32_to_bits(nbits as u8, y as u32)];
+                expected.push((path, FieldElm::from(count as u32)));
+            }
+        }
+    }
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn crawl_disables_wrong_value_words() {
+    let nbits = 5;
+    let layout = KeyLayout {
+        kind: KeyKind::Arith,
+        dim_lens: vec![nbits],
+        pairs_per_dim: vec![],
+        max_weight: 1,
+        n_attributes: 0,
+    };
+    let mut cols = [
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout.clone()),
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout),
+    ];
+    let (channel0, channel1) = channel_pair();
+    let mut channels = [channel0, channel1];
+
+    let points = [3i32, 4, 20];
+    for &x in &points {
+        let (k0, k1) = ArithDCFKey::gen_l_inf_ball(&MSB_u32_to_bits(nbits as u8, x as u32), 2);
+        cols[0].add_key(ClientKey::Arith(k0), None, vec![]);
+        cols[1].add_key(ClientKey::Arith(k1), None, vec![]);
+    }
+
+    // A client whose boundaries are well formed, but whose left key adds
+    // one to the output below a node on its path, in both groups. Only the
+    // server whose control bit is set there uses the word, so both shares
+    // of it change.
+    let (mut k0, mut k1) = ArithDCFKey::<(FE, FieldElm)>::gen_l_inf_ball(&MSB_u32_to_bits(nbits as u8, 10), 2);
+    for k in [&mut k0, &mut k1] {
+        let word = &mut k.0.val_words[1].1;
+        word.0.add(&FE::one());
+        word.1.add(&FieldElm::one());
+    }
+    cols[0].add_key(ClientKey::Arith(k0), None, vec![]);
+    cols[1].add_key(ClientKey::Arith(k1), None, vec![]);
+
+    let (disabled0, disabled1) = on_both(&mut cols, &mut channels, |col, gc_sender, channel| col.verify_keys(gc_sender, &[2], channel));
+    assert_eq!((disabled0, disabled1), (0, 0));
+
+    let mut found = crawl(&mut cols, &mut channels, nbits, 1);
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    assert!(cols.iter().all(|col| !col.keys[points.len()].0));
+
+    let mut expected = vec![];
+    for x in 0..32i32 {
+        let count = points.iter().filter(|&&p| (p - x).abs() <= 2).count();
+        if count > 0 {
+            expected.push((vec![MSB_u32_to_bits(nbits as u8, x as u32)], FieldElm::from(count as u32)));
+        }
+    }
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn verify_disables_bad_triples() {
+    let nbits = 4;
+    let n_triples = 512;
+    let layout = KeyLayout {
+        kind: KeyKind::Beaver,
+        dim_lens: vec![nbits, nbits],
+        pairs_per_dim: vec![],
+        max_weight: 1,
+        n_attributes: 0,
+    };
+    let mut cols = [
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout.clone()),
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout),
+    ];
+    cols.iter_mut().for_each(|col| col.set_beaver_triples(n_triples));
+    let (channel0, channel1) = channel_pair();
+    let mut channels = [channel0, channel1];
+
+    let points = [(3, 5), (3, 6), (9, 9)];
+    for &(x, y) in &points {
+        let point = vec![MSB_u32_to_bits(nbits as u8, x as u32), MSB_u32_to_bits(nbits as u8, y as u32)];
+        let (k0, k1) = BeaverKey::gen_l_inf_box(&point, &[1, 1], n_triples);
+        cols[0].add_key(ClientKey::Beaver(k0), None, vec![]);
+        cols[1].add_key(ClientKey::Beaver(k1), None, vec![]);
+    }
+
+    // Clients with a bad triple for the crawl, and with a bad spare one,
+    // in either group.
+    for bad in 0..2 {
+        let point = vec![MSB_u32_to_bits(nbits as u8, 12), MSB_u32_to_bits(nbits as u8, 12)];
+        let (k0, mut k1) = BeaverKey::<FE, FieldElm>::gen_l_inf_box(&point, &[1, 1], n_triples);
+        match bad {
+            0 => k1.triples.c[7].add(&FE::one()),
+            _ => k1.triples_last.c[n_triples + 7].add(&FieldElm::one()),
+        }
+        cols[0].add_key(ClientKey::Beaver(k0), None, vec![]);
+        cols[1].add_key(ClientKey::Beaver(k1), None, vec![]);
+    }
+
+    let (disabled0, disabled1) = on_both(&mut cols, &mut channels, |col, gc_sender, channel| col.verify_keys(gc_sender, &[1, 1], channel));
+    assert_eq!((disabled0, disabled1), (2, 2));
+
+    let mut found = crawl(&mut cols, &mut channels, nbits, 1);
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut expected = vec![];
+    for x in 0..16i32 {
+        for y in 0..16i32 {
+            let count = points.iter().filter(|&&(px, py)| (px - x).abs() <= 1 && (py - y).abs() <= 1).count();
+            if count > 0 {
+                let path = vec![MSB_u32_to_bits(nbits as u8, x as u32), MSB_u32_to_bits(nbits as u8, y as u32)];
+                expected.push((path, FieldElm::from(count as u32)));
+            }
+        }
+    }
+    assert_eq!(found, expected);
+}
+
+#[test]
+#[should_panic(expected = "raise beaver_triples")]
+fn crawl_aborts_short_of_triples() {
+    let nbits = 4;
+    // Enough for the four children of the root, but not for the level
+    // after.
+    let n_triples = 4;
+    let layout = KeyLayout {
+        kind: KeyKind::Beaver,
+        dim_lens: vec![nbits, nbits],
+        pairs_per_dim: vec![],
+        max_weight: 1,
+        n_attributes: 0,
+    };
+    let mut cols = [
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout.clone()),
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout),
+    ];
+    cols.iter_mut().for_each(|col| col.set_beaver_triples(n_triples));
+    let (channel0, channel1) = channel_pair();
+    let mut channels = [channel0, channel1];
+
+    for &(x, y) in &[(3, 5), (9, 9)] {
+        let point = vec![MSB_u32_to_bits(nbits as u8, x), MSB_u32_to_bits(nbits as u8, y)];
+        let (k0, k1) = BeaverKey::gen_l_inf_box(&point, &[1, 1], n_triples);
+        cols[0].add_key(ClientKey::Beaver(k0), None, vec![]);
+        cols[1].add_key(ClientKey::Beaver(k1), None, vec![]);
+    }
+    let (disabled0, disabled1) = on_both(&mut cols, &mut channels, |col, gc_sender, channel| col.verify_keys(gc_sender, &[1, 1], channel));
+    assert_eq!((disabled0, disabled1), (0, 0));
+
+    // Rather than crawl on with every client disabled, and find nothing.
+    crawl(&mut cols, &mut channels, nbits, 1);
+}
+
+#[test]
+fn crawl_disables_malformed_point_keys() {
+    let nbits = 5;
+    let layout = KeyLayout {
+        kind: KeyKind::Point,
+        dim_lens: vec![nbits],
+        pairs_per_dim: vec![],
+        max_weight: 1,
+        n_attributes: 0,
+    };
+    let mut cols = [
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout.clone()),
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout),
+    ];
+    let (channel0, channel1) = channel_pair();
+    let mut channels = [channel0, channel1];
+
+    let points = [3u32, 3, 20];
+    for &x in &points {
+        let (k0, k1) = ArithDCFKey::gen_point(&MSB_u32_to_bits(nbits as u8, x));
+        cols[0].add_key(ClientKey::Point(k0), None, vec![]);
+        cols[1].add_key(ClientKey::Point(k1), None, vec![]);
+    }
+
+    // A client whose key counts it twice below a node on its path.
+    let (mut k0, mut k1) = ArithDCFKey::<(FE, FieldElm)>::gen_point(&MSB_u32_to_bits(nbits as u8, 10));
+    for k in [&mut k0, &mut k1] {
+        let word = &mut k.val_words[2].0;
+        word.0.add(&FE::one());
+        word.1.add(&FieldElm::one());
+    }
+    cols[0].add_key(ClientKey::Point(k0), None, vec![]);
+    cols[1].add_key(ClientKey::Point(k1), None, vec![]);
+
+    // A client whose key leaves its path for both children of a node.
+    let (mut k0, mut k1) = ArithDCFKey::<(FE, FieldElm)>::gen_point(&MSB_u32_to_bits(nbits as u8, 12));
+    for k in [&mut k0, &mut k1] {
+        let cw = &k.key.cor_words[1];
+        let (b0, b1) = cw.bits();
+        k.key.cor_words[1] = CorWord::new(cw.seed.clone(), (!b0, b1), cw.y_bits());
+    }
+    cols[0].add_key(ClientKey::Point(k0), None, vec![]);
+    cols[1].add_key(ClientKey::Point(k1), None, vec![]);
+
+    let (disabled0, disabled1) = on_both(&mut cols, &mut channels, |col, gc_sender, channel| col.verify_keys(gc_sender, &[0], channel));
+    assert_eq!((disabled0, disabled1), (0, 0));
+
+    let mut found = crawl(&mut cols, &mut channels, nbits, 1);
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    assert!(cols.iter().all(|col| !col.keys[points.len()].0 && !col.keys[points.len() + 1].0));
+
+    let expected = vec![
+        (vec![MSB_u32_to_bits(nbits as u8, 3)], FieldElm::from(2)),
+        (vec![MSB_u32_to_bits(nbits as u8, 20)], FieldElm::from(1)),
+    ];
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn full_domain_refuses_large_domains() {
+    let (channel0, _channel1) = channel_pair();
+    let mut channel = channel0;
+    let box_keys = |nbits: usize, n_dims: usize, nclients: usize| {
+        let layout = KeyLayout {
+            kind: KeyKind::Box,
+            dim_lens: vec![nbits; n_dims],
+            pairs_per_dim: vec![1; n_dims],
+            max_weight: 1,
+            n_attributes: 0,
+        };
+        let mut col = KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout);
+        for _ in 0..nclients {
+            let alpha = vec![MSB_u32_to_bits(nbits as u8, 7); n_dims];
+            let (k0, _) = ibDCFKey::gen_l_inf_box(alpha, &vec![1; n_dims]);
+            col.add_key(ClientKey::Box(to_union(k0)), None, vec![]);
+        }
+        col
+    };
+
+    // 2^22 nodes above the leaves.
+    let mut col = box_keys(12, 2, 1);
+    assert!(col.tree_init_all(true, &mut channel).unwrap_err().contains("2^22 nodes"));
+
+    // 2^20 nodes, but 17 clients on each.
+    let mut col = box_keys(21, 1, 17);
+    assert!(col.tree_init_all(true, &mut channel).unwrap_err().contains("17 clients"));
+}
+
+#[test]
+fn crawl_in_the_ring() {
+    let nbits = 4;
+    let layout = KeyLayout {
+        kind: KeyKind::Box,
+        dim_lens: vec![nbits, nbits],
+        pairs_per_dim: vec![1, 1],
+        max_weight: 3,
+        n_attributes: 0,
+    };
+    let mut cols = [
+        KeyCollection::<RingElm, RingElm>::new(&prg::PrgSeed::random(), nbits, layout.clone()),
+        KeyCollection::<RingElm, RingElm>::new(&prg::PrgSeed::random(), nbits, layout),
+    ];
+    let (channel0, channel1) = channel_pair();
+    let mut channels = [channel0, channel1];
+
+    let clients = [((3, 5), 1), ((3, 6), 2), ((9, 9), 1), ((15, 0), 3)];
+    for &((x, y), weight) in &clients {
+        let point = vec![MSB_u32_to_bits(nbits as u8, x as u32), MSB_u32_to_bits(nbits as u8, y as u32)];
+        let (k0, k1) = ibDCFKey::gen_l_inf_ball(point, 1);
+        let (w0, w1) = share_weight::<RingElm>(weight, 3);
+        let (u0, u1) = share_weight::<RingElm>(weight, 3);
+        let (bits0, bits1) = share_weight_bits(weight, 3);
+        cols[0].add_key(ClientKey::Box(to_union(k0)), Some(WeightShare { value: (w0, u0), bits: bits0 }), vec![]);
+        cols[1].add_key(ClientKey::Box(to_union(k1)), Some(WeightShare { value: (w1, u1), bits: bits1 }), vec![]);
+    }
+
+    let (disabled0, disabled1) = on_both(&mut cols, &mut channels, |col, gc_sender, channel| col.verify_keys(gc_sender, &[1, 1], channel));
+    assert_eq!((disabled0, disabled1), (0, 0));
+
+    let mut found = crawl(&mut cols, &mut channels, nbits, 2);
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // Every point whose clients within distance one on both dimensions
+    // weigh at least two.
+    let mut expected = vec![];
+    for x in 0..16i32 {
+        for y in 0..16i32 {
+            let weight = clients
+                .iter()
+                .filter(|&&((px, py), _)| (px - x).abs() <= 1 && (py - y).abs() <= 1)
+                .map(|&(_, w)| w)
+                .sum::<u32>();
+            if weight >= 2 {
+                let path = vec![MSB_u32_to_bits(nbits as u8, x as u32), MSB_u32_to_bits(nbits as u8, y as u32)];
+                expected.push((path, RingElm::from(weight)));
+            }
+        }
+    }
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn verify_disables_out_of_range_weights() {
+    let nbits = 4;
+    let max_weight = 5;
+    let layout = KeyLayout {
+        kind: KeyKind::Box,
+        dim_lens: vec![nbits],
+        pairs_per_dim: vec![1],
+        max_weight,
+        n_attributes: 0,
+    };
+    let mut cols = [
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout.clone()),
+        KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed::random(), nbits, layout),
+    ];
+    let (channel0, channel1) = channel_pair();
+    let mut channels = [channel0, channel1];
+
+    // Each client's weight shares, and the weight that its bits are of.
+    let minus_one = |mut x: FieldElm| {
+        x.sub(&FieldElm::one());
+        x
+    };
+    let clients = vec![
+        ((FE::from(0u32), FieldElm::from(0u32)), 0),
+        ((FE::from(3u32), FieldElm::from(3u32)), 3),
+        ((FE::from(5u32), FieldElm::from(5u32)), 5),
+        // Above the cap, with the bits of the cap.
+        ((FE::from(6u32), FieldElm::from(6u32)), 5),
+        // Too heavy at the last level only.
+        ((FE::from(2u32), FieldElm::from(1000u32)), 2),
+        // Negative, with the bits of zero.
+        ((FE::from(0u32), minus_one(FieldElm::zero())), 0),
+    ];
+    for (i, ((t, u), bits_of)) in clients.into_iter().enumerate() {
+        let point = vec![MSB_u32_to_bits(nbits as u8, i as u32)];
+        let (k0, k1) = ibDCFKey::gen_l_inf_ball(point, 1);
+        let ((t0, t1), (u0, u1)) = (t.share(), u.share());
+        let (bits0, bits1) = share_weight_bits(bits_of, max_weight);
+        cols[0].add_key(ClientKey::Box(to_union(k0)), Some(WeightShare { value: (t0, u0), bits: bits0 }), vec![]);
+        cols[1].add_key(ClientKey::Box(to_union(k1)), Some(WeightShare { value: (t1, u1), bits: bits1 }), vec![]);
+    }
+
+    let (disabled0, disabled1) = on_both(&mut cols, &mut channels, |col, gc_sender, channel| col.verify_keys(gc_sender, &[1], channel));
+    assert_eq!((disabled0, disabled1), (3, 3));
+    let live = cols[0].keys.iter().map(|(live, _)| *live).collect::<Vec<bool>>();
+    assert_eq!(live, vec![true, true, true, false, false, false]);
+}
+
+#[test]
+fn seeded_crawls_send_the_same_messages() {
+    let nbits = 3;
+    let layout = KeyLayout {
+        kind: KeyKind::Box,
+        dim_lens: vec![nbits, nbits],
+        pairs_per_dim: vec![1, 1],
+        max_weight: 1,
+        n_attributes: 0,
+    };
+    let keys = [(1, 2), (2, 2), (6, 5)]
+        .iter()
+        .map(|&(x, y)| {
+            let point = vec![MSB_u32_to_bits(nbits as u8, x), MSB_u32_to_bits(nbits as u8, y)];
+            let (k0, k1) = ibDCFKey::gen_l_inf_ball(point, 1);
+            (to_union(k0), to_union(k1))
+        })
+        .collect::<Vec<_>>();
+
+    // The results of a checked crawl with the servers' seeds, and what
+    // each server sent.
+    let run = |seeds: [u8; 2]| {
+        let mut cols = [
+            KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed { key: [seeds[0]; 16] }, nbits, layout.clone()),
+            KeyCollection::<FE, FieldElm>::new(&prg::PrgSeed { key: [seeds[1]; 16] }, nbits, layout.clone()),
+        ];
+        for (k0, k1) in &keys {
+            cols[0].add_key(ClientKey::Box(k0.clone()), None, vec![]);
+            cols[1].add_key(ClientKey::Box(k1.clone()), None, vec![]);
+        }
+        let (channel0, channel1, relays) = recorded_channel_pair();
+        let mut channels = [channel0, channel1];
+        let disabled = on_both(&mut cols, &mut channels, |col, gc_sender, channel| col.verify_keys(gc_sender, &[1, 1], channel));
+        assert_eq!(disabled, (0, 0));
+        let found = crawl(&mut cols, &mut channels, nbits, 2);
+        drop(channels);
+        let [sent0, sent1] = relays;
+        (found, sent0.join().unwrap(), sent1.join().unwrap())
+    };
+
+    let first = run([1, 2]);
+    assert!(!first.0.is_empty());
+    assert_eq!(run([1, 2]), first);
+    // Another seed for one server changes what both send.
+    let other = run([1, 3]);
+    assert_eq!(other.0, first.0);
+    assert_ne!(other.1, first.1);
+    assert_ne!(other.2, first.2);
+}
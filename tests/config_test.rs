@@ -0,0 +1,46 @@
+#![cfg(feature = "server")]
+
+use counttree::config::get_config;
+use std::fs;
+
+// Writes `json` to a config file of its own and parses it.
+fn parse(name: &str, json: &str) {
+    let path = std::env::temp_dir().join(format!("counttree-{}-{}.json", name, std::process::id()));
+    fs::write(&path, json).unwrap();
+    let result = std::panic::catch_unwind(|| get_config(path.to_str().unwrap()));
+    fs::remove_file(&path).unwrap();
+    if let Err(e) = result {
+        std::panic::resume_unwind(e);
+    }
+}
+
+fn full_domain_config(data_len: usize) -> String {
+    format!(
+        r#"{{
+            "data_len": {},
+            "n_dims": 2,
+            "ball_size": 1,
+            "full_domain": true,
+            "threshold": 0.075,
+            "server0": "0.0.0.0:8000",
+            "server1": "0.0.0.0:8001",
+            "addkey_batch_size": 100,
+            "num_sites": 10000,
+            "zipf_exponent": 1.03,
+            "distribution": "zipf"
+        }}"#,
+        data_len
+    )
+}
+
+#[test]
+fn full_domain_accepts_small_domains() {
+    // Two 11-bit dimensions leave 2^20 nodes above the leaves.
+    parse("small", &full_domain_config(11));
+}
+
+#[test]
+#[should_panic(expected = "full_domain evaluates at most 20 bits, not 22")]
+fn full_domain_rejects_large_domains() {
+    parse("large", &full_domain_config(12));
+}
@@ -1,4 +1,4 @@
-use counttree::ibDCF::{eval_str, ibDCFKey};
+use counttree::ibDCF::{eval_str, ibDCFKey, to_union, IntervalKey};
 use counttree::{add_bitstrings, bits_to_u32, u32_to_bits, MSB_u32_to_bits};
 
 #[test]
@@ -353,4 +353,519 @@ fn interval_test() {
             );
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn l_inf_ball_from_negative_coords() {
+    use counttree::encoding::{Encoder, OffsetBinary};
+
+    // Austin's longitude in centidegrees, and a ball that straddles zero.
+    let centers = [(3026i16, -9774i16), (-2, 1)];
+    let size = 3i16;
+
+    for &(lat, long) in &centers {
+        let (keys0, keys1) = ibDCFKey::gen_l_inf_ball_from_coords((lat, long), (size, size));
+        for (dim, &center) in [lat, long].iter().enumerate() {
+            let ((l0, r0), (l1, r1)) = (&keys0[dim], &keys1[dim]);
+            for x in (center - 2 * size)..=(center + 2 * size) {
+                let bits = OffsetBinary.encode(x);
+                let outside_left = l0.eval_ibDCF(&bits) ^ l1.eval_ibDCF(&bits);
+                let outside_right = r0.eval_ibDCF(&bits) ^ r1.eval_ibDCF(&bits);
+                let inside = !outside_left && !outside_right;
+                assert_eq!(
+                    inside,
+                    (x - center).abs() <= size,
+                    "dim {} center {} point {}",
+                    dim,
+                    center,
+                    x
+                );
+            }
+        }
+    }
+}
+
+
+// Whether `point` (one prefix per dimension) lies in the set described by
+// the two servers' union keys: on every dimension, some pair must contain it.
+fn union_contains(keys0: &[Vec<IntervalKey>], keys1: &[Vec<IntervalKey>], point: &[Vec<bool>]) -> bool {
+    keys0.iter().zip(keys1).zip(point).all(|((pairs0, pairs1), x)| {
+        pairs0.iter().zip(pairs1).any(|((l0, r0), (l1, r1))| {
+            let outside_left = l0.eval_ibDCF(x) ^ l1.eval_ibDCF(x);
+            let outside_right = r0.eval_ibDCF(x) ^ r1.eval_ibDCF(x);
+            !outside_left && !outside_right
+        })
+    })
+}
+
+// Like `union_contains`, but for keys from `gen_l2_ball`: some box, i.e.,
+// the same pair on every dimension, must contain `point`.
+fn box_union_contains(keys0: &[Vec<IntervalKey>], keys1: &[Vec<IntervalKey>], point: &[Vec<bool>]) -> bool {
+    (0..keys0[0].len()).any(|j| {
+        keys0.iter().zip(keys1).zip(point).all(|((pairs0, pairs1), x)| {
+            let ((l0, r0), (l1, r1)) = (&pairs0[j], &pairs1[j]);
+            !(l0.eval_ibDCF(x) ^ l1.eval_ibDCF(x)) && !(r0.eval_ibDCF(x) ^ r1.eval_ibDCF(x))
+        })
+    })
+}
+
+#[test]
+fn l_inf_ball_domain_edges() {
+    let nbits = 6;
+    let size = 3;
+    let max = (1u32 << nbits) - 1;
+
+    for &center in &[0u32, 1, 30, 62, 63] {
+        let alpha = vec![MSB_u32_to_bits(nbits, center)];
+
+        // Bounded: the ball is clamped to [0, max].
+        let (k0, k1) = ibDCFKey::gen_l_inf_ball(alpha.clone(), size);
+        let (k0, k1) = (to_union(k0), to_union(k1));
+        for x in 0..=max {
+            let expected = (x as i64 - center as i64).abs() <= size as i64;
+            assert_eq!(union_contains(&k0, &k1, &[MSB_u32_to_bits(nbits, x)]), expected);
+        }
+
+        // Toroidal: the ball wraps around, at every prefix length.
+        let (k0, k1) = ibDCFKey::gen_l_inf_ball_toroidal(alpha, &[size], &[true]);
+        assert_eq!(k0.len(), 1);
+        assert_eq!(k0[0].len(), 2);
+        for len in 1..=nbits {
+            let shift = nbits - len;
+            for p in 0..(1u32 << len) {
+                let prefix = vec![MSB_u32_to_bits(len, p)];
+                let expected = (0..=max).any(|x| {
+                    let d = (x as i64 - center as i64).rem_euclid(max as i64 + 1);
+                    (x >> shift) == p && (d <= size as i64 || d >= (max + 1 - size) as i64)
+                });
+                assert_eq!(union_contains(&k0, &k1, &prefix), expected, "center {} prefix {:?}", center, prefix);
+            }
+        }
+    }
+}
+
+#[test]
+fn l_inf_ball_antimeridian() {
+    use counttree::encoding::{Encoder, OffsetBinary};
+
+    let size = 5i16;
+    for &long in &[17998i16, -17999, 0] {
+        let (k0, k1) = ibDCFKey::gen_l_inf_ball_from_coords_wrapping((100, long), (size, size));
+        assert_eq!(k0.iter().map(|d| d.len()).collect::<Vec<_>>(), vec![1, 2]);
+        for x in (long - 2 * size)..=(long + 2 * size) {
+            let wrapped = ((x as i32 + 18000).rem_euclid(36000) - 18000) as i16;
+            let point = vec![OffsetBinary.encode(100), OffsetBinary.encode(wrapped)];
+            assert_eq!(
+                union_contains(&k0, &k1, &point),
+                (x - long).abs() <= size,
+                "long {} point {}",
+                long,
+                wrapped
+            );
+        }
+    }
+}
+
+#[test]
+fn union_of_intervals() {
+    // Hours of the week (0..168) on an 8-bit axis: weekday mornings
+    // (7-9h) or evenings (17-19h), Monday through Friday. The second
+    // dimension is a plain interval.
+    let nbits = 8;
+    let wanted = |h: u32| h < 120 && ((7..=9).contains(&(h % 24)) || (17..=19).contains(&(h % 24)));
+
+    let mut hours = vec![];
+    for day in 0..5 {
+        for &(a, b) in &[(7, 9), (17, 19)] {
+            hours.push((MSB_u32_to_bits(nbits, day * 24 + a), MSB_u32_to_bits(nbits, day * 24 + b)));
+        }
+    }
+    // Pass the ranges out of order; gen_union sorts them.
+    hours.reverse();
+    let other = vec![(MSB_u32_to_bits(4, 3), MSB_u32_to_bits(4, 12))];
+
+    let (k0, k1) = ibDCFKey::gen_union(&[hours, other], &[12, 1]);
+    assert_eq!(k0[0].len(), 12);
+
+    for len in 1..=nbits {
+        let shift = nbits - len;
+        for p in 0..(1u32 << len) {
+            let expected = (0..(1u32 << nbits)).any(|h| (h >> shift) == p && wanted(h));
+            for y in 0..16u32 {
+                let point = vec![MSB_u32_to_bits(len, p), MSB_u32_to_bits(4, y)];
+                let inside = expected && (3..=12).contains(&y);
+                assert_eq!(union_contains(&k0, &k1, &point), inside, "prefix {} of len {} y {}", p, len, y);
+            }
+        }
+    }
+}
+
+#[test]
+fn per_dimension_lengths_and_radii() {
+    // An 8-bit axis with radius 2 and a 5-bit axis with radius 6, crawled
+    // together: the 5-bit axis stops advancing after level 5.
+    let lens = [8u8, 5];
+    let center = [100u32, 17];
+    let sizes = [2u32, 6];
+    let alpha = center
+        .iter()
+        .zip(&lens)
+        .map(|(&c, &len)| MSB_u32_to_bits(len, c))
+        .collect::<Vec<_>>();
+    let (k0, k1) = ibDCFKey::gen_l_inf_box(alpha, &sizes);
+    let (k0, k1) = (to_union(k0), to_union(k1));
+    let init = |keys: &Vec<Vec<IntervalKey>>| {
+        keys.iter()
+            .map(|d| d.iter().map(|(l, r)| (l.eval_init(), r.eval_init())).collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+
+    for x in 90..110u32 {
+        for y in 0..32u32 {
+            let point = [MSB_u32_to_bits(8, x), MSB_u32_to_bits(5, y)];
+            let (mut s0, mut s1) = (init(&k0), init(&k1));
+            for level in 0..8 {
+                let bits = point
+                    .iter()
+                    .filter(|p| level < p.len())
+                    .map(|p| p[level])
+                    .collect::<Vec<_>>();
+                s0 = eval_str(&k0, &s0, &bits);
+                s1 = eval_str(&k1, &s1, &bits);
+
+                let prefix = point
+                    .iter()
+                    .map(|p| p[..std::cmp::min(level + 1, p.len())].to_vec())
+                    .collect::<Vec<_>>();
+                let inside = s0.iter().zip(&s1).all(|(d0, d1)| {
+                    let ((l0, r0), (l1, r1)) = (&d0[0], &d1[0]);
+                    !(l0.y_bit ^ l0.bit ^ l1.y_bit ^ l1.bit) && !(r0.y_bit ^ r0.bit ^ r1.y_bit ^ r1.bit)
+                });
+                assert_eq!(inside, union_contains(&k0, &k1, &prefix), "x {} y {} level {}", x, y, level);
+            }
+
+            let expected = (x as i64 - 100).abs() <= 2 && (y as i64 - 17).abs() <= 6;
+            assert_eq!(union_contains(&k0, &k1, &point), expected, "x {} y {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn key_wire_format() {
+    use counttree::ibDCF::KEY_FORMAT_VERSION;
+
+    for &nbits in &[1u8, 7, 32] {
+        let alpha = MSB_u32_to_bits(nbits, 0x5a5a5a5a & ((1u64 << nbits) - 1) as u32);
+        let (k0, k1) = ibDCFKey::gen_ibDCF(&alpha, false);
+
+        for key in &[k0.clone(), k1.clone()] {
+            let bytes = key.to_bytes();
+            let n = nbits as usize;
+            assert_eq!(bytes.len(), 2 + 4 + 16 + 16 * n + (n + 1) / 2 + (n + 7) / 8);
+            assert_eq!(bytes[0], KEY_FORMAT_VERSION);
+
+            // Serde (and so the RPC layer) uses the same encoding.
+            let via_bincode: ibDCFKey = bincode::deserialize(&bincode::serialize(key).unwrap()).unwrap();
+            let decoded = ibDCFKey::from_bytes(&bytes).unwrap();
+            for k in &[via_bincode, decoded] {
+                assert_eq!(k.to_bytes(), bytes);
+                assert_eq!(k.key_idx, key.key_idx);
+                for x in 0..(1u32 << std::cmp::min(nbits, 8)) {
+                    let x = MSB_u32_to_bits(std::cmp::min(nbits, 8), x);
+                    assert_eq!(k.eval_ibDCF(&x), key.eval_ibDCF(&x));
+                }
+            }
+
+            // Checked decoding.
+            assert!(ibDCFKey::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+            let mut longer = bytes.clone();
+            longer.push(0);
+            assert!(ibDCFKey::from_bytes(&longer).is_err());
+            let mut bad_version = bytes.clone();
+            bad_version[0] = KEY_FORMAT_VERSION + 1;
+            assert!(ibDCFKey::from_bytes(&bad_version).is_err());
+            let mut bad_flags = bytes.clone();
+            bad_flags[1] = 2;
+            assert!(ibDCFKey::from_bytes(&bad_flags).is_err());
+            if n % 2 == 1 {
+                let mut bad_padding = bytes.clone();
+                bad_padding[bytes.len() - (n + 7) / 8 - 1] |= 0x10;
+                assert!(ibDCFKey::from_bytes(&bad_padding).is_err());
+            }
+            if n % 8 != 0 {
+                let mut bad_padding = bytes.clone();
+                *bad_padding.last_mut().unwrap() |= 0x80;
+                assert!(ibDCFKey::from_bytes(&bad_padding).is_err());
+            }
+        }
+    }
+}
+
+// Whether the two shares of a boundary key generated with `side` pass the
+// check of every level on every node, as the servers would run it in a
+// crawl of the whole domain.
+fn passes_every_level(k0: &ibDCFKey, k1: &ibDCFKey, side: bool) -> bool {
+    use counttree::ibDCF::LevelCheck;
+    use counttree::prg::PrgSeed;
+
+    let seed = PrgSeed::random();
+    (0..k0.domain_size()).all(|level| {
+        let mut checks = vec![];
+        for key in [k0, k1] {
+            let mut rng = seed.to_rng_at(level as u64);
+            let mut check = LevelCheck::new(key, level);
+            let (parents, children) = (key.eval_all(level), key.eval_all(level + 1));
+            for (j, parent) in parents.iter().enumerate() {
+                key.check_step(side, parent, (&children[2 * j], &children[2 * j + 1]), &mut rng, &mut check);
+            }
+            checks.push(check);
+        }
+        checks[0].passes(&checks[1])
+    })
+}
+
+#[test]
+fn key_validity_check() {
+    use counttree::ibDCF::CorWord;
+
+    let nbits = 8;
+    let ranges = vec![
+        vec![(MSB_u32_to_bits(nbits, 3), MSB_u32_to_bits(nbits, 200))],
+        vec![
+            (MSB_u32_to_bits(nbits, 0), MSB_u32_to_bits(nbits, 0)),
+            (MSB_u32_to_bits(nbits, 255), MSB_u32_to_bits(nbits, 255)),
+        ],
+    ];
+    let (k0, k1) = ibDCFKey::gen_union(&ranges, &[1, 2]);
+    let passes = |k0: &Vec<Vec<IntervalKey>>, k1: &Vec<Vec<IntervalKey>>| {
+        k0.iter().flatten().zip(k1.iter().flatten()).all(|((l0, r0), (l1, r1))| {
+            passes_every_level(l0, l1, true) && passes_every_level(r0, r1, false)
+        })
+    };
+    assert!(passes(&k0, &k1));
+
+    // Padding pairs hold the empty range.
+    let (e0, e1) = ibDCFKey::gen_union(&[vec![(MSB_u32_to_bits(nbits, 9), MSB_u32_to_bits(nbits, 9))]], &[3]);
+    assert!(passes(&e0, &e1));
+
+    // Swapping the boundaries gives the complement of the interval.
+    let swap = |k: &Vec<Vec<IntervalKey>>| {
+        let mut k = k.clone();
+        let (l, r) = k[0][0].clone();
+        k[0][0] = (r, l);
+        k
+    };
+    assert!(!passes(&swap(&k0), &swap(&k1)));
+
+    // A malicious client changes both shares in the same way.
+    let tamper = |f: &dyn Fn(&mut ibDCFKey)| {
+        let (mut t0, mut t1) = (k0.clone(), k1.clone());
+        f(&mut t0[0][0].1);
+        f(&mut t1[0][0].1);
+        passes(&t0, &t1)
+    };
+    for level in [0, 4, 7] {
+        // Both children stay on the path.
+        assert!(!tamper(&|k: &mut ibDCFKey| {
+            let cw = &k.cor_words[level];
+            let (b0, b1) = cw.bits();
+            k.cor_words[level] = CorWord::new(cw.seed.clone(), (!b0, !b1), cw.y_bits());
+        }));
+        // The output flips below one child of the path.
+        assert!(!tamper(&|k: &mut ibDCFKey| {
+            let cw = &k.cor_words[level];
+            let (y0, y1) = cw.y_bits();
+            k.cor_words[level] = CorWord::new(cw.seed.clone(), cw.bits(), (!y0, y1));
+        }));
+    }
+    // The seeds of the node that leaves the path stay apart, and the
+    // outputs below it go astray. The leaves' seeds are never used.
+    for level in [0, 4, 6] {
+        assert!(!tamper(&|k: &mut ibDCFKey| {
+            let mut seed = k.cor_words[level].seed.clone();
+            seed.key[5] ^= 1;
+            k.cor_words[level] = CorWord::new(seed, k.cor_words[level].bits(), k.cor_words[level].y_bits());
+        }));
+    }
+
+    // The client's shares of `alpha` are not those of the point that the
+    // key compares with.
+    for level in [0, 4, 7] {
+        let mut t0 = k0.clone();
+        t0[0][0].1.alpha_share[level] ^= true;
+        assert!(!passes(&t0, &k1));
+    }
+}
+
+#[test]
+fn endpoint_shares() {
+    let nbits = 10;
+    let ranges = vec![
+        vec![(MSB_u32_to_bits(nbits, 17), MSB_u32_to_bits(nbits, 544))],
+        vec![(MSB_u32_to_bits(nbits, 0), MSB_u32_to_bits(nbits, 2)), (MSB_u32_to_bits(nbits, 1020), MSB_u32_to_bits(nbits, 1023))],
+    ];
+    let (k0, k1) = ibDCFKey::gen_union(&ranges, &[1, 3]);
+
+    let xor = |a: &Vec<bool>, b: &Vec<bool>| a.iter().zip(b).map(|(x, y)| x ^ y).collect::<Vec<_>>();
+    for (d, dim_ranges) in ranges.iter().enumerate() {
+        for (i, ((l0, r0), (l1, r1))) in k0[d].iter().zip(&k1[d]).enumerate() {
+            // The third pair on the second dimension is padding.
+            let (l, r) = dim_ranges
+                .get(i)
+                .cloned()
+                .unwrap_or((vec![true; nbits as usize], vec![false; nbits as usize]));
+            assert_eq!(xor(&l0.alpha_share, &l1.alpha_share), l);
+            assert_eq!(xor(&r0.alpha_share, &r1.alpha_share), r);
+        }
+    }
+}
+
+#[test]
+fn l2_ball_approximation() {
+    use counttree::ibDCF::l2_boxes;
+
+    let nbits = 7;
+    let (cx, cy) = (60i64, 70i64);
+    let radius = 24u32;
+    let alpha = vec![MSB_u32_to_bits(nbits, cx as u32), MSB_u32_to_bits(nbits, cy as u32)];
+
+    let mut last_error = f64::INFINITY;
+    for &n_boxes in &[1usize, 2, 4, 8] {
+        let (k0, k1) = ibDCFKey::gen_l2_ball(alpha.clone(), radius, n_boxes);
+        assert_eq!(k0.iter().map(|d| d.len()).collect::<Vec<_>>(), vec![n_boxes; 2]);
+        let boxes = l2_boxes(radius, n_boxes);
+
+        // Every point counts once, if any box holds it. No box reaches
+        // further than the radius.
+        let (mut disc, mut wrong) = (0, 0);
+        let r = radius as i64;
+        for x in (cx - r - 2)..=(cx + r + 2) {
+            for y in (cy - r - 2)..=(cy + r + 2) {
+                let point = vec![MSB_u32_to_bits(nbits, x as u32), MSB_u32_to_bits(nbits, y as u32)];
+                let inside = box_union_contains(&k0, &k1, &point);
+                let in_boxes = boxes
+                    .iter()
+                    .any(|&(dx, dy)| (x - cx).abs() <= dx as i64 && (y - cy).abs() <= dy as i64);
+                assert_eq!(inside, in_boxes, "boxes {} point ({}, {})", n_boxes, x, y);
+
+                let in_disc = (x - cx).pow(2) + (y - cy).pow(2) <= (radius as i64).pow(2);
+                disc += in_disc as usize;
+                wrong += (inside != in_disc) as usize;
+            }
+        }
+        let error = wrong as f64 / disc as f64;
+        println!("{} boxes: {} of {} points wrong ({:.1}%)", n_boxes, wrong, disc, 100.0 * error);
+        assert!(error < last_error);
+        last_error = error;
+
+        // A node counts the client if some box meets it.
+        for &len in &[1, 3, 5] {
+            let shift = nbits - len;
+            for p in 0..(1i64 << len) {
+                for q in 0..(1i64 << len) {
+                    let prefix = vec![MSB_u32_to_bits(len, p as u32), MSB_u32_to_bits(len, q as u32)];
+                    let expected = boxes.iter().any(|&(dx, dy)| {
+                        let meets = |lo: i64, c: i64, d: u32| (lo..lo + (1 << shift)).any(|v| (v - c).abs() <= d as i64);
+                        meets(p << shift, cx, dx) && meets(q << shift, cy, dy)
+                    });
+                    assert_eq!(box_union_contains(&k0, &k1, &prefix), expected);
+                }
+            }
+        }
+    }
+    assert!(last_error < 0.1);
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn arith_dcf_outputs() {
+    use counttree::fastfield::FE;
+    use counttree::ibDCF::ArithDCFKey;
+    use counttree::Group;
+
+    let nbits = 5;
+    let share = |v0: FE, v1: FE| {
+        let mut v = v0;
+        v.sub(&v1);
+        v
+    };
+
+    for &alpha in &[0u32, 1, 13, 30, 31] {
+        let alpha_bits = MSB_u32_to_bits(nbits, alpha);
+        for &side in &[false, true] {
+            let (k0, k1) = ArithDCFKey::<FE>::gen(&alpha_bits, side);
+            for len in 1..=nbits {
+                let shift = nbits - len;
+                for p in 0..(1u32 << len) {
+                    let (lo, hi) = (p << shift, ((p + 1) << shift) - 1);
+                    let expected = if side { hi < alpha } else { lo > alpha };
+                    let prefix = MSB_u32_to_bits(len, p);
+                    assert_eq!(
+                        share(k0.eval(&prefix), k1.eval(&prefix)),
+                        FE::from(expected as u32),
+                        "alpha {} side {} prefix {} of len {}",
+                        alpha, side, p, len
+                    );
+                }
+            }
+        }
+    }
+
+    // One minus both outputs of an interval key is whether a prefix meets
+    // the interval.
+    let (l, r) = (9u32, 22u32);
+    let (k0, k1) = ArithDCFKey::<FE>::gen_interval(&MSB_u32_to_bits(nbits, l), &MSB_u32_to_bits(nbits, r));
+    for len in 1..=nbits {
+        let shift = nbits - len;
+        for p in 0..(1u32 << len) {
+            let prefix = MSB_u32_to_bits(len, p);
+            let mut inside = FE::one();
+            inside.sub(&share(k0.0.eval(&prefix), k1.0.eval(&prefix)));
+            inside.sub(&share(k0.1.eval(&prefix), k1.1.eval(&prefix)));
+            let meets = (p << shift) <= r && ((p + 1) << shift) - 1 >= l;
+            assert_eq!(inside, FE::from(meets as u32), "prefix {} of len {}", p, len);
+        }
+    }
+
+    // A point key is one exactly on the prefixes of its point.
+    for &alpha in &[0u32, 13, 31] {
+        let (k0, k1) = ArithDCFKey::<FE>::gen_point(&MSB_u32_to_bits(nbits, alpha));
+        for len in 1..=nbits {
+            for p in 0..(1u32 << len) {
+                let prefix = MSB_u32_to_bits(len, p);
+                let expected = p == alpha >> (nbits - len);
+                assert_eq!(share(k0.eval(&prefix), k1.eval(&prefix)), FE::from(expected as u32));
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "server")]
+fn full_domain_evaluation() {
+    use counttree::fastfield::FE;
+    use counttree::ibDCF::ArithDCFKey;
+
+    let nbits = 6;
+    let alpha = MSB_u32_to_bits(nbits, 37);
+    let (k0, _) = ibDCFKey::gen_ibDCF(&alpha, true);
+    let (a0, _) = ArithDCFKey::<FE>::gen(&alpha, false);
+    for len in 0..=nbits {
+        let all = k0.eval_all(len as usize);
+        let arith_all = a0.eval_all(len as usize);
+        assert_eq!(all.len(), 1 << len);
+        assert_eq!(arith_all.len(), 1 << len);
+        for p in 0..(1u32 << len) {
+            let mut state = k0.eval_init();
+            let mut arith_state = a0.eval_init();
+            for &bit in &MSB_u32_to_bits(len, p) {
+                state = k0.eval_bit(&state, bit);
+                arith_state = a0.eval_bit(&arith_state, bit);
+            }
+            let i = p as usize;
+            assert_eq!((all[i].bit, all[i].y_bit), (state.bit, state.y_bit));
+            assert_eq!(arith_all[i].1, arith_state.1);
+            assert_eq!((arith_all[i].0.bit, arith_all[i].0.y_bit), (arith_state.0.bit, arith_state.0.y_bit));
+        }
+    }
+}